@@ -0,0 +1,350 @@
+//! Offline lookups in a local copy of Pwned Passwords.
+//!
+//! `HashFile` reads either of two formats, which are detected when the file is
+//! opened:
+//!
+//! * the downloadable SHA-1 "ordered by hash" text file, with a line of the form
+//!   `5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:10434004` for each password, or
+//! * a compact binary index built from it by `HashFile::build_index`, which is
+//!   the 8 bytes `PWNDIDX1` followed by a record for each line of the text file:
+//!   the 20 bytes of the hash and the count as a 4 byte big-endian integer.
+//!
+//! Both are searched with a binary search of the file on disk, so a lookup reads
+//! a few kilobytes rather than the whole file.
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::{parse_hex, BreachCorpus, Sha1Hash};
+use crate::error::{Error, Result};
+
+const INDEX_MAGIC: &[u8; 8] = b"PWNDIDX1";
+const RECORD_LENGTH: u64 = 24;
+/// The longest line of a text file: 40 digits, a colon, a count and a line ending.
+const MAX_LINE_LENGTH: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Text,
+    Index,
+}
+
+/// A line of a text file, which is followed by the line that starts at the
+/// byte offset `next`.
+struct Line {
+    next: u64,
+    hash: Sha1Hash,
+    count: u64,
+}
+
+/// A Pwned Passwords SHA-1 text file or binary index on disk.
+#[derive(Debug)]
+pub struct HashFile {
+    path: PathBuf,
+    file: Mutex<File>,
+    format: Format,
+    length: u64,
+}
+
+impl HashFile {
+    /// Open the text file or binary index at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<HashFile> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = File::open(&path).map_err(|e| io_error(&path, e))?;
+        let length = file.metadata().map_err(|e| io_error(&path, e))?.len();
+        let mut magic = [0u8; 8];
+        let is_index = length >= magic.len() as u64
+            && file.read_exact(&mut magic).is_ok()
+            && magic == *INDEX_MAGIC;
+        let format = if is_index {
+            if !(length - INDEX_MAGIC.len() as u64).is_multiple_of(RECORD_LENGTH) {
+                return Err(Error::Breach(format!(
+                    "'{}' is a truncated index",
+                    path.display()
+                )));
+            }
+            Format::Index
+        } else {
+            Format::Text
+        };
+        Ok(HashFile {
+            path,
+            file: Mutex::new(file),
+            format,
+            length,
+        })
+    }
+
+    /// Build the binary index of the text file at `input` in a new file at
+    /// `output`, returning the number of hashes. The lines of `input` must be
+    /// ordered by hash, as they are in the downloadable file.
+    pub fn build_index<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> Result<u64> {
+        let (input, output) = (input.as_ref(), output.as_ref());
+        let reader = BufReader::new(File::open(input).map_err(|e| io_error(input, e))?);
+        let mut writer = BufWriter::new(File::create(output).map_err(|e| io_error(output, e))?);
+        let write_error = |e| io_error(output, e);
+
+        writer.write_all(INDEX_MAGIC).map_err(write_error)?;
+        let mut previous: Option<Sha1Hash> = None;
+        let mut records = 0;
+        for (number, line) in reader.split(b'\n').enumerate() {
+            let line = line.map_err(|e| io_error(input, e))?;
+            if trim_line(&line).is_empty() {
+                continue;
+            }
+            let malformed = || {
+                Error::Breach(format!(
+                    "line {} of '{}' is not a hash and count",
+                    number + 1,
+                    input.display()
+                ))
+            };
+            let (hash, count) = parse_line(&line).ok_or_else(malformed)?;
+            if previous.is_some_and(|previous| previous >= hash) {
+                return Err(Error::Breach(format!(
+                    "line {} of '{}' is out of order; the file must be ordered by hash",
+                    number + 1,
+                    input.display()
+                )));
+            }
+            previous = Some(hash);
+            let count = u32::try_from(count).unwrap_or(u32::MAX);
+            writer.write_all(&hash).map_err(write_error)?;
+            writer
+                .write_all(&count.to_be_bytes())
+                .map_err(write_error)?;
+            records += 1;
+        }
+        writer.flush().map_err(write_error)?;
+        Ok(records)
+    }
+
+    /// The path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read from `offset` until `buffer` is full or the end of the file,
+    /// returning the number of bytes read.
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.seek(SeekFrom::Start(offset))
+            .map_err(|e| io_error(&self.path, e))?;
+        let mut read = 0;
+        while read < buffer.len() {
+            match file.read(&mut buffer[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(io_error(&self.path, e)),
+            }
+        }
+        Ok(read)
+    }
+
+    /// The first line of a text file that starts at or after `offset`, if any.
+    fn line_at(&self, offset: u64) -> Result<Option<Line>> {
+        let from = offset.saturating_sub(1);
+        let mut buffer = [0u8; 2 * MAX_LINE_LENGTH];
+        let read = self.read_at(from, &mut buffer)?;
+        let buffer = &buffer[..read];
+        let skip = if offset == 0 {
+            0
+        } else {
+            // the line starts after the first line ending at or after `offset - 1`
+            match buffer.iter().position(|b| *b == b'\n') {
+                Some(i) => i + 1,
+                None if from + (read as u64) == self.length => return Ok(None),
+                None => return Err(self.malformed(from)),
+            }
+        };
+        let start = from + skip as u64;
+        let rest = &buffer[skip..];
+        if trim_line(rest).is_empty() && start + (rest.len() as u64) == self.length {
+            return Ok(None);
+        }
+        let length = match rest.iter().position(|b| *b == b'\n') {
+            Some(length) => length,
+            None if start + (rest.len() as u64) == self.length => rest.len(),
+            None => return Err(self.malformed(start)),
+        };
+        let (hash, count) = parse_line(&rest[..length]).ok_or_else(|| self.malformed(start))?;
+        Ok(Some(Line {
+            next: start + length as u64 + 1,
+            hash,
+            count,
+        }))
+    }
+
+    fn count_text(&self, hash: &Sha1Hash) -> Result<u64> {
+        // `low` and `high` bound the byte offset at which the line of `hash` starts
+        let (mut low, mut high) = (0, self.length);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match self.line_at(middle)? {
+                Some(line) if line.hash == *hash => return Ok(line.count),
+                Some(line) if line.hash < *hash => low = line.next,
+                // no line starts between `middle` and the line found
+                Some(_) | None => high = middle,
+            }
+        }
+        Ok(0)
+    }
+
+    fn count_index(&self, hash: &Sha1Hash) -> Result<u64> {
+        let (mut low, mut high) = (0, (self.length - INDEX_MAGIC.len() as u64) / RECORD_LENGTH);
+        let mut record = [0u8; RECORD_LENGTH as usize];
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let offset = INDEX_MAGIC.len() as u64 + middle * RECORD_LENGTH;
+            if self.read_at(offset, &mut record)? != record.len() {
+                return Err(self.malformed(offset));
+            }
+            match record[..20].cmp(&hash[..]) {
+                std::cmp::Ordering::Equal => {
+                    let count = [record[20], record[21], record[22], record[23]];
+                    return Ok(u32::from_be_bytes(count) as u64);
+                }
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+            }
+        }
+        Ok(0)
+    }
+
+    fn malformed(&self, offset: u64) -> Error {
+        Error::Breach(format!(
+            "'{}' is malformed at byte {}",
+            self.path.display(),
+            offset
+        ))
+    }
+}
+
+impl BreachCorpus for HashFile {
+    fn count_hash(&self, hash: &Sha1Hash) -> Result<u64> {
+        match self.format {
+            Format::Text => self.count_text(hash),
+            Format::Index => self.count_index(hash),
+        }
+    }
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> Error {
+    Error::Breach(format!("could not access '{}': {}", path.display(), error))
+}
+
+fn trim_line(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Parse a line of the form `HASH:COUNT`.
+fn parse_line(line: &[u8]) -> Option<(Sha1Hash, u64)> {
+    let line = trim_line(line);
+    let colon = line.iter().position(|b| *b == b':')?;
+    let hash = parse_hex(&line[..colon])?;
+    let count = std::str::from_utf8(&line[colon + 1..]).ok()?.parse().ok()?;
+    Some((hash, count))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::breach::{sha1, to_hex};
+    use crate::generators::base::{Constant, PasswordGenerator};
+    use crate::generators::random_string::RandomString;
+
+    /// A path in the temporary directory that is unique to this process and `name`.
+    fn temporary_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("passwords-{}-{}", std::process::id(), name))
+    }
+
+    /// Write a text file of the hashes of `passwords`, with their index in
+    /// `passwords` plus one as their count, and return its path.
+    fn write_text_file(name: &str, passwords: &[&str], line_ending: &str) -> PathBuf {
+        let mut lines: Vec<String> = passwords
+            .iter()
+            .enumerate()
+            .map(|(i, password)| format!("{}:{}", to_hex(&sha1(password)), i + 1))
+            .collect();
+        lines.sort();
+        let path = temporary_path(name);
+        std::fs::write(&path, lines.join(line_ending) + line_ending).unwrap();
+        path
+    }
+
+    fn passwords() -> Vec<String> {
+        (0..500).map(|i| format!("password{}", i)).collect()
+    }
+
+    #[test]
+    fn test_hash_file_lookups() {
+        let passwords = passwords();
+        let passwords: Vec<&str> = passwords.iter().map(|p| p.as_str()).collect();
+        for line_ending in ["\n", "\r\n"].iter() {
+            let text = write_text_file("lookups.txt", &passwords, line_ending);
+            let index = temporary_path("lookups.idx");
+            assert_eq!(
+                HashFile::build_index(&text, &index),
+                Ok(passwords.len() as u64)
+            );
+            for path in [&text, &index].iter() {
+                let file = HashFile::open(path).unwrap();
+                for (i, password) in passwords.iter().enumerate() {
+                    assert_eq!(file.count(password), Ok(i as u64 + 1), "{}", password);
+                }
+                assert_eq!(file.is_breached("password"), Ok(false));
+                assert_eq!(file.is_breached("password500"), Ok(false));
+                assert_eq!(file.count_hash(&[0; 20]), Ok(0));
+                assert_eq!(file.count_hash(&[0xff; 20]), Ok(0));
+            }
+            std::fs::remove_file(text).unwrap();
+            std::fs::remove_file(index).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_hash_file_errors() {
+        let path = temporary_path("unordered.txt");
+        let lines = [to_hex(&sha1("b")), to_hex(&sha1("a"))];
+        std::fs::write(&path, format!("{}:1\n{}:1\n", lines[0], lines[1])).unwrap();
+        let index = temporary_path("unordered.idx");
+        assert!(matches!(
+            HashFile::build_index(&path, &index),
+            Err(Error::Breach(message)) if message.contains("line 2")
+        ));
+
+        std::fs::write(&path, "not a hash file\n").unwrap();
+        let file = HashFile::open(&path).unwrap();
+        assert!(matches!(file.is_breached("a"), Err(Error::Breach(_))));
+
+        std::fs::remove_file(path).unwrap();
+        let _ = std::fs::remove_file(index);
+        assert!(matches!(
+            HashFile::open(temporary_path("missing.txt")),
+            Err(Error::Breach(_))
+        ));
+    }
+
+    #[test]
+    fn test_without_breached() {
+        let path = write_text_file("filter.txt", &["000", "111"], "\n");
+        let file = HashFile::open(&path).unwrap();
+        let passwords = RandomString::digits(1)
+            .pipe(RandomString::digits(2))
+            .without_breached(&file);
+        for _ in 0..20 {
+            let password = passwords.generate();
+            assert!(password != "000" && password != "111", "{}", password);
+        }
+        let passwords = Constant::new("000").without_breached(&file);
+        assert!(matches!(
+            passwords.try_generate(),
+            Err(Error::Rejected { .. })
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+}
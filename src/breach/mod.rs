@@ -0,0 +1,84 @@
+//! Checks of whether passwords appear in breach corpora, such as the "Pwned
+//! Passwords" of Have I Been Pwned, which identify each password by its SHA-1 hash.
+//!
+//! The corpus can be a local copy, with `file`, or, with the `hibp-api` feature,
+//! the range API, with `range`.
+
+pub mod file;
+#[cfg(feature = "hibp-api")]
+pub mod range;
+
+pub use file::HashFile;
+#[cfg(feature = "hibp-api")]
+pub use range::RangeClient;
+
+use sha1::{Digest, Sha1};
+
+use crate::error::Result;
+
+/// The SHA-1 hash of a password.
+pub type Sha1Hash = [u8; 20];
+
+/// The SHA-1 hash of the UTF-8 bytes of `password`.
+pub fn sha1(password: &str) -> Sha1Hash {
+    Sha1::digest(password.as_bytes()).into()
+}
+
+/// The uppercase hexadecimal digits of `hash`, as used by Pwned Passwords.
+pub fn to_hex(hash: &Sha1Hash) -> String {
+    hash.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Parse 40 hexadecimal digits, in either case, into a hash.
+pub(crate) fn parse_hex(hex: &[u8]) -> Option<Sha1Hash> {
+    if hex.len() != 40 {
+        return None;
+    }
+    let mut hash = [0u8; 20];
+    for (byte, digits) in hash.iter_mut().zip(hex.chunks(2)) {
+        let digits = std::str::from_utf8(digits).ok()?;
+        *byte = u8::from_str_radix(digits, 16).ok()?;
+    }
+    Some(hash)
+}
+
+/// A corpus of breached passwords, identified by their SHA-1 hashes.
+pub trait BreachCorpus {
+    /// The number of times that a password with the SHA-1 `hash` appears in the
+    /// corpus, which is zero if it does not appear.
+    fn count_hash(&self, hash: &Sha1Hash) -> Result<u64>;
+
+    /// The number of times that `password` appears in the corpus.
+    fn count(&self, password: &str) -> Result<u64> {
+        self.count_hash(&sha1(password))
+    }
+
+    /// Whether `password` appears in the corpus.
+    fn is_breached(&self, password: &str) -> Result<bool> {
+        Ok(self.count(password)? > 0)
+    }
+}
+
+impl<T: BreachCorpus + ?Sized> BreachCorpus for &T {
+    fn count_hash(&self, hash: &Sha1Hash) -> Result<u64> {
+        (**self).count_hash(hash)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sha1_hex() {
+        let hash = sha1("password");
+        assert_eq!(to_hex(&hash), "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8");
+        assert_eq!(parse_hex(to_hex(&hash).as_bytes()), Some(hash));
+        assert_eq!(
+            parse_hex(b"5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8"),
+            Some(hash)
+        );
+        assert_eq!(parse_hex(b"5BAA61E4"), None);
+        assert_eq!(parse_hex(&[b'G'; 40]), None);
+    }
+}
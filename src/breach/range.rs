@@ -0,0 +1,335 @@
+//! A client for the Pwned Passwords range API, which preserves the anonymity of
+//! the passwords that are looked up.
+//!
+//! Only the first 5 hexadecimal digits of the SHA-1 hash of a password, its
+//! prefix, are sent to the API, which responds with the remaining 35 digits, the
+//! suffix, and the count of every breached hash with that prefix. The suffix of
+//! the password is then looked up locally. With padding, the API also responds
+//! with random suffixes with a count of zero, so that the size of the response
+//! does not reveal the prefix either.
+//!
+//! The requests are made by a `Transport`, which is `UreqTransport` by default
+//! and can be replaced, e.g. by a proxy or a stub server for tests.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::{to_hex, BreachCorpus, Sha1Hash};
+use crate::error::{Error, Result};
+
+/// The URL of the range API, to which the prefix is appended.
+pub const DEFAULT_BASE_URL: &str = "https://api.pwnedpasswords.com/range/";
+
+/// The suffixes of the hashes with a prefix, and their counts.
+pub type Range = HashMap<String, u64>;
+
+/// Makes the HTTP requests of a `RangeClient`.
+pub trait Transport {
+    /// Send a GET request for `url` with `headers`, returning the body of a
+    /// successful response.
+    fn get(&self, url: &str, headers: &[(&str, &str)]) -> Result<String>;
+}
+
+/// A `Transport` that makes requests with `ureq`.
+#[derive(Debug)]
+pub struct UreqTransport {
+    agent: ureq::Agent,
+}
+
+impl UreqTransport {
+    /// Create a `UreqTransport` whose requests time out after 10 seconds.
+    pub fn new() -> UreqTransport {
+        UreqTransport {
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(10))
+                .user_agent(concat!("passwords/", env!("CARGO_PKG_VERSION")))
+                .build(),
+        }
+    }
+}
+
+impl Default for UreqTransport {
+    fn default() -> UreqTransport {
+        UreqTransport::new()
+    }
+}
+
+impl Transport for UreqTransport {
+    fn get(&self, url: &str, headers: &[(&str, &str)]) -> Result<String> {
+        let mut request = self.agent.get(url);
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+        request
+            .call()
+            .map_err(|e| Error::Breach(format!("request to '{}' failed: {}", url, e)))?
+            .into_string()
+            .map_err(|e| Error::Breach(format!("could not read the response of '{}': {}", url, e)))
+    }
+}
+
+/// A client of the range API, which caches the range of each prefix that it
+/// looks up.
+pub struct RangeClient<T: Transport = UreqTransport> {
+    transport: T,
+    base_url: String,
+    padding: bool,
+    cache: Option<Mutex<HashMap<String, Arc<Range>>>>,
+}
+
+impl RangeClient<UreqTransport> {
+    /// Create a `RangeClient` for the Pwned Passwords API that makes requests
+    /// with `UreqTransport`.
+    pub fn pwned_passwords() -> RangeClient<UreqTransport> {
+        RangeClient::new(UreqTransport::new())
+    }
+}
+
+impl<T: Transport> RangeClient<T> {
+    /// Create a `RangeClient` for the Pwned Passwords API that makes requests
+    /// with `transport`, with padding and caching.
+    pub fn new(transport: T) -> RangeClient<T> {
+        RangeClient {
+            transport,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            padding: true,
+            cache: Some(Mutex::new(HashMap::new())),
+        }
+    }
+    /// Create a new `RangeClient` for a different API, whose URL for a prefix is
+    /// `base_url` followed by the prefix.
+    pub fn with_base_url(self, base_url: &str) -> RangeClient<T> {
+        RangeClient {
+            base_url: base_url.to_string(),
+            ..self
+        }
+    }
+    /// Create a new `RangeClient` that does not request padded responses.
+    pub fn without_padding(self) -> RangeClient<T> {
+        RangeClient {
+            padding: false,
+            ..self
+        }
+    }
+    /// Create a new `RangeClient` that requests the range of a prefix each time
+    /// that it is looked up.
+    pub fn without_cache(self) -> RangeClient<T> {
+        RangeClient {
+            cache: None,
+            ..self
+        }
+    }
+
+    /// The suffixes and counts of the breached hashes that start with `prefix`,
+    /// 5 hexadecimal digits, excluding any padding.
+    pub fn range(&self, prefix: &str) -> Result<Arc<Range>> {
+        let prefix = prefix.to_uppercase();
+        if prefix.len() != 5 || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Error::Breach(format!(
+                "'{}' is not a prefix of 5 hexadecimal digits",
+                prefix
+            )));
+        }
+        if let Some(range) = self.cached(&prefix) {
+            return Ok(range);
+        }
+
+        let url = format!("{}{}", self.base_url, prefix);
+        let headers: &[(&str, &str)] = if self.padding {
+            &[("Add-Padding", "true")]
+        } else {
+            &[]
+        };
+        let range = Arc::new(parse_range(&self.transport.get(&url, headers)?, &url)?);
+        if let Some(ref cache) = self.cache {
+            let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+            cache.insert(prefix, range.clone());
+        }
+        Ok(range)
+    }
+
+    fn cached(&self, prefix: &str) -> Option<Arc<Range>> {
+        let cache = self.cache.as_ref()?;
+        let cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache.get(prefix).cloned()
+    }
+}
+
+impl<T: Transport> BreachCorpus for RangeClient<T> {
+    fn count_hash(&self, hash: &Sha1Hash) -> Result<u64> {
+        let hex = to_hex(hash);
+        Ok(self.range(&hex[..5])?.get(&hex[5..]).copied().unwrap_or(0))
+    }
+}
+
+/// Parse a response of lines of the form `SUFFIX:COUNT`, omitting the padding,
+/// whose count is zero.
+fn parse_range(body: &str, url: &str) -> Result<Range> {
+    let mut range = Range::new();
+    for line in body.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let malformed = || {
+            Error::Breach(format!(
+                "the response of '{}' has a malformed line '{}'",
+                url, line
+            ))
+        };
+        let (suffix, count) = line.split_once(':').ok_or_else(malformed)?;
+        let count: u64 = count.parse().map_err(|_| malformed())?;
+        if suffix.len() != 35 || !suffix.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(malformed());
+        }
+        if count > 0 {
+            range.insert(suffix.to_uppercase(), count);
+        }
+    }
+    Ok(range)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::breach::sha1;
+    use crate::generators::base::{Constant, PasswordGenerator};
+    use crate::generators::random_string::RandomString;
+
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// A request received by a `StubServer`: its path and whether it asked for padding.
+    type Request = (String, bool);
+
+    /// A local HTTP server that responds to a request for a prefix with the lines
+    /// of `breached` passwords whose hashes have that prefix, padded with a line
+    /// with a count of zero, and records the requests that it receives.
+    struct StubServer {
+        base_url: String,
+        requests: Arc<Mutex<Vec<Request>>>,
+    }
+
+    impl StubServer {
+        fn start(breached: &[(&str, u64)]) -> StubServer {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let base_url = format!("http://{}/range/", listener.local_addr().unwrap());
+            let hashes: Vec<(String, u64)> = breached
+                .iter()
+                .map(|(password, count)| (to_hex(&sha1(password)), *count))
+                .collect();
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let received = requests.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let mut stream = stream.unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut lines = Vec::new();
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        if line.trim().is_empty() {
+                            break;
+                        }
+                        lines.push(line.trim().to_lowercase());
+                    }
+                    let path = lines[0].split(' ').nth(1).unwrap().to_uppercase();
+                    let padding = lines.iter().any(|l| l == "add-padding: true");
+                    received.lock().unwrap().push((path.clone(), padding));
+
+                    let prefix = path.rsplit('/').next().unwrap();
+                    let mut body: Vec<String> = hashes
+                        .iter()
+                        .filter(|(hash, _)| hash.starts_with(prefix))
+                        .map(|(hash, count)| format!("{}:{}", &hash[5..], count))
+                        .collect();
+                    if padding {
+                        body.push(format!("{}:0", "0".repeat(35)));
+                    }
+                    let body = body.join("\r\n");
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                    .unwrap();
+                }
+            });
+            StubServer { base_url, requests }
+        }
+
+        fn requests(&self) -> Vec<Request> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    fn prefix_path(password: &str) -> String {
+        format!("/RANGE/{}", &to_hex(&sha1(password))[..5])
+    }
+
+    #[test]
+    fn test_range_client_lookups() {
+        let server = StubServer::start(&[("password", 10), ("hunter2", 3)]);
+        let client = RangeClient::pwned_passwords().with_base_url(&server.base_url);
+        assert_eq!(client.count("password"), Ok(10));
+        assert_eq!(client.count("hunter2"), Ok(3));
+        assert_eq!(client.is_breached("correct horse"), Ok(false));
+        // the padding is requested, and omitted from the range
+        let range = client.range(&to_hex(&sha1("password"))[..5]).unwrap();
+        assert_eq!(range.len(), 1);
+        assert_eq!(
+            server.requests(),
+            vec![
+                (prefix_path("password"), true),
+                (prefix_path("hunter2"), true),
+                (prefix_path("correct horse"), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_range_client_cache_and_padding() {
+        let server = StubServer::start(&[("password", 10)]);
+        let client = RangeClient::pwned_passwords()
+            .with_base_url(&server.base_url)
+            .without_padding()
+            .without_cache();
+        assert_eq!(client.count("password"), Ok(10));
+        assert_eq!(client.count("password"), Ok(10));
+        assert_eq!(server.requests(), vec![(prefix_path("password"), false); 2]);
+
+        let server = StubServer::start(&[("password", 10)]);
+        let client = RangeClient::pwned_passwords().with_base_url(&server.base_url);
+        assert_eq!(client.count("password"), Ok(10));
+        assert_eq!(client.count("password"), Ok(10));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_range_client_errors() {
+        let client = RangeClient::pwned_passwords().with_base_url("http://127.0.0.1:1/range/");
+        assert!(matches!(client.count("password"), Err(Error::Breach(_))));
+        assert!(matches!(client.range("XYZ12"), Err(Error::Breach(_))));
+        assert!(matches!(
+            parse_range("not a range", "url"),
+            Err(Error::Breach(_))
+        ));
+        // an unavailable API is an error rather than a rejection
+        assert!(matches!(
+            Constant::new("password")
+                .without_breached(&client)
+                .try_generate(),
+            Err(Error::Breach(_))
+        ));
+    }
+
+    #[test]
+    fn test_range_client_filter() {
+        let server = StubServer::start(&[("0", 1), ("1", 1), ("2", 1)]);
+        let client = RangeClient::pwned_passwords().with_base_url(&server.base_url);
+        let passwords = RandomString::digits(1).without_breached(&client);
+        for _ in 0..20 {
+            let digit: u32 = passwords.generate().parse().unwrap();
+            assert!(digit > 2);
+        }
+    }
+}
@@ -0,0 +1,390 @@
+//! A user configuration file of named profiles, each a recipe for passwords,
+//! e.g. `~/.config/passwords/config.toml`:
+//!
+//! ```toml
+//! [profiles.work]
+//! pipeline = "words(nouns,5) | case(kebab) | digits(3)"
+//! policy = "minlength: 20; required: lower; required: digit; allowed: [-]"
+//! format = "json"
+//!
+//! [profiles.wifi]
+//! pipeline = "xkcd"
+//! corpus = "wordlists/team.txt"
+//!
+//! [profiles.pin]
+//! pipeline = "digits(6)"
+//! ```
+//!
+//! A profile has a `pipeline`, which is `phrases`, `xkcd` or a pipeline
+//! expression, or a `spec` file, and may have a `text` or `corpus` file for the
+//! `phrases` or `xkcd` pipelines, a `passwordrules` `policy` and an output
+//! `format`. A profile named `default` is used when no other is chosen. Relative
+//! paths are relative to the directory of the configuration file.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::generators::base::PasswordGenerator;
+use crate::generators::phrase::Text;
+use crate::output::Format;
+use crate::presets::{PhraseOptions, XkcdOptions};
+use crate::spec::{PipelineSpec, TextSpec};
+use crate::version::AlgorithmVersion;
+use crate::{password_rules, pipeline, policy_passwords_version};
+
+/// The name of the profile that is used when no other is chosen.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The profiles of a configuration file.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// A named recipe for passwords.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// `phrases`, `xkcd` or a pipeline expression.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pipeline: Option<String>,
+    /// A TOML or JSON pipeline specification file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spec: Option<PathBuf>,
+    /// The text of the `phrases` or `xkcd` pipelines.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<TextSpec>,
+    /// A text file for the `phrases` or `xkcd` pipelines.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub corpus: Option<PathBuf>,
+    /// A `passwordrules` string that the passwords must satisfy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<String>,
+    /// The format of the output, rather than one password per line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<Format>,
+}
+
+impl Config {
+    /// Parse a `Config` from a TOML document, checking that every profile is valid.
+    pub fn parse(input: &str) -> Result<Config> {
+        let config: Config = toml::from_str(input).map_err(|e| invalid(e.to_string()))?;
+        for (name, profile) in config.profiles.iter() {
+            profile
+                .check()
+                .map_err(|message| invalid(format!("profile '{}': {}", name, message)))?;
+        }
+        Ok(config)
+    }
+    /// Load a `Config` from a file, resolving the relative paths of its profiles
+    /// against the directory of the file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|e| invalid(format!("could not read '{}': {}", path.display(), e)))?;
+        let mut config = Config::parse(&contents).map_err(|e| match e {
+            Error::InvalidConfig(message) => invalid(format!("'{}': {}", path.display(), message)),
+            e => e,
+        })?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        for profile in config.profiles.values_mut() {
+            profile.resolve_paths(directory);
+        }
+        Ok(config)
+    }
+    /// Load the `Config` at `default_path`, or `None` if there is no such file.
+    pub fn load_default() -> Result<Option<Config>> {
+        match default_path() {
+            Some(path) if path.is_file() => Config::load(path).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// The profile named `name`.
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.get(name)
+    }
+    /// The text files of the profiles, from `corpus` or `text`, with the name of
+    /// the profile of each.
+    pub fn corpora(&self) -> Vec<(&str, &Path)> {
+        self.profiles
+            .iter()
+            .filter_map(|(name, profile)| match (&profile.corpus, &profile.text) {
+                (Some(path), _) | (None, Some(TextSpec::File(path))) => {
+                    Some((name.as_str(), path.as_path()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// The path of the configuration file, `passwords/config.toml` in
+/// `$XDG_CONFIG_HOME`, or else in `$HOME/.config`.
+pub fn default_path() -> Option<PathBuf> {
+    let directory = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(directory.join("passwords").join("config.toml"))
+}
+
+impl Profile {
+    /// The built-in profiles, `phrases` and `xkcd`, which are used when a
+    /// configuration file does not define them.
+    pub fn builtin(name: &str) -> Option<Profile> {
+        match name {
+            "phrases" | "xkcd" => Some(Profile {
+                pipeline: Some(name.to_string()),
+                ..Profile::default()
+            }),
+            _ => None,
+        }
+    }
+
+    /// Build the generator of this profile with the algorithms of `version`, or
+    /// else the latest version, or the version of a pipeline specification.
+    ///
+    /// The generator of a profile with a policy but no pipeline is that of
+    /// `policy_passwords`, and that of a profile with both repairs the passwords
+    /// of the pipeline to satisfy the policy.
+    pub fn build(&self, version: Option<AlgorithmVersion>) -> Result<Box<dyn PasswordGenerator>> {
+        let policy = self
+            .policy
+            .as_deref()
+            .map(password_rules::parse)
+            .transpose()?;
+        let text = self.text_spec();
+        let contents = text.as_ref().map(TextSpec::contents).transpose()?.flatten();
+        let text = text.map(|text| text.to_text(&contents));
+        let phrases =
+            |text: Option<&Text>| PhraseOptions::default().build(text, version.unwrap_or_default());
+        let build_spec = |mut spec: PipelineSpec| {
+            spec.version = version.or(spec.version);
+            spec.build()
+        };
+
+        let generator: Box<dyn PasswordGenerator> = match (&self.pipeline, &self.spec) {
+            (Some(name), _) if name == "phrases" => Box::new(phrases(text.as_ref())?),
+            (Some(name), _) if name == "xkcd" => {
+                let version = version.unwrap_or_default();
+                Box::new(XkcdOptions::default().build(text.as_ref(), version)?)
+            }
+            (Some(expression), _) => build_spec(pipeline::parse(expression)?)?,
+            (None, Some(path)) => build_spec(PipelineSpec::load(path)?)?,
+            (None, None) => match policy {
+                Some(policy) => {
+                    let version = version.unwrap_or_default();
+                    return Ok(Box::new(policy_passwords_version(&policy, version)));
+                }
+                None => Box::new(phrases(text.as_ref())?),
+            },
+        };
+        Ok(match policy {
+            Some(policy) => Box::new(generator.with_policy(policy).with_repair()),
+            None => generator,
+        })
+    }
+
+    /// The text of the `phrases` or `xkcd` pipelines, from `text` or `corpus`.
+    pub fn text_spec(&self) -> Option<TextSpec> {
+        match self.corpus {
+            Some(ref path) => Some(TextSpec::File(path.clone())),
+            None => self.text.clone(),
+        }
+    }
+
+    fn check(&self) -> std::result::Result<(), String> {
+        if self.pipeline.is_some() && self.spec.is_some() {
+            return Err("has both a pipeline and a spec".to_string());
+        }
+        if self.text.is_some() && self.corpus.is_some() {
+            return Err("has both a text and a corpus".to_string());
+        }
+        let preset = match self.pipeline.as_deref() {
+            Some("phrases") | Some("xkcd") => true,
+            Some(expression) => {
+                pipeline::parse(expression).map_err(|e| e.to_string())?;
+                false
+            }
+            None => self.spec.is_none(),
+        };
+        if !preset && self.text_spec().is_some() {
+            return Err(
+                "has a text or corpus, which only apply to the phrases and xkcd pipelines"
+                    .to_string(),
+            );
+        }
+        if let Some(ref policy) = self.policy {
+            password_rules::parse(policy).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn resolve_paths(&mut self, directory: &Path) {
+        let resolve = |path: &mut PathBuf| {
+            if path.is_relative() {
+                *path = directory.join(&*path);
+            }
+        };
+        if let Some(ref mut path) = self.spec {
+            resolve(path);
+        }
+        if let Some(ref mut path) = self.corpus {
+            resolve(path);
+        }
+        if let Some(TextSpec::File(ref mut path)) = self.text {
+            resolve(path);
+        }
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::InvalidConfig(message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::xkcd_passwords;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    static CONFIG: &str = r#"
+[profiles.work]
+pipeline = "words(nouns,5) | case(kebab) | digits(3)"
+policy = "minlength: 20; required: lower; required: digit; allowed: [-]"
+format = "json"
+
+[profiles.wifi]
+pipeline = "xkcd"
+text = "alice-in-wonderland"
+
+[profiles.pin]
+pipeline = "digits(6)"
+
+[profiles.strict]
+policy = "minlength: 12; maxlength: 12; required: lower, upper, digit"
+"#;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(CONFIG).unwrap();
+        assert_eq!(config.profiles.len(), 4);
+        let work = config.profile("work").unwrap();
+        assert_eq!(work.format, Some(Format::Json));
+        assert_eq!(
+            config.profile("wifi").unwrap().text_spec(),
+            Some(TextSpec::AliceInWonderland)
+        );
+        assert!(config.profile("home").is_none());
+        assert!(config.corpora().is_empty());
+        assert_eq!(Config::parse(""), Ok(Config::default()));
+    }
+
+    #[test]
+    fn test_build_profiles() {
+        let config = Config::parse(CONFIG).unwrap();
+        let password = config
+            .profile("work")
+            .unwrap()
+            .build(None)
+            .unwrap()
+            .generate();
+        assert!(
+            password.len() >= 20 && password.contains('-'),
+            "{}",
+            password
+        );
+        let password = config
+            .profile("pin")
+            .unwrap()
+            .build(None)
+            .unwrap()
+            .generate();
+        assert!(password.len() == 6 && password.chars().all(|c| c.is_ascii_digit()));
+        let password = config
+            .profile("strict")
+            .unwrap()
+            .build(None)
+            .unwrap()
+            .generate();
+        assert_eq!(password.len(), 12);
+        let wifi = config.profile("wifi").unwrap().build(None).unwrap();
+        assert_eq!(wifi.generate().split(' ').count(), 4);
+
+        let xkcd = Profile::builtin("xkcd").unwrap().build(None).unwrap();
+        assert!((xkcd.entropy() - xkcd_passwords(None).entropy()).abs() < 1e-9);
+        assert!(Profile::builtin("work").is_none());
+
+        let phrases = Profile::builtin("phrases")
+            .unwrap()
+            .build(Some(AlgorithmVersion::V1))
+            .unwrap();
+        let expected = crate::phrase_passwords_version(None, AlgorithmVersion::V1);
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut expected_rng = StdRng::seed_from_u64(1);
+        for _ in 0..10 {
+            assert_eq!(
+                phrases.generate_with_rng(&mut rng),
+                expected.generate_with_rng(&mut expected_rng)
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_configs() {
+        for config in [
+            "[profiles.a]\npipeline = \"digits(\"",
+            "[profiles.a]\npolicy = \"required: nonsense\"",
+            "[profiles.a]\npipeline = \"digits(3)\"\ntext = \"nouns\"",
+            "[profiles.a]\npipeline = \"xkcd\"\ntext = \"nouns\"\ncorpus = \"a.txt\"",
+            "[profiles.a]\npipeline = \"xkcd\"\nspec = \"a.toml\"",
+            "[profiles.a]\nformat = \"xml\"",
+            "[profiles.a]\ncolour = \"blue\"",
+        ]
+        .iter()
+        {
+            assert!(
+                matches!(Config::parse(config), Err(Error::InvalidConfig(_))),
+                "{}",
+                config
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_config() {
+        let directory =
+            std::env::temp_dir().join(format!("passwords-config-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("team.txt"), "alpha bravo charlie delta echo").unwrap();
+        let path = directory.join("config.toml");
+        fs::write(
+            &path,
+            "[profiles.default]\npipeline = \"xkcd\"\ncorpus = \"team.txt\"\n",
+        )
+        .unwrap();
+        let config = Config::load(&path).unwrap();
+        let profile = config.profile(DEFAULT_PROFILE).unwrap();
+        assert_eq!(profile.corpus, Some(directory.join("team.txt")));
+        let team = directory.join("team.txt");
+        assert_eq!(config.corpora(), [("default", team.as_path())]);
+        let password = profile.build(None).unwrap().generate();
+        fs::remove_dir_all(&directory).unwrap();
+        assert!(password
+            .split(' ')
+            .all(|word| "alpha bravo charlie delta echo".contains(word)));
+        assert!(matches!(
+            Config::load(directory.join("missing.toml")),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+}
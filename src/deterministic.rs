@@ -0,0 +1,269 @@
+//! Deterministic, site-specific passwords derived from a master passphrase, so
+//! that a password can be regenerated from what the user remembers rather than
+//! stored.
+//!
+//! # Derivation, version 1
+//!
+//! 1. The salt is the bytes of `passwords-v1`, followed by the site name and the
+//!    login, each as a 4 byte big-endian length and its UTF-8 bytes, followed by
+//!    the counter as a 4 byte big-endian integer. The site name and login are used
+//!    exactly as given, so `example.com` and `Example.com` are different sites.
+//! 2. A 32 byte key is derived from the UTF-8 bytes of the master passphrase and
+//!    the salt with Argon2id (version 0x13) or scrypt, using the parameters of the
+//!    `Kdf`, which are part of the derivation.
+//! 3. The key seeds a `ChaCha20Rng`, which drives the pipeline in place of a
+//!    thread-local random number generator.
+//!
+//! The password is therefore a function of the master passphrase, site, login,
+//! counter, `Kdf`, and pipeline and its `AlgorithmVersion`. Changing the counter gives a new password for the
+//! same site, e.g. after a forced password rotation.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+
+use crate::error::{Error, Result};
+use crate::generators::base::PasswordGenerator;
+
+/// The version of the derivation that is documented above.
+pub const VERSION: u32 = 1;
+
+/// A key derivation function and its parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kdf {
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+    Scrypt {
+        log_n: u8,
+        r: u32,
+        p: u32,
+    },
+}
+
+impl Kdf {
+    /// Argon2id with 19 MiB of memory, 2 iterations and 1 lane, the minimum
+    /// that OWASP recommends.
+    pub fn argon2id() -> Kdf {
+        Kdf::Argon2id {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+    /// scrypt with N = 2^15, r = 8 and p = 1.
+    pub fn scrypt() -> Kdf {
+        Kdf::Scrypt {
+            log_n: 15,
+            r: 8,
+            p: 1,
+        }
+    }
+
+    /// Derive a 32 byte key from `password` and `salt`.
+    pub fn derive(&self, password: &[u8], salt: &[u8]) -> Result<[u8; 32]> {
+        let mut key = [0u8; 32];
+        match *self {
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let params = Params::new(memory_kib, iterations, parallelism, Some(key.len()))
+                    .map_err(|e| Error::KeyDerivation(e.to_string()))?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(password, salt, &mut key)
+                    .map_err(|e| Error::KeyDerivation(e.to_string()))?;
+            }
+            Kdf::Scrypt { log_n, r, p } => {
+                let params = scrypt::Params::new(log_n, r, p, key.len())
+                    .map_err(|e| Error::KeyDerivation(e.to_string()))?;
+                scrypt::scrypt(password, salt, &params, &mut key)
+                    .map_err(|e| Error::KeyDerivation(e.to_string()))?;
+            }
+        }
+        Ok(key)
+    }
+}
+
+impl Default for Kdf {
+    fn default() -> Kdf {
+        Kdf::argon2id()
+    }
+}
+
+/// A site, the login for the site, and a counter that is incremented each time
+/// the password for the site must change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Site {
+    name: String,
+    login: String,
+    counter: u32,
+}
+
+impl Site {
+    /// Create a `Site` with an empty login and a counter of 1.
+    pub fn new(name: &str) -> Site {
+        Site {
+            name: name.to_string(),
+            login: String::new(),
+            counter: 1,
+        }
+    }
+    /// Create a new `Site` with a different `login`.
+    pub fn with_login(self, login: &str) -> Site {
+        Site {
+            login: login.to_string(),
+            ..self
+        }
+    }
+    /// Create a new `Site` with a different `counter`.
+    pub fn with_counter(self, counter: u32) -> Site {
+        Site { counter, ..self }
+    }
+
+    /// The salt for this site, as described in the module documentation.
+    pub fn salt(&self) -> Vec<u8> {
+        let mut salt = format!("passwords-v{}", VERSION).into_bytes();
+        for field in [&self.name, &self.login] {
+            salt.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            salt.extend_from_slice(field.as_bytes());
+        }
+        salt.extend_from_slice(&self.counter.to_be_bytes());
+        salt
+    }
+}
+
+/// Derives the random number generator for a `Site` from a master passphrase.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Derivation {
+    kdf: Kdf,
+}
+
+impl Derivation {
+    /// Create a `Derivation` that derives keys with `kdf`.
+    pub fn new(kdf: Kdf) -> Derivation {
+        Derivation { kdf }
+    }
+
+    /// Derive the key for `site` from the `master` passphrase, or return
+    /// `Error::KeyDerivation` if the passphrase is empty.
+    pub fn key(&self, master: &str, site: &Site) -> Result<[u8; 32]> {
+        if master.is_empty() {
+            return Err(Error::KeyDerivation(
+                "the master passphrase is empty".to_string(),
+            ));
+        }
+        self.kdf.derive(master.as_bytes(), &site.salt())
+    }
+    /// Derive a random number generator for `site` from the `master` passphrase.
+    pub fn rng(&self, master: &str, site: &Site) -> Result<ChaCha20Rng> {
+        Ok(ChaCha20Rng::from_seed(self.key(master, site)?))
+    }
+    /// Generate the password for `site` from the `master` passphrase with `generator`.
+    pub fn generate(
+        &self,
+        generator: &dyn PasswordGenerator,
+        master: &str,
+        site: &Site,
+    ) -> Result<String> {
+        generator.try_generate_with_rng(&mut self.rng(master, site)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Parameters that are cheap enough for tests, but not for real passwords.
+    fn fast_argon2id() -> Derivation {
+        Derivation::new(Kdf::Argon2id {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        })
+    }
+
+    fn fast_scrypt() -> Derivation {
+        Derivation::new(Kdf::Scrypt {
+            log_n: 4,
+            r: 8,
+            p: 1,
+        })
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_site_salt() {
+        let site = Site::new("example.com").with_login("me").with_counter(2);
+        let mut expected = b"passwords-v1".to_vec();
+        expected.extend_from_slice(&[0, 0, 0, 11]);
+        expected.extend_from_slice(b"example.com");
+        expected.extend_from_slice(&[0, 0, 0, 2]);
+        expected.extend_from_slice(b"me");
+        expected.extend_from_slice(&[0, 0, 0, 2]);
+        assert_eq!(site.salt(), expected);
+
+        // the length prefixes keep the fields apart
+        assert_ne!(
+            Site::new("ab").with_login("c").salt(),
+            Site::new("a").with_login("bc").salt()
+        );
+    }
+
+    #[test]
+    fn test_derivation_key() {
+        let site = Site::new("example.com");
+        assert_eq!(
+            hex(&fast_argon2id().key("correct horse", &site).unwrap()),
+            "e4a887fff315086982161779fbe6d659f914a8457f3c2a6cbf1943ce8ebdc6c0"
+        );
+        assert_eq!(
+            hex(&fast_scrypt().key("correct horse", &site).unwrap()),
+            "9ecebdabad84a341328eb39e5e8910eceae4f6a46d4adbb5b79654c99ba88bd5"
+        );
+    }
+
+    #[test]
+    fn test_derivation_generate_is_deterministic() {
+        let generator = crate::xkcd_passwords(None);
+        let site = Site::new("example.com").with_login("me");
+        for derivation in [fast_argon2id(), fast_scrypt()].iter() {
+            let password = derivation.generate(&generator, "master", &site).unwrap();
+            assert_eq!(
+                derivation.generate(&generator, "master", &site),
+                Ok(password.clone())
+            );
+            assert_ne!(
+                derivation.generate(&generator, "master", &site.clone().with_counter(2)),
+                Ok(password.clone())
+            );
+            assert_ne!(
+                derivation.generate(&generator, "Master", &site),
+                Ok(password)
+            );
+        }
+    }
+
+    #[test]
+    fn test_derivation_invalid_parameters() {
+        let derivation = Derivation::new(Kdf::Argon2id {
+            memory_kib: 1,
+            iterations: 1,
+            parallelism: 1,
+        });
+        assert!(matches!(
+            derivation.key("master", &Site::new("example.com")),
+            Err(Error::KeyDerivation(_))
+        ));
+        assert!(matches!(
+            fast_scrypt().generate(&crate::xkcd_passwords(None), "", &Site::new("example.com")),
+            Err(Error::KeyDerivation(_))
+        ));
+    }
+}
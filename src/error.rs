@@ -0,0 +1,111 @@
+use std::fmt;
+use std::ops::Range;
+
+/// The errors that can occur while constructing or running a `PasswordGenerator`.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    /// A generator has no words, phrases or characters to choose from.
+    EmptyVocabulary,
+    /// A range was specified with a minimum that is larger than its maximum.
+    InvalidRange { min: usize, max: usize },
+    /// A `Switch` was given no generators, or weights that are negative,
+    /// not finite, or all zero.
+    InvalidWeights,
+    /// No password satisfying a `Policy` was generated within the allowed attempts.
+    PolicyNotSatisfied { attempts: usize },
+    /// A probability was not between zero and one.
+    InvalidProbability(f64),
+    /// A `passwordrules` string could not be parsed.
+    InvalidRules(String),
+    /// A pipeline specification could not be parsed or built.
+    InvalidSpec(String),
+    /// A pipeline expression could not be parsed, because of the characters at
+    /// the byte offsets `span` of the expression.
+    InvalidPipeline { message: String, span: Range<usize> },
+    /// A key could not be derived, e.g. because of invalid parameters.
+    KeyDerivation(String),
+    /// An algorithm version is not supported by this release.
+    UnsupportedVersion(u32),
+    /// No password passed a `Filter` within the allowed attempts.
+    Rejected { filter: String, attempts: usize },
+    /// A breach corpus could not be read or queried.
+    Breach(String),
+    /// A `pwquality.conf` file could not be read or parsed.
+    InvalidPwQuality(String),
+    /// A password could not be hashed, or a hash could not be parsed.
+    Hash(String),
+    /// A CSV file of accounts to provision could not be parsed.
+    InvalidCsv(String),
+    /// Provisioned accounts could not be formatted as JSON.
+    Json(String),
+    /// A configuration file could not be read or parsed, or has an invalid profile.
+    InvalidConfig(String),
+    /// An option of the `phrases` or `xkcd` pipelines is invalid, e.g. zero words.
+    InvalidOption(String),
+}
+
+/// A `Result` type alias with `Error` as the error type.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::EmptyVocabulary => write!(f, "there are no candidates to choose from"),
+            Error::InvalidRange { min, max } => {
+                write!(
+                    f,
+                    "invalid range: minimum {} is larger than maximum {}",
+                    min, max
+                )
+            }
+            Error::InvalidWeights => {
+                write!(f, "weights must be finite, non-negative, and not all zero")
+            }
+            Error::PolicyNotSatisfied { attempts } => write!(
+                f,
+                "no password satisfied the policy after {} attempts",
+                attempts
+            ),
+            Error::InvalidProbability(p) => {
+                write!(f, "invalid probability: {} is not between 0 and 1", p)
+            }
+            Error::InvalidRules(message) => write!(f, "invalid password rules: {}", message),
+            Error::InvalidSpec(message) => write!(f, "invalid pipeline spec: {}", message),
+            Error::InvalidPipeline { message, span } => write!(
+                f,
+                "invalid pipeline at {}..{}: {}",
+                span.start, span.end, message
+            ),
+            Error::KeyDerivation(message) => write!(f, "key derivation failed: {}", message),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported algorithm version: {}", version)
+            }
+            Error::Rejected { filter, attempts } => write!(
+                f,
+                "no password passed the filter '{}' after {} attempts",
+                filter, attempts
+            ),
+            Error::Breach(message) => write!(f, "breach corpus lookup failed: {}", message),
+            Error::InvalidPwQuality(message) => {
+                write!(f, "invalid pwquality configuration: {}", message)
+            }
+            Error::Hash(message) => write!(f, "password hashing failed: {}", message),
+            Error::InvalidCsv(message) => write!(f, "invalid CSV: {}", message),
+            Error::Json(message) => write!(f, "JSON formatting failed: {}", message),
+            Error::InvalidConfig(message) => write!(f, "invalid configuration: {}", message),
+            Error::InvalidOption(message) => write!(f, "invalid option: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Return an `Error::InvalidRange` if `min` is larger than `max`.
+pub(crate) fn check_range(min: usize, max: usize) -> Result<()> {
+    if min > max {
+        Err(Error::InvalidRange { min, max })
+    } else {
+        Ok(())
+    }
+}
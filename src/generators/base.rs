@@ -1,11 +1,28 @@
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
 use std::str::FromStr;
 
-use rand::rngs::ThreadRng;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::{StdRng, ThreadRng};
 use rand::seq::SliceRandom;
+use rand::{RngCore, SeedableRng};
+use serde::Serialize;
+
+use super::filter::Filter;
+use super::policy::{Policy, WithPolicy};
+use crate::breach::BreachCorpus;
+use crate::error::{Error, Result};
 
 pub static ASCII_LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
 pub static ASCII_UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
 pub static DIGITS: &str = "0123456789";
+pub static ASCII_PUNCTUATION: &str = r##"!"#$%&'()*+,-./:;<=>?@[\]^_`{|}~"##;
+
+/// The number of passwords sampled to estimate the entropy of a pipeline whose
+/// stages have entropies that depend on their seeds.
+const ENTROPY_SAMPLES: usize = 64;
 
 /// A trait that is implemented by all objects that generate passwords.
 pub trait PasswordGenerator {
@@ -15,18 +32,155 @@ pub trait PasswordGenerator {
     /// * `rng` - a random number generator
     /// * `seed` - an input string that the random number generator can act on.
     ///
-    fn generate_with_seed(&self, _rng: &mut ThreadRng, seed: String) -> String {
+    fn generate_with_seed(&self, _rng: &mut dyn RngCore, seed: String) -> String {
         seed
     }
 
+    /// Try to generate a random password given a seed and random number generator,
+    /// returning an `Error` rather than panicking if the password cannot be generated.
+    ///
+    /// Calls `generate_with_seed` unless overridden by a generator that can fail.
+    ///
+    fn try_generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> Result<String> {
+        Ok(self.generate_with_seed(rng, seed))
+    }
+
+    /// Estimate the entropy, in bits, that this generator adds to its seed.
+    ///
+    /// Generators that do not use the random number generator add no entropy.
+    ///
+    fn entropy(&self) -> f64 {
+        0.0
+    }
+
+    /// Compute the entropy, in bits, that this generator adds to a particular `seed`.
+    ///
+    /// Defaults to `entropy`, which is exact for generators whose entropy does
+    /// not depend on the seed.
+    ///
+    fn entropy_with_seed(&self, _seed: &str) -> f64 {
+        self.entropy()
+    }
+
+    /// Whether `entropy_with_seed` depends on the seed, so that the entropy of a
+    /// pipeline with this generator as a stage is estimated from sampled seeds.
+    ///
+    fn entropy_depends_on_seed(&self) -> bool {
+        false
+    }
+
+    /// Try to generate a candidate password as `try_generate_with_seed` does, but
+    /// without the checks of any filters, such as strength estimates or breach
+    /// lookups, which are too expensive or have side effects. Pipelines generate
+    /// candidates to sample the seeds of stages whose entropy depends on them.
+    ///
+    fn try_generate_candidate_with_seed(
+        &self,
+        rng: &mut dyn RngCore,
+        seed: String,
+    ) -> Result<String> {
+        self.try_generate_with_seed(rng, seed)
+    }
+
+    /// Describe this generator and its parameters on a single line, e.g.
+    /// `RandomString(length=2, characters="0123456789")`.
+    ///
+    fn description(&self) -> String {
+        "PasswordGenerator".to_string()
+    }
+
+    /// The generators that this generator is composed of, if any.
+    ///
+    fn children(&self) -> Vec<&dyn PasswordGenerator> {
+        Vec::new()
+    }
+
+    /// Whether this generator is a pipeline of stages, whose `children` are
+    /// shown as stages of any enclosing pipeline.
+    ///
+    fn is_pipeline(&self) -> bool {
+        false
+    }
+
+    /// Render this generator as a tree of its `children`, with the parameters
+    /// and entropy of each.
+    ///
+    fn describe(&self) -> String {
+        let mut lines = Vec::new();
+        describe_tree(
+            self.description(),
+            self.entropy(),
+            self.children(),
+            0,
+            &mut lines,
+        );
+        lines.join("\n")
+    }
+
+    /// Try to generate a random password given a seed and random number generator,
+    /// recording the output of each stage of the pipeline in `stages`.
+    ///
+    /// Calls `try_generate_with_seed` and records a single stage unless
+    /// overridden by a generator that is composed of other generators.
+    ///
+    fn explain_with_seed(
+        &self,
+        rng: &mut dyn RngCore,
+        seed: String,
+        depth: usize,
+        stages: &mut Vec<Stage>,
+    ) -> Result<String> {
+        let entropy = self.entropy_with_seed(&seed);
+        let output = self.try_generate_with_seed(rng, seed)?;
+        stages.push(Stage {
+            depth,
+            description: self.description(),
+            output: output.clone(),
+            entropy,
+        });
+        Ok(output)
+    }
+
+    /// Generate a random password, returning the output of each stage of the
+    /// pipeline that produced it. The last stage's output is the password.
+    ///
+    fn explain(&self, rng: &mut dyn RngCore) -> Result<Vec<Stage>> {
+        let mut stages = Vec::new();
+        self.explain_with_seed(rng, String::new(), 0, &mut stages)?;
+        Ok(stages)
+    }
+
     /// Generate a random password.
     ///
-    /// Calls `generate_with_seed` with a new random number generator and an
-    /// empty seed string
+    /// Calls `generate_with_rng` with a new thread-local random number generator.
     ///
     fn generate(&self) -> String {
         let mut rng = rand::thread_rng();
-        self.generate_with_seed(&mut rng, String::new())
+        self.generate_with_rng(&mut rng)
+    }
+
+    /// Generate a random password using the provided random number generator.
+    ///
+    /// Calls `generate_with_seed` with `rng` and an empty seed string. Any
+    /// `RngCore` may be used, e.g. a seeded `StdRng` for reproducible output
+    /// or `OsRng` when a cryptographically secure generator is required.
+    ///
+    fn generate_with_rng(&self, rng: &mut dyn RngCore) -> String {
+        self.generate_with_seed(rng, String::new())
+    }
+
+    /// Try to generate a random password, returning an `Error` rather than
+    /// panicking if the password cannot be generated.
+    ///
+    fn try_generate(&self) -> Result<String> {
+        let mut rng = rand::thread_rng();
+        self.try_generate_with_rng(&mut rng)
+    }
+
+    /// Try to generate a random password using the provided random number generator.
+    ///
+    fn try_generate_with_rng(&self, rng: &mut dyn RngCore) -> Result<String> {
+        self.try_generate_with_seed(rng, String::new())
     }
 
     /// Create a `ChainedGenerator` by pipelining this `PasswordGenerator` with
@@ -39,6 +193,7 @@ pub trait PasswordGenerator {
         ChainedGenerator {
             first: Box::new(self),
             second: Box::new(other),
+            entropy: OnceCell::new(),
         }
     }
 
@@ -48,21 +203,181 @@ pub trait PasswordGenerator {
     where
         Self: Sized + 'a,
     {
-        let rng = rand::thread_rng();
+        self.iterator_with_rng(rand::thread_rng())
+    }
+
+    /// Create a `PasswordIterator` from a `PasswordGenerator` that draws its
+    /// randomness from `rng` rather than the thread-local random number generator.
+    fn iterator_with_rng<'a, R>(self, rng: R) -> PasswordIterator<'a, R>
+    where
+        Self: Sized + 'a,
+        R: RngCore,
+    {
         PasswordIterator {
             generator: Box::new(self),
-            rng: rng,
+            rng,
         }
     }
 
+    /// Create a `Switch` that chooses uniformly between this `PasswordGenerator`
+    /// and another one.
     fn or<'a, T>(self, other: T) -> Switch<'a>
     where
         Self: Sized + 'a,
         T: PasswordGenerator + Sized + 'a,
     {
-        let generators: Vec<Box<dyn PasswordGenerator>> = vec![Box::new(other), Box::new(self)];
+        any_of(vec![Box::new(other), Box::new(self)])
+    }
 
-        Switch { generators }
+    /// Create a `WithPolicy` generator that only generates passwords from this
+    /// `PasswordGenerator` that satisfy `policy`.
+    fn with_policy<'a>(self, policy: Policy) -> WithPolicy<'a>
+    where
+        Self: Sized + 'a,
+    {
+        WithPolicy::new(self, policy)
+    }
+
+    /// Create a `Filter` generator that only generates passwords from this
+    /// `PasswordGenerator` that `predicate` accepts.
+    fn filter<'a, F>(self, name: &str, predicate: F) -> Filter<'a>
+    where
+        Self: Sized + 'a,
+        F: Fn(&str) -> bool + 'a,
+    {
+        Filter::new(self, name, predicate)
+    }
+
+    /// Create a `Filter` generator that only generates passwords from this
+    /// `PasswordGenerator` whose estimated number of guesses, as estimated by
+    /// `strength::estimate_reproducibly`, is at least `min_guesses`.
+    fn with_min_guesses<'a>(self, min_guesses: f64) -> Filter<'a>
+    where
+        Self: Sized + 'a,
+    {
+        let name = format!("guesses >= {:e}", min_guesses);
+        Filter::new(self, &name, move |password: &str| {
+            crate::strength::estimate_reproducibly(password).guesses >= min_guesses
+        })
+    }
+
+    /// Create a `Filter` generator that only generates passwords from this
+    /// `PasswordGenerator` that do not appear in the breach `corpus`. An error
+    /// looking up a password is returned rather than treated as a rejection.
+    ///
+    /// The entropy of the filter is that of this generator, as few generated
+    /// passwords are breached and estimating the fraction would query the corpus.
+    fn without_breached<'a, C>(self, corpus: C) -> Filter<'a>
+    where
+        Self: Sized + 'a,
+        C: BreachCorpus + 'a,
+    {
+        Filter::try_new(self, "not breached", move |password: &str| {
+            Ok(!corpus.is_breached(password)?)
+        })
+        .without_acceptance_estimate()
+    }
+}
+
+/// The output of one stage of a pipeline, as recorded by `PasswordGenerator::explain`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Stage {
+    /// How deeply the stage is nested within `Switch`es and other combinators.
+    pub depth: usize,
+    pub description: String,
+    pub output: String,
+    /// The entropy, in bits, that the stage added to its input.
+    pub entropy: f64,
+}
+
+/// Append a line for a generator and, recursively, its children to `lines`.
+fn describe_tree(
+    description: String,
+    entropy: f64,
+    children: Vec<&dyn PasswordGenerator>,
+    depth: usize,
+    lines: &mut Vec<String>,
+) {
+    lines.push(format!(
+        "{}{} [{:.1} bits]",
+        "  ".repeat(depth),
+        description,
+        entropy
+    ));
+    for child in children {
+        describe_tree(
+            child.description(),
+            child.entropy(),
+            child.children(),
+            depth + 1,
+            lines,
+        );
+    }
+}
+
+impl fmt::Display for dyn PasswordGenerator + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.description())
+    }
+}
+
+impl fmt::Debug for dyn PasswordGenerator + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.description())
+    }
+}
+
+/// A boxed `PasswordGenerator` is a `PasswordGenerator`, so that generators built
+/// at runtime, e.g. from a `PipelineSpec`, can be piped and wrapped like any other.
+impl<T: PasswordGenerator + ?Sized> PasswordGenerator for Box<T> {
+    fn generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> String {
+        (**self).generate_with_seed(rng, seed)
+    }
+
+    fn try_generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> Result<String> {
+        (**self).try_generate_with_seed(rng, seed)
+    }
+
+    fn entropy(&self) -> f64 {
+        (**self).entropy()
+    }
+
+    fn entropy_with_seed(&self, seed: &str) -> f64 {
+        (**self).entropy_with_seed(seed)
+    }
+
+    fn entropy_depends_on_seed(&self) -> bool {
+        (**self).entropy_depends_on_seed()
+    }
+
+    fn try_generate_candidate_with_seed(
+        &self,
+        rng: &mut dyn RngCore,
+        seed: String,
+    ) -> Result<String> {
+        (**self).try_generate_candidate_with_seed(rng, seed)
+    }
+
+    fn description(&self) -> String {
+        (**self).description()
+    }
+
+    fn children(&self) -> Vec<&dyn PasswordGenerator> {
+        (**self).children()
+    }
+
+    fn is_pipeline(&self) -> bool {
+        (**self).is_pipeline()
+    }
+
+    fn explain_with_seed(
+        &self,
+        rng: &mut dyn RngCore,
+        seed: String,
+        depth: usize,
+        stages: &mut Vec<Stage>,
+    ) -> Result<String> {
+        (**self).explain_with_seed(rng, seed, depth, stages)
     }
 }
 
@@ -71,13 +386,115 @@ pub trait PasswordGenerator {
 pub struct ChainedGenerator<'a> {
     first: Box<dyn PasswordGenerator + 'a>,
     second: Box<dyn PasswordGenerator + 'a>,
+    entropy: OnceCell<f64>,
 }
 
 impl<'a> PasswordGenerator for ChainedGenerator<'a> {
-    fn generate_with_seed(&self, rng: &mut ThreadRng, seed: String) -> String {
+    fn generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> String {
         let seed = self.first.generate_with_seed(rng, seed);
         self.second.generate_with_seed(rng, seed)
     }
+
+    fn try_generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> Result<String> {
+        let seed = self.first.try_generate_with_seed(rng, seed)?;
+        self.second.try_generate_with_seed(rng, seed)
+    }
+
+    /// The entropy of the pipeline with an empty seed, which is computed once.
+    fn entropy(&self) -> f64 {
+        *self.entropy.get_or_init(|| self.entropy_with_seed(""))
+    }
+
+    /// The sum of the entropy that each stage adds to its input.
+    ///
+    /// The entropy of stages such as `Defects` and `RandomCase`, which depends
+    /// on their seed, is averaged over seeds sampled from candidates of the
+    /// preceding stages with a fixed random seed, and that of the other stages
+    /// is their `entropy`. The candidates skip the checks of filters, and a
+    /// stage whose seed could not be generated counts its `entropy`.
+    fn entropy_with_seed(&self, seed: &str) -> f64 {
+        let stages = self.children();
+        let sampled = match stages
+            .iter()
+            .rposition(|stage| stage.entropy_depends_on_seed())
+        {
+            Some(last) => last + 1,
+            None => return stages.iter().map(|stage| stage.entropy()).sum(),
+        };
+        let mut entropy: f64 = stages
+            .iter()
+            .filter(|stage| !stage.entropy_depends_on_seed())
+            .map(|stage| stage.entropy())
+            .sum();
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..ENTROPY_SAMPLES {
+            let mut output = Some(seed.to_string());
+            for (i, stage) in stages[..sampled].iter().enumerate() {
+                if stage.entropy_depends_on_seed() {
+                    let stage_entropy = match output {
+                        Some(ref output) => stage.entropy_with_seed(output),
+                        None => stage.entropy(),
+                    };
+                    entropy += stage_entropy / ENTROPY_SAMPLES as f64;
+                }
+                if i + 1 < sampled {
+                    output = output.and_then(|output| {
+                        stage
+                            .try_generate_candidate_with_seed(&mut rng, output)
+                            .ok()
+                    });
+                }
+            }
+        }
+        entropy
+    }
+
+    fn entropy_depends_on_seed(&self) -> bool {
+        self.children()
+            .iter()
+            .any(|stage| stage.entropy_depends_on_seed())
+    }
+
+    fn try_generate_candidate_with_seed(
+        &self,
+        rng: &mut dyn RngCore,
+        seed: String,
+    ) -> Result<String> {
+        let seed = self.first.try_generate_candidate_with_seed(rng, seed)?;
+        self.second.try_generate_candidate_with_seed(rng, seed)
+    }
+
+    fn description(&self) -> String {
+        "Chain".to_string()
+    }
+
+    /// The stages of the pipeline, flattening any nested pipelines.
+    fn children(&self) -> Vec<&dyn PasswordGenerator> {
+        let mut children = Vec::new();
+        for child in [self.first.as_ref(), self.second.as_ref()] {
+            if child.is_pipeline() {
+                children.extend(child.children());
+            } else {
+                children.push(child);
+            }
+        }
+        children
+    }
+
+    fn is_pipeline(&self) -> bool {
+        true
+    }
+
+    fn explain_with_seed(
+        &self,
+        rng: &mut dyn RngCore,
+        seed: String,
+        depth: usize,
+        stages: &mut Vec<Stage>,
+    ) -> Result<String> {
+        let seed = self.first.explain_with_seed(rng, seed, depth, stages)?;
+        self.second.explain_with_seed(rng, seed, depth, stages)
+    }
 }
 
 /// A `PasswordGenerator` that simply generates a constant password.
@@ -87,7 +504,7 @@ pub struct Constant {
 
 impl Constant {
     pub fn new(seed: &str) -> Constant {
-        let value = String::from_str(&seed).unwrap();
+        let value = String::from_str(seed).unwrap();
         Constant { value }
     }
     pub fn empty() -> Constant {
@@ -96,19 +513,99 @@ impl Constant {
 }
 
 impl PasswordGenerator for Constant {
-    fn generate_with_seed(&self, _rng: &mut ThreadRng, seed: String) -> String {
+    fn generate_with_seed(&self, _rng: &mut dyn RngCore, seed: String) -> String {
         seed + &self.value
     }
+
+    fn description(&self) -> String {
+        format!("Constant({:?})", self.value)
+    }
+}
+
+/// A `PasswordGenerator` that surrounds the seed password with a `left` and
+/// `right` string.
+pub struct Surround {
+    left: String,
+    right: String,
+}
+
+impl Surround {
+    pub fn new(left: &str, right: &str) -> Surround {
+        Surround {
+            left: left.to_string(),
+            right: right.to_string(),
+        }
+    }
+    /// Create a `Surround` object with the same string on both sides.
+    pub fn symmetric(value: &str) -> Surround {
+        Surround::new(value, value)
+    }
+}
+
+impl PasswordGenerator for Surround {
+    fn generate_with_seed(&self, _rng: &mut dyn RngCore, seed: String) -> String {
+        format!("{}{}{}", self.left, seed, self.right)
+    }
+
+    fn description(&self) -> String {
+        format!("Surround({:?}, {:?})", self.left, self.right)
+    }
+}
+
+/// A `PasswordGenerator` that surrounds the seed password with a pair of
+/// `braces` chosen at random.
+pub struct RandomSurround {
+    braces: Vec<(String, String)>,
+}
+
+impl RandomSurround {
+    /// Create a `RandomSurround` object from pairs of left and right braces.
+    pub fn new(braces: &[(&str, &str)]) -> RandomSurround {
+        let braces = braces
+            .iter()
+            .map(|(left, right)| (left.to_string(), right.to_string()))
+            .collect();
+        RandomSurround { braces }
+    }
+    /// Create a `RandomSurround` object that chooses between `()`, `[]`, `{}` and `<>`.
+    pub fn brackets() -> RandomSurround {
+        RandomSurround::new(&[("(", ")"), ("[", "]"), ("{", "}"), ("<", ">")])
+    }
+}
+
+impl PasswordGenerator for RandomSurround {
+    fn generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> String {
+        self.try_generate_with_seed(rng, seed)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> Result<String> {
+        let (left, right) = self.braces.choose(rng).ok_or(Error::EmptyVocabulary)?;
+        Ok(format!("{}{}{}", left, seed, right))
+    }
+
+    fn entropy(&self) -> f64 {
+        choice_entropy(&self.braces)
+    }
+
+    fn description(&self) -> String {
+        let braces: Vec<String> = self
+            .braces
+            .iter()
+            .map(|(left, right)| format!("{}{}", left, right))
+            .collect();
+        format!("RandomSurround({})", braces.join(" "))
+    }
 }
 
 /// An `Iterator` that allows for iterating over random passwords generated by
 /// a `PasswordGenerator`.
-pub struct PasswordIterator<'a> {
+pub struct PasswordIterator<'a, R: RngCore = ThreadRng> {
     generator: Box<dyn PasswordGenerator + 'a>,
-    rng: ThreadRng,
+    rng: R,
 }
 
-impl<'a> Iterator for PasswordIterator<'a> {
+impl<'a, R: RngCore> Iterator for PasswordIterator<'a, R> {
     type Item = String;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -119,20 +616,204 @@ impl<'a> Iterator for PasswordIterator<'a> {
     }
 }
 
+/// A `PasswordGenerator` that randomly chooses one of several `PasswordGenerator`
+/// objects, with probabilities proportional to their `weights`, to generate
+/// each password.
 pub struct Switch<'a> {
     generators: Vec<Box<dyn PasswordGenerator + 'a>>,
+    weights: Vec<f64>,
+    distribution: WeightedIndex<f64>,
+}
+
+impl<'a> Switch<'a> {
+    /// Create a `Switch` that chooses each generator with a probability
+    /// proportional to its weight.
+    ///
+    /// # Panics
+    /// Panics if `generators` is empty, or if any weight is negative or not
+    /// finite, or if all of the weights are zero. See `try_weighted`.
+    pub fn weighted(generators: Vec<(f64, Box<dyn PasswordGenerator + 'a>)>) -> Switch<'a> {
+        Switch::try_weighted(generators).unwrap_or_else(|e| panic!("{}", e))
+    }
+    /// Create a `Switch` that chooses each generator with a probability
+    /// proportional to its weight, or return `Error::InvalidWeights` if the
+    /// weights do not define a probability distribution.
+    pub fn try_weighted(
+        generators: Vec<(f64, Box<dyn PasswordGenerator + 'a>)>,
+    ) -> Result<Switch<'a>> {
+        let (weights, generators): (Vec<f64>, Vec<_>) = generators.into_iter().unzip();
+        if !weights.iter().all(|w| w.is_finite()) {
+            return Err(Error::InvalidWeights);
+        }
+        let distribution = WeightedIndex::new(&weights).map_err(|_| Error::InvalidWeights)?;
+
+        Ok(Switch {
+            generators,
+            weights,
+            distribution,
+        })
+    }
+    /// Create a new `Switch` that may also choose `generator` with the given
+    /// `weight`, or return `Error::InvalidWeights` if `weight` is negative or
+    /// not finite.
+    pub fn with_weight<T>(self, weight: f64, generator: T) -> Result<Switch<'a>>
+    where
+        T: PasswordGenerator + 'a,
+    {
+        let mut generators: Vec<(f64, Box<dyn PasswordGenerator + 'a>)> =
+            self.weights.into_iter().zip(self.generators).collect();
+        generators.push((weight, Box::new(generator)));
+        Switch::try_weighted(generators)
+    }
+    /// Create a new `Switch` that may also choose `other`.
+    ///
+    /// Unlike `PasswordGenerator::or`, this does not nest switches: `other` is
+    /// given the average weight of the existing generators, so a chain of `or`
+    /// calls chooses uniformly between all of its generators.
+    pub fn or<T>(self, other: T) -> Switch<'a>
+    where
+        T: PasswordGenerator + 'a,
+    {
+        // the average of valid weights is itself a valid weight
+        let weight = self.weights.iter().sum::<f64>() / self.weights.len() as f64;
+        self.with_weight(weight, other)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+    /// The probability with which each generator is chosen.
+    pub fn probabilities(&self) -> Vec<f64> {
+        let total: f64 = self.weights.iter().sum();
+        self.weights.iter().map(|w| w / total).collect()
+    }
+}
+
+/// Create a `Switch` that chooses uniformly between all of the `generators`.
+pub fn any_of<'a>(generators: Vec<Box<dyn PasswordGenerator + 'a>>) -> Switch<'a> {
+    Switch::weighted(generators.into_iter().map(|g| (1.0, g)).collect())
 }
 
 impl<'a> PasswordGenerator for Switch<'a> {
-    fn generate_with_seed(&self, rng: &mut ThreadRng, seed: String) -> String {
-        let selection = self.generators.choose(rng).unwrap();
+    fn generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> String {
+        let selection = &self.generators[self.distribution.sample(rng)];
         selection.generate_with_seed(rng, seed)
     }
+
+    fn try_generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> Result<String> {
+        let selection = &self.generators[self.distribution.sample(rng)];
+        selection.try_generate_with_seed(rng, seed)
+    }
+
+    /// The entropy of the choice of generator plus the expected entropy of the
+    /// chosen generator, assuming that the outputs of the generators are distinct.
+    fn entropy(&self) -> f64 {
+        self.probabilities()
+            .into_iter()
+            .zip(self.generators.iter())
+            .filter(|(p, _)| *p > 0.0)
+            .map(|(p, g)| p * (g.entropy() - p.log2()))
+            .sum()
+    }
+
+    /// As `entropy`, with the entropy of each generator given `seed`.
+    fn entropy_with_seed(&self, seed: &str) -> f64 {
+        self.probabilities()
+            .into_iter()
+            .zip(self.generators.iter())
+            .filter(|(p, _)| *p > 0.0)
+            .map(|(p, g)| p * (g.entropy_with_seed(seed) - p.log2()))
+            .sum()
+    }
+
+    fn entropy_depends_on_seed(&self) -> bool {
+        self.generators.iter().any(|g| g.entropy_depends_on_seed())
+    }
+
+    fn try_generate_candidate_with_seed(
+        &self,
+        rng: &mut dyn RngCore,
+        seed: String,
+    ) -> Result<String> {
+        let selection = &self.generators[self.distribution.sample(rng)];
+        selection.try_generate_candidate_with_seed(rng, seed)
+    }
+
+    fn description(&self) -> String {
+        let probabilities: Vec<String> = self
+            .probabilities()
+            .iter()
+            .map(|p| format!("{:.2}", p))
+            .collect();
+        format!("Switch(probabilities=[{}])", probabilities.join(", "))
+    }
+
+    fn children(&self) -> Vec<&dyn PasswordGenerator> {
+        self.generators.iter().map(|g| g.as_ref()).collect()
+    }
+
+    /// Records the choice of generator as a stage that passes its seed through,
+    /// followed by the stages of the chosen generator.
+    fn explain_with_seed(
+        &self,
+        rng: &mut dyn RngCore,
+        seed: String,
+        depth: usize,
+        stages: &mut Vec<Stage>,
+    ) -> Result<String> {
+        let index = self.distribution.sample(rng);
+        let probabilities = self.probabilities();
+        stages.push(Stage {
+            depth,
+            description: format!(
+                "{} chose option {} of {}",
+                self.description(),
+                index + 1,
+                self.generators.len()
+            ),
+            output: seed.clone(),
+            entropy: probabilities
+                .iter()
+                .filter(|p| **p > 0.0)
+                .map(|p| -p * p.log2())
+                .sum(),
+        });
+        self.generators[index].explain_with_seed(rng, seed, depth + 1, stages)
+    }
+}
+
+impl_fmt_with_description!(fmt::Display; ChainedGenerator<'_>, Constant, Surround, RandomSurround, Switch<'_>);
+impl_fmt_with_description!(fmt::Debug; ChainedGenerator<'_>, Constant, Surround, RandomSurround, Switch<'_>);
+
+/// Compute `log2` of the binomial coefficient `n` choose `k`.
+pub(crate) fn log2_binomial(n: usize, k: usize) -> f64 {
+    (0..k)
+        .map(|i| ((n - i) as f64).log2() - ((i + 1) as f64).log2())
+        .sum()
+}
+
+/// Compute the entropy, in bits, of a uniform random choice from `options`.
+///
+/// Repeated options are more likely to be chosen, so this is the Shannon entropy
+/// of the option frequencies, which is `log2(options.len())` when all options
+/// are unique.
+pub fn choice_entropy<T: Eq + Hash>(options: &[T]) -> f64 {
+    let mut counts: HashMap<&T, usize> = HashMap::new();
+    for option in options {
+        *counts.entry(option).or_insert(0) += 1;
+    }
+    let total = options.len() as f64;
+    counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
     #[test]
     fn test_constant_new() {
@@ -160,15 +841,252 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_constant_try_generate() {
+        let passwords = Constant::new("test input").pipe(Constant::new(" and more"));
+        assert_eq!(
+            passwords.try_generate(),
+            Ok("test input and more".to_string())
+        );
+    }
+
     #[test]
     fn test_constant_iterator() {
         let value = "test input".to_string();
         let passwords = Constant { value };
-        let mut iter = passwords.iterator().into_iter();
+        let mut iter = passwords.iterator();
         assert_eq!(iter.next(), Some("test input".to_string()));
         assert_eq!(iter.next(), Some("test input".to_string()));
     }
 
+    #[test]
+    fn test_constant_generate_with_rng() {
+        let passwords = Constant::new("test input");
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(passwords.generate_with_rng(&mut rng), "test input");
+    }
+
+    #[test]
+    fn test_iterator_with_rng_is_reproducible() {
+        let passwords = || {
+            Constant::new("a")
+                .or(Constant::new("b"))
+                .pipe(Constant::new("c").or(Constant::new("d")))
+        };
+        let first: Vec<String> = passwords()
+            .iterator_with_rng(StdRng::seed_from_u64(42))
+            .take(20)
+            .collect();
+        let second: Vec<String> = passwords()
+            .iterator_with_rng(StdRng::seed_from_u64(42))
+            .take(20)
+            .collect();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_choice_entropy() {
+        assert_eq!(choice_entropy(&["a", "b", "c", "d"]), 2.0);
+        assert_eq!(choice_entropy(&["a", "a", "b", "b"]), 1.0);
+        assert_eq!(choice_entropy(&["a"]), 0.0);
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(Constant::new("a").entropy(), 0.0);
+        assert_eq!(Constant::new("a").or(Constant::new("b")).entropy(), 1.0);
+        let passwords = Constant::new("a")
+            .or(Constant::new("b"))
+            .pipe(Constant::new("c").or(Constant::new("d")));
+        assert_eq!(passwords.entropy(), 2.0);
+    }
+
+    #[test]
+    fn test_pipeline_entropy_with_seed_dependent_stages() {
+        use crate::generators::case::RandomCase;
+        use crate::generators::defects::Defects;
+
+        let passwords = Constant::new("ab").pipe(RandomCase::Flip(0.5));
+        assert_eq!(RandomCase::Flip(0.5).entropy(), 0.0);
+        assert_eq!(passwords.entropy(), 2.0);
+        assert_eq!(passwords.entropy_with_seed("c"), 3.0);
+
+        let passwords = Constant::new("xa")
+            .or(Constant::new("xae"))
+            .pipe(Defects::with_vowels(1, 1));
+        assert!(
+            (passwords.entropy() - 3.5).abs() < 0.2,
+            "{}",
+            passwords.entropy()
+        );
+    }
+
+    #[test]
+    fn test_pipeline_entropy_does_not_run_filters() {
+        use crate::generators::case::RandomCase;
+        use crate::generators::filter::Filter;
+
+        let filter = Filter::new(Constant::new("ab"), "panics", |_: &str| -> bool {
+            panic!("the predicate should not be evaluated")
+        })
+        .without_acceptance_estimate();
+        let passwords = Constant::empty()
+            .pipe(filter)
+            .pipe(RandomCase::Flip(0.5))
+            .pipe(Constant::new("c"));
+        assert_eq!(passwords.entropy(), 2.0);
+    }
+
+    #[test]
+    fn test_switch_weighted() {
+        let passwords = Switch::weighted(vec![
+            (3.0, Box::new(Constant::new("a"))),
+            (1.0, Box::new(Constant::new("b"))),
+            (0.0, Box::new(Constant::new("c"))),
+        ]);
+        assert_eq!(passwords.probabilities(), vec![0.75, 0.25, 0.0]);
+
+        let samples: Vec<String> = passwords
+            .iterator_with_rng(StdRng::seed_from_u64(0))
+            .take(1000)
+            .collect();
+        let n_a = samples.iter().filter(|s| *s == "a").count();
+        assert!(n_a > 650 && n_a < 850);
+        assert!(samples.iter().all(|s| s != "c"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_switch_weighted_with_negative_weight() {
+        Switch::weighted(vec![(-1.0, Box::new(Constant::new("a")))]);
+    }
+
+    #[test]
+    fn test_switch_try_weighted() {
+        assert_eq!(
+            Switch::try_weighted(vec![]).err(),
+            Some(Error::InvalidWeights)
+        );
+        assert_eq!(
+            Switch::try_weighted(vec![(0.0, Box::new(Constant::new("a")))]).err(),
+            Some(Error::InvalidWeights)
+        );
+        assert_eq!(
+            Switch::try_weighted(vec![(f64::NAN, Box::new(Constant::new("a")))]).err(),
+            Some(Error::InvalidWeights)
+        );
+        assert!(Switch::try_weighted(vec![(1.0, Box::new(Constant::new("a")))]).is_ok());
+        assert_eq!(
+            Switch::try_weighted(vec![
+                (0.0, Box::new(Constant::new("a"))),
+                (0.0, Box::new(Constant::new("b"))),
+            ])
+            .err(),
+            Some(Error::InvalidWeights)
+        );
+    }
+
+    #[test]
+    fn test_switch_with_weight() {
+        let switch = || any_of(vec![Box::new(Constant::new("a"))]);
+        let passwords = switch().with_weight(3.0, Constant::new("b")).unwrap();
+        assert_eq!(passwords.probabilities(), vec![0.25, 0.75]);
+        assert!(switch().with_weight(0.0, Constant::new("b")).is_ok());
+        for weight in [-1.0, f64::NAN, f64::INFINITY].iter() {
+            assert_eq!(
+                switch().with_weight(*weight, Constant::new("b")).err(),
+                Some(Error::InvalidWeights)
+            );
+        }
+    }
+
+    #[test]
+    fn test_switch_or_is_flat() {
+        let passwords = Constant::new("a")
+            .or(Constant::new("b"))
+            .or(Constant::new("c"))
+            .or(Constant::new("d"));
+        assert_eq!(passwords.probabilities(), vec![0.25; 4]);
+        assert_eq!(passwords.entropy(), 2.0);
+    }
+
+    #[test]
+    fn test_switch_entropy() {
+        let passwords = any_of(vec![
+            Box::new(Constant::new("a")),
+            Box::new(Constant::new("b").or(Constant::new("c"))),
+        ]);
+        assert_eq!(passwords.entropy(), 1.5);
+
+        let passwords = Switch::weighted(vec![
+            (3.0, Box::new(Constant::new("a"))),
+            (1.0, Box::new(Constant::new("b"))),
+        ]);
+        let expected = -0.75 * 0.75f64.log2() - 0.25 * 0.25f64.log2();
+        assert!((passwords.entropy() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_surround() {
+        let passwords = Constant::new("abc").pipe(Surround::new("(", ")"));
+        assert_eq!(passwords.generate(), "(abc)");
+        assert_eq!(passwords.entropy(), 0.0);
+        assert_eq!(Surround::symmetric("**").generate(), "****");
+    }
+
+    #[test]
+    fn test_random_surround() {
+        let passwords = Constant::new("abc").pipe(RandomSurround::brackets());
+        for password in passwords.iterator().take(10) {
+            assert!(["(abc)", "[abc]", "{abc}", "<abc>"].contains(&password.as_str()));
+        }
+        assert_eq!(RandomSurround::brackets().entropy(), 2.0);
+        assert_eq!(
+            RandomSurround::new(&[("(", ")"), ("(", ")")]).entropy(),
+            0.0
+        );
+        assert_eq!(
+            RandomSurround::new(&[]).try_generate(),
+            Err(Error::EmptyVocabulary)
+        );
+    }
+
+    #[test]
+    fn test_describe() {
+        let passwords = Constant::new("a")
+            .pipe(Constant::new("b").pipe(Surround::new("(", ")")))
+            .pipe(Constant::new("c").or(Constant::new("d")));
+        assert_eq!(
+            passwords.describe(),
+            [
+                "Chain [1.0 bits]",
+                "  Constant(\"a\") [0.0 bits]",
+                "  Constant(\"b\") [0.0 bits]",
+                "  Surround(\"(\", \")\") [0.0 bits]",
+                "  Switch(probabilities=[0.50, 0.50]) [1.0 bits]",
+                "    Constant(\"d\") [0.0 bits]",
+                "    Constant(\"c\") [0.0 bits]",
+            ]
+            .join("\n")
+        );
+        assert_eq!(format!("{}", Constant::new("a")), "Constant(\"a\")");
+        assert_eq!(format!("{:?}", passwords), "Chain");
+    }
+
+    #[test]
+    fn test_explain() {
+        let passwords = Constant::new("a")
+            .pipe(Constant::new("b").or(Constant::new("b")))
+            .pipe(Surround::new("(", ")"));
+        let stages = passwords.explain(&mut rand::thread_rng()).unwrap();
+        let outputs: Vec<(usize, &str)> = stages
+            .iter()
+            .map(|stage| (stage.depth, stage.output.as_str()))
+            .collect();
+        assert_eq!(outputs, vec![(0, "a"), (0, "a"), (1, "ab"), (0, "(ab)")]);
+        assert_eq!(stages[1].entropy, 1.0);
+    }
+
     #[test]
     fn test_constant_pipe() {
         let passwords = Constant::new("test input").pipe(Constant::new(" and more"));
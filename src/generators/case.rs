@@ -1,12 +1,27 @@
-use inflector::Inflector;
-use rand::rngs::ThreadRng;
+use std::str::FromStr;
 
-use super::base::PasswordGenerator;
+use rand::seq::index::sample;
+use rand::{Rng, RngCore};
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::{Deserialize, Serialize};
+
+use super::base::{log2_binomial, PasswordGenerator};
+use crate::error::{Error, Result};
 
 /// A password generator object that will modify the case of a seed password string.
 ///
-/// This is a loose wrapper around functions provided by the `inflector` crate for
-/// converting between cases.
+/// The seed is split into words, which are runs of letters and digits. A word also
+/// ends where a lowercase letter is followed by an uppercase letter, so `whichCase`
+/// is two words. Whitespace between words is replaced by the separator of the case,
+/// e.g. `_` for `Case::Snake`, and every other character, such as a digit or symbol
+/// introduced by `Defects`, is kept in place.
+///
+/// Words are never singularized or pluralized, so unlike the `inflector` crate
+/// used by release 0.2.0, `Case::Class` keeps the last word as it is, and
+/// `Case::Table`, which was snake case with the last word pluralized, is the
+/// same as `Case::Snake`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Case {
     Camel,
     Class,
@@ -15,29 +30,240 @@ pub enum Case {
     Screaming,
     Sentence,
     Snake,
+    /// The same as `Case::Snake`, as words are not pluralized.
+    #[deprecated(note = "words are not pluralized, so this is the same as `Case::Snake`")]
     Table,
     Title,
     Upper,
 }
 
+/// The way in which a `Case` changes each word.
+#[derive(Clone, Copy)]
+enum WordCase {
+    Lower,
+    Upper,
+    Capitalized,
+}
+
+/// A piece of a seed password string.
+enum Token {
+    Word(String),
+    Space,
+    Other(char),
+}
+
+/// Split `seed` into words, runs of whitespace, and other characters.
+fn tokenize(seed: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut previous: Option<char> = None;
+    for c in seed.chars() {
+        if c.is_alphanumeric() {
+            let continues_word = previous
+                .is_some_and(|p| p.is_alphanumeric() && !(p.is_lowercase() && c.is_uppercase()));
+            match tokens.last_mut() {
+                Some(Token::Word(word)) if continues_word => word.push(c),
+                _ => tokens.push(Token::Word(c.to_string())),
+            }
+        } else if c.is_whitespace() {
+            if !matches!(tokens.last(), Some(Token::Space)) {
+                tokens.push(Token::Space);
+            }
+        } else {
+            tokens.push(Token::Other(c));
+        }
+        previous = Some(c);
+    }
+    tokens
+}
+
+fn convert_word(word: &str, case: WordCase) -> String {
+    match case {
+        WordCase::Lower => word.to_lowercase(),
+        WordCase::Upper => word.to_uppercase(),
+        WordCase::Capitalized => {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first
+                    .to_uppercase()
+                    .chain(chars.flat_map(char::to_lowercase))
+                    .collect(),
+                None => String::new(),
+            }
+        }
+    }
+}
+
+/// Convert every word of `seed` with `first` for the first word and `rest` for the
+/// others, separating words that are adjacent or separated by whitespace with `separator`.
+fn convert(seed: &str, first: WordCase, rest: WordCase, separator: &str) -> String {
+    let tokens = tokenize(seed);
+    let mut output = String::new();
+    let mut n_words = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Word(word) => {
+                if i > 0 && matches!(tokens[i - 1], Token::Word(_)) {
+                    output.push_str(separator);
+                }
+                let case = if n_words == 0 { first } else { rest };
+                output.push_str(&convert_word(word, case));
+                n_words += 1;
+            }
+            Token::Space if i > 0 && i + 1 < tokens.len() => output.push_str(separator),
+            Token::Space => {}
+            Token::Other(c) => output.push(*c),
+        }
+    }
+    output
+}
+
+impl FromStr for Case {
+    type Err = Error;
+
+    /// Parse the lowercase name of a case, e.g. `class`, as it is written in a
+    /// pipeline specification.
+    fn from_str(name: &str) -> Result<Case> {
+        Case::deserialize(StrDeserializer::<ValueError>::new(name))
+            .map_err(|_| Error::InvalidSpec(format!("unknown case '{}'", name)))
+    }
+}
+
 impl PasswordGenerator for Case {
-    fn generate_with_seed(&self, _rng: &mut ThreadRng, seed: String) -> String {
+    fn generate_with_seed(&self, _rng: &mut dyn RngCore, seed: String) -> String {
         match self {
-            Case::Camel => seed.to_camel_case(),
-            Case::Class => seed.to_class_case(),
-            Case::Kebab => seed.to_kebab_case(),
+            Case::Camel => convert(&seed, WordCase::Lower, WordCase::Capitalized, ""),
+            Case::Class => convert(&seed, WordCase::Capitalized, WordCase::Capitalized, ""),
+            Case::Kebab => convert(&seed, WordCase::Lower, WordCase::Lower, "-"),
             Case::Lower => seed.to_lowercase(),
-            Case::Screaming => seed.to_screaming_snake_case(),
-            Case::Sentence => seed.to_sentence_case(),
-            Case::Snake => seed.to_snake_case(),
-            Case::Table => seed.to_table_case(),
-            Case::Title => seed.to_title_case(),
+            Case::Screaming => convert(&seed, WordCase::Upper, WordCase::Upper, "_"),
+            Case::Sentence => convert(&seed, WordCase::Capitalized, WordCase::Lower, " "),
+            #[allow(deprecated)]
+            Case::Snake | Case::Table => convert(&seed, WordCase::Lower, WordCase::Lower, "_"),
+            Case::Title => convert(&seed, WordCase::Capitalized, WordCase::Capitalized, " "),
             Case::Upper => seed.to_uppercase(),
         }
-        .to_string()
     }
+
+    fn description(&self) -> String {
+        format!("Case::{:?}", self)
+    }
+}
+
+/// A password generator object that will randomly modify the case of the letters
+/// of a seed password string, leaving digits, symbols and separators in place.
+///
+/// The entropy of a `RandomCase` depends on the letters of the seed, so its
+/// `entropy` is zero and `entropy_with_seed` should be used when the seed is known.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RandomCase {
+    /// Flip the case of each letter with probability `p`.
+    Flip(f64),
+    /// Capitalize each word, a run of letters, with probability one half.
+    Words,
+    /// Upper-case exactly `k` randomly chosen lowercase letters, or every
+    /// lowercase letter if there are fewer than `k`.
+    Letters(usize),
+}
+
+/// Binary entropy, in bits, of an event with probability `p`.
+fn binary_entropy(p: f64) -> f64 {
+    if p <= 0.0 || p >= 1.0 {
+        return 0.0;
+    }
+    -p * p.log2() - (1.0 - p) * (1.0 - p).log2()
+}
+
+/// Flip the case of the letter `c`.
+fn flip_case(c: char) -> String {
+    if c.is_lowercase() {
+        c.to_uppercase().collect()
+    } else {
+        c.to_lowercase().collect()
+    }
+}
+
+/// Find the positions of the first letter of each run of letters in `chars`.
+fn word_starts(chars: &[char]) -> Vec<usize> {
+    (0..chars.len())
+        .filter(|&i| chars[i].is_alphabetic() && (i == 0 || !chars[i - 1].is_alphabetic()))
+        .collect()
 }
 
+impl PasswordGenerator for RandomCase {
+    fn generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> String {
+        self.try_generate_with_seed(rng, seed)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> Result<String> {
+        let chars: Vec<char> = seed.chars().collect();
+        let mut output: Vec<String> = chars.iter().map(|c| c.to_string()).collect();
+
+        match *self {
+            RandomCase::Flip(p) => {
+                if !(0.0..=1.0).contains(&p) {
+                    return Err(Error::InvalidProbability(p));
+                }
+                for (i, c) in chars.iter().enumerate() {
+                    if (c.is_lowercase() || c.is_uppercase()) && rng.gen_bool(p) {
+                        output[i] = flip_case(*c);
+                    }
+                }
+            }
+            RandomCase::Words => {
+                for i in word_starts(&chars) {
+                    if chars[i].is_lowercase() && rng.gen_bool(0.5) {
+                        output[i] = chars[i].to_uppercase().collect();
+                    }
+                }
+            }
+            RandomCase::Letters(k) => {
+                let lowercase: Vec<usize> = (0..chars.len())
+                    .filter(|&i| chars[i].is_lowercase())
+                    .collect();
+                let k = std::cmp::min(k, lowercase.len());
+                for j in sample(rng, lowercase.len(), k) {
+                    let i = lowercase[j];
+                    output[i] = chars[i].to_uppercase().collect();
+                }
+            }
+        }
+        Ok(output.concat())
+    }
+
+    fn entropy_depends_on_seed(&self) -> bool {
+        true
+    }
+
+    fn entropy_with_seed(&self, seed: &str) -> f64 {
+        let chars: Vec<char> = seed.chars().collect();
+        match *self {
+            RandomCase::Flip(p) => {
+                let n_cased = chars
+                    .iter()
+                    .filter(|c| c.is_lowercase() || c.is_uppercase())
+                    .count();
+                n_cased as f64 * binary_entropy(p)
+            }
+            RandomCase::Words => word_starts(&chars)
+                .into_iter()
+                .filter(|&i| chars[i].is_lowercase())
+                .count() as f64,
+            RandomCase::Letters(k) => {
+                let n_lowercase = chars.iter().filter(|c| c.is_lowercase()).count();
+                log2_binomial(n_lowercase, std::cmp::min(k, n_lowercase))
+            }
+        }
+    }
+
+    fn description(&self) -> String {
+        format!("RandomCase::{:?}", self)
+    }
+}
+
+impl_fmt_with_description!(std::fmt::Display; Case, RandomCase);
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -53,12 +279,12 @@ mod test {
         let seed = "which case is this";
         let case_outputs = [
             (Case::Camel, "whichCaseIsThis"),
-            // (Case::Class, "WhichCaseIsThis"),
+            (Case::Class, "WhichCaseIsThis"),
             (Case::Kebab, "which-case-is-this"),
             (Case::Lower, "which case is this"),
             (Case::Screaming, "WHICH_CASE_IS_THIS"),
+            (Case::Sentence, "Which case is this"),
             (Case::Snake, "which_case_is_this"),
-            (Case::Table, "which_case_is_this"),
             (Case::Title, "Which Case Is This"),
             (Case::Upper, "WHICH CASE IS THIS"),
         ];
@@ -67,4 +293,104 @@ mod test {
             assert_eq!(case.generate_with_seed(&mut rng, seed.to_string()), *output);
         }
     }
+
+    #[test]
+    fn test_case_from_str() {
+        assert_eq!("class".parse::<Case>(), Ok(Case::Class));
+        assert_eq!("screaming".parse::<Case>(), Ok(Case::Screaming));
+        #[allow(deprecated)]
+        let table = Case::Table;
+        assert_eq!("table".parse::<Case>(), Ok(table));
+        assert_eq!(
+            table.generate_with_seed(&mut rand::thread_rng(), "which case is this".to_string()),
+            "which_case_is_this"
+        );
+        assert!(matches!(
+            "Class".parse::<Case>(),
+            Err(Error::InvalidSpec(_))
+        ));
+    }
+
+    #[test]
+    fn test_case_preserves_digits_and_symbols() {
+        let mut rng = rand::thread_rng();
+        let seed = "correct7horse! battery st4ple 42";
+        let case_outputs = [
+            (Case::Camel, "correct7horse!BatterySt4ple42"),
+            (Case::Class, "Correct7horse!BatterySt4ple42"),
+            (Case::Kebab, "correct7horse!-battery-st4ple-42"),
+            (Case::Snake, "correct7horse!_battery_st4ple_42"),
+            (Case::Title, "Correct7horse! Battery St4ple 42"),
+        ];
+
+        for (case, output) in case_outputs.iter() {
+            assert_eq!(case.generate_with_seed(&mut rng, seed.to_string()), *output);
+        }
+    }
+
+    #[test]
+    fn test_case_word_boundaries() {
+        let mut rng = rand::thread_rng();
+        let case_outputs = [
+            (Case::Snake, "  whichCase is--this  ", "which_case_is--this"),
+            (Case::Class, "WhichCaseIsThis", "WhichCaseIsThis"),
+            (Case::Camel, "which_case", "which_Case"),
+            (Case::Class, "élan vital", "ÉlanVital"),
+            (Case::Screaming, "straße", "STRASSE"),
+        ];
+
+        for (case, seed, output) in case_outputs.iter() {
+            assert_eq!(case.generate_with_seed(&mut rng, seed.to_string()), *output);
+        }
+    }
+
+    #[test]
+    fn test_random_case_flip() {
+        let mut rng = rand::thread_rng();
+        let seed = "ab-12 Cd";
+        assert_eq!(
+            RandomCase::Flip(0.0).generate_with_seed(&mut rng, seed.to_string()),
+            seed
+        );
+        assert_eq!(
+            RandomCase::Flip(1.0).generate_with_seed(&mut rng, seed.to_string()),
+            "AB-12 cD"
+        );
+        assert_eq!(RandomCase::Flip(0.5).entropy_with_seed(seed), 4.0);
+        assert_eq!(RandomCase::Flip(0.5).entropy(), 0.0);
+        assert_eq!(
+            RandomCase::Flip(1.5).try_generate_with_seed(&mut rng, seed.to_string()),
+            Err(Error::InvalidProbability(1.5))
+        );
+    }
+
+    #[test]
+    fn test_random_case_words() {
+        let mut rng = rand::thread_rng();
+        let seed = "correct7horse!battery Staple";
+        assert_eq!(RandomCase::Words.entropy_with_seed(seed), 3.0);
+
+        for _ in 0..10 {
+            let password = RandomCase::Words.generate_with_seed(&mut rng, seed.to_string());
+            assert_eq!(password.to_lowercase(), seed.to_lowercase());
+            assert!(password.contains("7") && password.contains("!") && password.contains(" S"));
+        }
+    }
+
+    #[test]
+    fn test_random_case_letters() {
+        let mut rng = rand::thread_rng();
+        let seed = "h3ll0 w0rld";
+        for _ in 0..10 {
+            let password = RandomCase::Letters(2).generate_with_seed(&mut rng, seed.to_string());
+            assert_eq!(password.to_lowercase(), seed);
+            assert_eq!(password.chars().filter(|c| c.is_uppercase()).count(), 2);
+        }
+        assert_eq!(
+            RandomCase::Letters(10).generate_with_seed(&mut rng, "ab1".to_string()),
+            "AB1"
+        );
+        assert_eq!(RandomCase::Letters(1).entropy_with_seed("abcd"), 2.0);
+        assert_eq!(RandomCase::Letters(10).entropy_with_seed("abcd"), 0.0);
+    }
 }
@@ -1,16 +1,35 @@
 use std::cmp::min;
-use std::collections::HashMap;
-use std::string::ToString;
+use std::collections::BTreeMap;
 
-use rand::rngs::ThreadRng;
 use rand::seq::SliceRandom;
-use rand::Rng;
+use rand::{Rng, RngCore};
 
-use super::base::PasswordGenerator;
+use super::base::{log2_binomial, PasswordGenerator};
+use crate::error::{check_range, Result};
+use crate::version::AlgorithmVersion;
+
+/// Letters, and the numbers or symbols that look similar to them.
+static SYMBOLS: [(&str, &str); 15] = [
+    ("A", "4"),
+    ("OoQ", "0"),
+    ("E", "3"),
+    ("LlIJ", "1"),
+    ("ij", "!:;"),
+    ("Ss", "$5"),
+    ("Zz", "2"),
+    ("LVv", "7^"),
+    ("a", "@"),
+    ("N", r"\%"),
+    ("B", r"8\%&"),
+    ("Ppq", "9"),
+    ("bd", "6&"),
+    ("XxfF", "+"),
+    ("H", "#"),
+];
 
 /// A `PasswordGenerator` object that will apply defects to an input `seed` string.
 pub struct Defects {
-    defects: HashMap<char, Vec<char>>,
+    defects: BTreeMap<char, Vec<char>>,
     min_defects: usize,
     max_defects: usize,
 }
@@ -20,34 +39,25 @@ impl Defects {
     /// defects to an input seed string by replacing letters with numbers or symbols
     /// that look similar to those letters.
     pub fn with_symbols(min_defects: usize, max_defects: usize) -> Defects {
-        let defects: HashMap<char, Vec<char>> = [
-            ("A", "4"),
-            ("OoQ", "0"),
-            ("E", "3"),
-            ("LlIJ", "1"),
-            ("ij", "!:;"),
-            ("Ss", "$5"),
-            ("Zz", "2"),
-            ("LVv", "7^"),
-            ("a", "@"),
-            ("N", r"\%"),
-            ("B", r"8\%&"),
-            ("Ppq", "9"),
-            ("bd", "6&"),
-            ("XxfF", "+"),
-            ("H", "#"),
-        ]
-        .iter()
-        .flat_map(|(s, t): &(&str, &str)| -> Vec<(char, Vec<char>)> {
-            s.chars()
-                .into_iter()
-                .map(|c| {
-                    let t_vec: Vec<char> = t.to_string().chars().collect();
-                    (c, t_vec)
-                })
-                .collect()
-        })
-        .collect();
+        Defects::with_symbols_version(min_defects, max_defects, AlgorithmVersion::LATEST)
+    }
+    /// Create a `Defects` object as with `with_symbols`, using the replacement
+    /// table of an `AlgorithmVersion`.
+    pub fn with_symbols_version(
+        min_defects: usize,
+        max_defects: usize,
+        version: AlgorithmVersion,
+    ) -> Defects {
+        let mut defects: BTreeMap<char, Vec<char>> = BTreeMap::new();
+        for (letters, replacements) in SYMBOLS.iter() {
+            for c in letters.chars() {
+                let options = defects.entry(c).or_default();
+                if version == AlgorithmVersion::V1 {
+                    options.clear();
+                }
+                options.extend(replacements.chars());
+            }
+        }
 
         Defects {
             defects,
@@ -60,7 +70,7 @@ impl Defects {
     pub fn with_vowels(min_defects: usize, max_defects: usize) -> Defects {
         let vowels = vec!['a', 'e', 'i', 'o', 'u'];
 
-        let defects: HashMap<char, Vec<char>> = vowels
+        let defects: BTreeMap<char, Vec<char>> = vowels
             .iter()
             .map(|v| {
                 let vowels_less_v = vowels.clone().into_iter().filter(|c| c != v).collect();
@@ -74,11 +84,34 @@ impl Defects {
             max_defects,
         }
     }
+    /// The letters that may be replaced, and their replacements.
+    pub(crate) fn replacements(&self) -> &BTreeMap<char, Vec<char>> {
+        &self.defects
+    }
+    /// Create a `Defects` object as with `with_symbols`, or return
+    /// `Error::InvalidRange` if `min_defects` is larger than `max_defects`.
+    pub fn try_with_symbols(min_defects: usize, max_defects: usize) -> Result<Defects> {
+        check_range(min_defects, max_defects)?;
+        Ok(Defects::with_symbols(min_defects, max_defects))
+    }
+    /// Create a `Defects` object as with `with_vowels`, or return
+    /// `Error::InvalidRange` if `min_defects` is larger than `max_defects`.
+    pub fn try_with_vowels(min_defects: usize, max_defects: usize) -> Result<Defects> {
+        check_range(min_defects, max_defects)?;
+        Ok(Defects::with_vowels(min_defects, max_defects))
+    }
 }
 
 impl PasswordGenerator for Defects {
-    fn generate_with_seed(&self, rng: &mut ThreadRng, seed: String) -> String {
-        let chars: Vec<char> = seed.chars().into_iter().collect();
+    fn generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> String {
+        self.try_generate_with_seed(rng, seed)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> Result<String> {
+        check_range(self.min_defects, self.max_defects)?;
+
+        let chars: Vec<char> = seed.chars().collect();
 
         let mut possible_defect_locations: Vec<usize> = chars
             .iter()
@@ -98,7 +131,7 @@ impl PasswordGenerator for Defects {
 
         let defect_locations = &possible_defect_locations[0..n_defects];
 
-        chars
+        Ok(chars
             .into_iter()
             .enumerate()
             .map(|(i, c): (usize, char)| -> char {
@@ -109,13 +142,71 @@ impl PasswordGenerator for Defects {
                     c
                 }
             })
-            .collect()
+            .collect())
+    }
+
+    /// An estimate of the entropy that does not depend on the seed: the entropy
+    /// of the defects applied to a seed that contains each replaceable letter
+    /// once, including the choice of their positions.
+    fn entropy(&self) -> f64 {
+        let seed: String = self.defects.keys().collect();
+        self.entropy_with_seed(&seed)
+    }
+
+    fn entropy_depends_on_seed(&self) -> bool {
+        true
+    }
+
+    /// Compute the entropy, in bits, of the defects applied to a particular `seed`.
+    ///
+    /// This accounts for the choice of the number of defects, the choice of their
+    /// positions among the candidate letters in `seed`, and the choice of their
+    /// replacements.
+    fn entropy_with_seed(&self, seed: &str) -> f64 {
+        let options: Vec<f64> = seed
+            .chars()
+            .filter_map(|c| self.defects.get(&c))
+            .map(|options| (options.len() as f64).log2())
+            .collect();
+
+        let n_possible = options.len();
+        let n_min = min(n_possible, self.min_defects);
+        let n_max = min(n_possible, self.max_defects);
+        if n_possible == 0 || n_min > n_max {
+            return 0.0;
+        }
+
+        // Every candidate is equally likely to be chosen, so on average
+        // a fraction `k / n_possible` of the replacement entropy is realized.
+        let replacement_entropy: f64 = options.iter().sum::<f64>() / n_possible as f64;
+        let n_choices = (n_max - n_min + 1) as f64;
+        let average: f64 = (n_min..=n_max)
+            .map(|k| log2_binomial(n_possible, k) + k as f64 * replacement_entropy)
+            .sum::<f64>()
+            / n_choices;
+
+        n_choices.log2() + average
+    }
+
+    fn description(&self) -> String {
+        let mut letters: Vec<char> = self.defects.keys().cloned().collect();
+        letters.sort_unstable();
+        format!(
+            "Defects(defects={}..={}, letters={:?})",
+            self.min_defects,
+            self.max_defects,
+            letters.into_iter().collect::<String>()
+        )
     }
 }
 
+impl_fmt_with_description!(std::fmt::Display; Defects);
+impl_fmt_with_description!(std::fmt::Debug; Defects);
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::error::Error;
 
     #[test]
     fn test_defects_with_symbols() {
@@ -140,6 +231,60 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_defects_with_symbols_version() {
+        let options = |version| {
+            Defects::with_symbols_version(1, 1, version)
+                .defects
+                .get(&'L')
+                .cloned()
+        };
+        assert_eq!(options(AlgorithmVersion::V1), Some(vec!['7', '^']));
+        assert_eq!(options(AlgorithmVersion::V2), Some(vec!['1', '7', '^']));
+    }
+
+    #[test]
+    fn test_defects_try_with_invalid_range() {
+        let invalid_range = Some(Error::InvalidRange { min: 2, max: 1 });
+        assert_eq!(Defects::try_with_symbols(2, 1).err(), invalid_range);
+        assert_eq!(Defects::try_with_vowels(2, 1).err(), invalid_range);
+        assert_eq!(
+            Defects::with_vowels(2, 1).try_generate_with_seed(&mut rand::thread_rng(), "a".into()),
+            Err(Error::InvalidRange { min: 2, max: 1 })
+        );
+    }
+
+    #[test]
+    fn test_defects_entropy() {
+        let passwords = Defects::with_symbols(1, 1);
+        assert!(passwords.entropy() > 5.0, "{}", passwords.entropy());
+        assert_eq!(Defects::with_vowels(1, 1).entropy(), 5f64.log2() + 2.0);
+        let expected = 1.0 + (2.0 * 10f64.log2() + 4.0 + 3.0 * 2.0) / 2.0;
+        assert!((Defects::with_vowels(2, 3).entropy() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_defects_entropy_with_seed() {
+        let passwords = Defects::with_vowels(1, 1);
+        assert_eq!(passwords.entropy_with_seed("xyz"), 0.0);
+        assert_eq!(passwords.entropy_with_seed("a"), 2.0);
+        assert_eq!(passwords.entropy_with_seed("abbe"), 3.0);
+
+        let passwords = Defects::with_vowels(0, 1);
+        assert_eq!(passwords.entropy_with_seed("a"), 2.0);
+
+        let passwords = Defects::with_symbols(1, 1);
+        assert_eq!(passwords.entropy_with_seed("AEQJ"), 2.0);
+    }
+
+    #[test]
+    fn test_defects_description() {
+        assert_eq!(
+            Defects::with_vowels(1, 2).to_string(),
+            "Defects(defects=1..=2, letters=\"aeiou\")"
+        );
+    }
+
     #[test]
     fn test_defects_with_vowels_with_replacement() {
         let passwords = Defects::with_vowels(1, 1);
@@ -0,0 +1,278 @@
+use std::cell::OnceCell;
+
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+use super::base::{PasswordGenerator, Stage};
+use crate::error::{Error, Result};
+
+/// The number of samples used to estimate the fraction of passwords that pass a
+/// filter or satisfy a policy.
+const ACCEPTANCE_SAMPLES: usize = 256;
+
+/// An estimate of the fraction of candidate passwords that a `Filter` or a
+/// `WithPolicy` accepts, and of the fraction of the entropy of the candidates
+/// that the accepted passwords keep, which is computed once.
+#[derive(Default)]
+pub(crate) struct AcceptanceRate(OnceCell<(f64, f64)>);
+
+impl AcceptanceRate {
+    /// The entropy of `generator` reduced by the information revealed by `accept`,
+    /// `-log2` of the estimated fraction of its passwords that are accepted.
+    ///
+    /// `accept` returns `None` for a rejected candidate, and otherwise the
+    /// fraction of its entropy that the accepted password keeps, which is less
+    /// than one if the candidate is changed, e.g. truncated by a repair. The
+    /// entropy of `generator` is scaled by the average of the fractions. The
+    /// estimate uses a fixed random seed so that it is reproducible, and the
+    /// random number generator is also passed to `accept`, e.g. to repair passwords.
+    pub(crate) fn entropy<F>(&self, generator: &dyn PasswordGenerator, mut accept: F) -> f64
+    where
+        F: FnMut(&mut dyn RngCore, String) -> Option<f64>,
+    {
+        let (acceptance_rate, kept) = *self.0.get_or_init(|| {
+            let mut rng = StdRng::seed_from_u64(0);
+            let kept: Vec<f64> = (0..ACCEPTANCE_SAMPLES)
+                .filter_map(|_| match generator.try_generate_with_rng(&mut rng) {
+                    Ok(password) => accept(&mut rng, password),
+                    Err(_) => None,
+                })
+                .collect();
+            let mean_kept = kept.iter().sum::<f64>() / kept.len().max(1) as f64;
+            (kept.len() as f64 / ACCEPTANCE_SAMPLES as f64, mean_kept)
+        });
+        if acceptance_rate == 0.0 {
+            return 0.0;
+        }
+        (kept * generator.entropy() + acceptance_rate.log2()).max(0.0)
+    }
+}
+
+/// Call `attempt` up to `max_attempts` times and return the first password that
+/// it accepts, or the error made by `rejected` if it accepts none. Errors of
+/// `attempt` are returned immediately.
+pub(crate) fn retry<F, E>(max_attempts: usize, mut attempt: F, rejected: E) -> Result<String>
+where
+    F: FnMut() -> Result<Option<String>>,
+    E: FnOnce() -> Error,
+{
+    for _ in 0..max_attempts {
+        if let Some(password) = attempt()? {
+            return Ok(password);
+        }
+    }
+    Err(rejected())
+}
+
+/// A predicate on passwords that can fail.
+type Predicate<'a> = Box<dyn Fn(&str) -> Result<bool> + 'a>;
+
+/// A `PasswordGenerator` that regenerates the passwords of another
+/// `PasswordGenerator` until one is accepted by a predicate, e.g. a minimum
+/// strength or not appearing in a breach corpus.
+pub struct Filter<'a> {
+    generator: Box<dyn PasswordGenerator + 'a>,
+    name: String,
+    predicate: Predicate<'a>,
+    max_attempts: usize,
+    estimate_acceptance: bool,
+    acceptance_rate: AcceptanceRate,
+}
+
+impl<'a> Filter<'a> {
+    /// Create a `Filter` object named `name` that makes up to 100 attempts to
+    /// generate a password from `generator` that `predicate` accepts.
+    pub fn new<T, F>(generator: T, name: &str, predicate: F) -> Filter<'a>
+    where
+        T: PasswordGenerator + 'a,
+        F: Fn(&str) -> bool + 'a,
+    {
+        Filter::try_new(generator, name, move |password: &str| {
+            Ok(predicate(password))
+        })
+    }
+    /// Create a `Filter` object like `new` with a `predicate` that can fail, e.g.
+    /// a lookup in a breach corpus. Errors of the predicate are returned by
+    /// `try_generate_with_seed`.
+    pub fn try_new<T, F>(generator: T, name: &str, predicate: F) -> Filter<'a>
+    where
+        T: PasswordGenerator + 'a,
+        F: Fn(&str) -> Result<bool> + 'a,
+    {
+        Filter {
+            generator: Box::new(generator),
+            name: name.to_string(),
+            predicate: Box::new(predicate),
+            max_attempts: 100,
+            estimate_acceptance: true,
+            acceptance_rate: AcceptanceRate::default(),
+        }
+    }
+    /// Create a new `Filter` object that gives up after `max_attempts` attempts.
+    pub fn with_max_attempts(self, max_attempts: usize) -> Filter<'a> {
+        Filter {
+            max_attempts,
+            ..self
+        }
+    }
+    /// Create a new `Filter` object whose entropy is that of its generator,
+    /// rather than being reduced by an estimate of the fraction of passwords that
+    /// pass. This avoids evaluating the predicate while estimating entropy, for
+    /// predicates that are expensive or have side effects, such as a network request.
+    pub fn without_acceptance_estimate(self) -> Filter<'a> {
+        Filter {
+            estimate_acceptance: false,
+            ..self
+        }
+    }
+    /// Whether `predicate` accepts `password`.
+    pub fn accepts(&self, password: &str) -> Result<bool> {
+        (self.predicate)(password)
+    }
+    /// The error returned when no attempt passes the filter.
+    fn rejected(&self) -> Error {
+        Error::Rejected {
+            filter: self.name.clone(),
+            attempts: self.max_attempts,
+        }
+    }
+}
+
+impl<'a> PasswordGenerator for Filter<'a> {
+    fn generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> String {
+        self.try_generate_with_seed(rng, seed)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> Result<String> {
+        retry(
+            self.max_attempts,
+            || {
+                let password = self.generator.try_generate_with_seed(rng, seed.clone())?;
+                Ok(if self.accepts(&password)? {
+                    Some(password)
+                } else {
+                    None
+                })
+            },
+            || self.rejected(),
+        )
+    }
+
+    /// The entropy of the underlying generator reduced by the information revealed
+    /// by the filter, `-log2` of the estimated fraction of passwords that pass it.
+    fn entropy(&self) -> f64 {
+        if !self.estimate_acceptance {
+            return self.generator.entropy();
+        }
+        self.acceptance_rate
+            .entropy(self.generator.as_ref(), |_, password| {
+                Some(1.0).filter(|_| self.accepts(&password).unwrap_or(false))
+            })
+    }
+
+    fn description(&self) -> String {
+        format!("Filter({}, max_attempts={})", self.name, self.max_attempts)
+    }
+
+    fn children(&self) -> Vec<&dyn PasswordGenerator> {
+        vec![self.generator.as_ref()]
+    }
+
+    /// A candidate of the underlying generator, without evaluating the predicate.
+    fn try_generate_candidate_with_seed(
+        &self,
+        rng: &mut dyn RngCore,
+        seed: String,
+    ) -> Result<String> {
+        self.generator.try_generate_candidate_with_seed(rng, seed)
+    }
+
+    /// Records the stages of the attempt that passed the filter, followed by a
+    /// stage for the filter itself.
+    fn explain_with_seed(
+        &self,
+        rng: &mut dyn RngCore,
+        seed: String,
+        depth: usize,
+        stages: &mut Vec<Stage>,
+    ) -> Result<String> {
+        retry(
+            self.max_attempts,
+            || {
+                let mut attempt = Vec::new();
+                let password =
+                    self.generator
+                        .explain_with_seed(rng, seed.clone(), depth + 1, &mut attempt)?;
+                if !self.accepts(&password)? {
+                    return Ok(None);
+                }
+                stages.extend(attempt);
+                stages.push(Stage {
+                    depth,
+                    description: self.description(),
+                    output: password.clone(),
+                    entropy: 0.0,
+                });
+                Ok(Some(password))
+            },
+            || self.rejected(),
+        )
+    }
+}
+
+impl_fmt_with_description!(std::fmt::Display; Filter<'_>);
+impl_fmt_with_description!(std::fmt::Debug; Filter<'_>);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::generators::base::Constant;
+    use crate::generators::random_string::RandomString;
+
+    #[test]
+    fn test_filter_rejection() {
+        let passwords = Filter::new(RandomString::digits(1), "odd", |password: &str| {
+            password.parse::<u32>().unwrap() % 2 == 1
+        });
+        for _ in 0..10 {
+            let digit: u32 = passwords.generate().parse().unwrap();
+            assert_eq!(digit % 2, 1);
+        }
+        assert!((passwords.entropy() - 10f64.log2() + 1.0).abs() < 0.2);
+    }
+
+    #[test]
+    fn test_filter_gives_up() {
+        let passwords =
+            Filter::new(Constant::new("a"), "never", |_: &str| false).with_max_attempts(3);
+        assert_eq!(
+            passwords.try_generate(),
+            Err(Error::Rejected {
+                filter: "never".to_string(),
+                attempts: 3
+            })
+        );
+        assert_eq!(passwords.entropy(), 0.0);
+    }
+
+    #[test]
+    fn test_filter_predicate_error() {
+        let passwords = Filter::try_new(Constant::new("a"), "fails", |_: &str| {
+            Err(Error::Breach("unavailable".to_string()))
+        });
+        assert_eq!(
+            passwords.try_generate(),
+            Err(Error::Breach("unavailable".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_filter_without_acceptance_estimate() {
+        let passwords = Filter::new(RandomString::digits(2), "panics", |_: &str| -> bool {
+            panic!("the predicate should not be evaluated")
+        })
+        .without_acceptance_estimate();
+        assert_eq!(passwords.entropy(), RandomString::digits(2).entropy());
+    }
+}
@@ -1,5 +1,22 @@
+/// Implement a formatting trait, such as `std::fmt::Display`, for `PasswordGenerator`
+/// types by writing their `description`.
+macro_rules! impl_fmt_with_description {
+    ($fmt:path; $($t:ty),*) => {
+        $(
+            impl $fmt for $t {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    f.write_str(&crate::generators::base::PasswordGenerator::description(self))
+                }
+            }
+        )*
+    };
+}
+
 pub mod base;
 pub mod case;
 pub mod defects;
+pub mod filter;
 pub mod phrase;
+pub mod policy;
 pub mod random_string;
+pub mod separator;
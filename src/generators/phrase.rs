@@ -1,7 +1,13 @@
-use rand::rngs::ThreadRng;
+use std::cell::OnceCell;
+use std::collections::HashSet;
+
 use rand::seq::SliceRandom;
+use rand::RngCore;
 
-use super::base::PasswordGenerator;
+use super::base::{choice_entropy, PasswordGenerator};
+use super::separator::Separator;
+use crate::error::{check_range, Error, Result};
+use crate::version::AlgorithmVersion;
 
 /// An object with convenience methods for loading words or phrases from a file.
 pub struct Text<'a> {
@@ -16,11 +22,11 @@ impl<'a> Text<'a> {
     pub const NOUNS: Text<'static> = Text::new(include_str!("../../texts/nouns.txt"));
 
     /// Create a `Text` object from a text string
-    pub const fn new(text: &str) -> Text {
+    pub const fn new(text: &str) -> Text<'_> {
         Text { text }
     }
     /// Load a vector of lowercase words from file.
-    fn load_words(&self) -> Vec<String> {
+    pub(crate) fn load_words(&self) -> Vec<String> {
         self.text
             .to_lowercase()
             .replace(|c: char| !c.is_alphanumeric(), " ")
@@ -36,51 +42,185 @@ impl<'a> Text<'a> {
                 |c: char| !(c.is_alphanumeric() || c == '.' || c == ',' || c == '\n'),
                 " ",
             )
-            .split(|c: char| c == '.' || c == ',')
+            .split(['.', ','])
             .map(|p| p.split_whitespace().map(|word| word.to_string()).collect())
             .collect()
     }
+    /// Count the words and phrases of this text.
+    pub fn statistics(&self) -> TextStatistics {
+        let words = self.load_words();
+        let vocabulary: HashSet<&String> = words.iter().collect();
+        let phrases = self.load_phrases();
+        let phrases: HashSet<&Vec<String>> = phrases.iter().filter(|p| !p.is_empty()).collect();
+        let phrase_words: usize = phrases.iter().map(|p| p.len()).sum();
+        TextStatistics {
+            words: words.len(),
+            vocabulary: vocabulary.len(),
+            phrases: phrases.len(),
+            mean_phrase_length: phrase_words as f64 / std::cmp::max(phrases.len(), 1) as f64,
+        }
+    }
+}
+
+/// Counts of the words and phrases of a `Text`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextStatistics {
+    pub words: usize,
+    /// The number of distinct words.
+    pub vocabulary: usize,
+    /// The number of distinct phrases.
+    pub phrases: usize,
+    /// The mean number of words of the distinct phrases.
+    pub mean_phrase_length: f64,
 }
 
 /// A `PasswordGenerator` that will generate a random sequence of words of
-/// length `n_words` selected from the `words` vector.
+/// length `n_words` selected from the `words` vector, joined by a `separator`.
 pub struct RandomWords {
     words: Vec<String>,
     n_words: usize,
+    separator: Separator,
+    word_entropy: OnceCell<f64>,
 }
 
 impl RandomWords {
-    /// Create a `RandomWords` object with `words` with lengths that are greater than
-    /// or equal in length to `min_word_length` loaded from an input `text`.
+    /// Create a `RandomWords` object with `words` of at least `min_word_length`
+    /// characters loaded from an input `text`.
     pub fn from_text(text: &Text, n_words: usize, min_word_length: usize) -> RandomWords {
+        RandomWords::from_text_version(text, n_words, min_word_length, AlgorithmVersion::LATEST)
+    }
+    /// Create a `RandomWords` object as with `from_text`, with the algorithms of
+    /// `version`, which count the length of words in bytes before `V3`.
+    pub fn from_text_version(
+        text: &Text,
+        n_words: usize,
+        min_word_length: usize,
+        version: AlgorithmVersion,
+    ) -> RandomWords {
+        let length = |word: &String| match version {
+            AlgorithmVersion::V1 | AlgorithmVersion::V2 => word.len(),
+            _ => word.chars().count(),
+        };
         let words: Vec<String> = text
             .load_words()
             .into_iter()
-            .filter(|s| s.len() >= min_word_length)
+            .filter(|word| length(word) >= min_word_length)
             .collect();
 
-        RandomWords { words, n_words }
+        RandomWords {
+            words,
+            n_words,
+            separator: Separator::default(),
+            word_entropy: OnceCell::new(),
+        }
+    }
+    /// Create a new `RandomWords` object that joins words with `separator`
+    /// rather than a single space.
+    pub fn with_separator(self, separator: Separator) -> RandomWords {
+        RandomWords { separator, ..self }
+    }
+    /// Create a new `RandomWords` object that only chooses words of at most
+    /// `max_word_length` characters.
+    pub fn with_max_word_length(self, max_word_length: usize) -> RandomWords {
+        let words = self
+            .words
+            .into_iter()
+            .filter(|word| word.chars().count() <= max_word_length)
+            .collect();
+        RandomWords {
+            words,
+            word_entropy: OnceCell::new(),
+            ..self
+        }
+    }
+    /// The distinct words that this `RandomWords` object chooses from, sorted.
+    pub fn vocabulary(&self) -> Vec<&str> {
+        let mut vocabulary: Vec<&str> = self.words.iter().map(String::as_str).collect();
+        vocabulary.sort_unstable();
+        vocabulary.dedup();
+        vocabulary
+    }
+    /// Create a `RandomWords` object as with `from_text`, or return
+    /// `Error::EmptyVocabulary` if no words in `text` are long enough.
+    pub fn try_from_text(
+        text: &Text,
+        n_words: usize,
+        min_word_length: usize,
+    ) -> Result<RandomWords> {
+        RandomWords::try_from_text_version(text, n_words, min_word_length, AlgorithmVersion::LATEST)
+    }
+    /// Create a `RandomWords` object as with `from_text_version`, or return
+    /// `Error::EmptyVocabulary` if no words in `text` are long enough.
+    pub fn try_from_text_version(
+        text: &Text,
+        n_words: usize,
+        min_word_length: usize,
+        version: AlgorithmVersion,
+    ) -> Result<RandomWords> {
+        let passwords = RandomWords::from_text_version(text, n_words, min_word_length, version);
+        if passwords.words.is_empty() {
+            return Err(Error::EmptyVocabulary);
+        }
+        Ok(passwords)
     }
 }
 
 impl PasswordGenerator for RandomWords {
-    fn generate_with_seed(&self, rng: &mut ThreadRng, seed: String) -> String {
-        let mut string_array = Vec::new();
-        if seed.len() > 0 {
-            string_array.push(seed)
-        }
+    fn generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> String {
+        self.try_generate_with_seed(rng, seed)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
 
+    fn try_generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> Result<String> {
+        let mut string_array = Vec::new();
         for _ in 0..self.n_words {
-            let word = self.words.choose(rng).unwrap();
+            let word = self.words.choose(rng).ok_or(Error::EmptyVocabulary)?;
             string_array.push(word.to_string());
         }
-        string_array.join(" ")
+        self.separator.join(rng, seed, &string_array)
+    }
+
+    /// `n_words` times the entropy of a single word, which is `log2` of the
+    /// vocabulary size when every word in `words` is unique, plus the entropy
+    /// of the separators between the words.
+    fn entropy(&self) -> f64 {
+        self.entropy_with_seed("")
+    }
+
+    fn entropy_depends_on_seed(&self) -> bool {
+        true
+    }
+
+    /// As `entropy`, with a separator between a non-empty `seed` and the words.
+    fn entropy_with_seed(&self, seed: &str) -> f64 {
+        let n_gaps = if seed.is_empty() {
+            self.n_words.saturating_sub(1)
+        } else {
+            self.n_words
+        };
+        let word_entropy = *self
+            .word_entropy
+            .get_or_init(|| choice_entropy(&self.words));
+        self.n_words as f64 * word_entropy + self.separator.entropy(n_gaps)
+    }
+
+    fn description(&self) -> String {
+        let vocabulary: HashSet<&String> = self.words.iter().collect();
+        format!(
+            "RandomWords(n_words={}, vocabulary={}, separator={:?})",
+            self.n_words,
+            vocabulary.len(),
+            self.separator
+        )
     }
 }
 
-/// A `PasswordGenerator` object that will select a random phrase from a vector of `phrases`.
+/// A `PasswordGenerator` object that will select a random phrase from a vector of `phrases`
+/// and join its words with a `separator`.
 pub struct RandomPhrases {
     phrases: Vec<Vec<String>>,
+    separator: Separator,
+    phrase_entropy: OnceCell<f64>,
 }
 
 impl RandomPhrases {
@@ -96,17 +236,81 @@ impl RandomPhrases {
             })
             .collect();
 
-        RandomPhrases { phrases }
+        RandomPhrases {
+            phrases,
+            separator: Separator::default(),
+            phrase_entropy: OnceCell::new(),
+        }
+    }
+    /// Create a new `RandomPhrases` object that joins words with `separator`
+    /// rather than a single space.
+    pub fn with_separator(self, separator: Separator) -> RandomPhrases {
+        RandomPhrases { separator, ..self }
+    }
+    /// Create a `RandomPhrases` object as with `from_text`, or return an `Error`
+    /// if `min_length` is larger than `max_length` or no phrases in `text` have
+    /// an allowed length.
+    pub fn try_from_text(
+        text: &Text,
+        min_length: usize,
+        max_length: usize,
+    ) -> Result<RandomPhrases> {
+        check_range(min_length, max_length)?;
+        let passwords = RandomPhrases::from_text(text, min_length, max_length);
+        if passwords.phrases.is_empty() {
+            return Err(Error::EmptyVocabulary);
+        }
+        Ok(passwords)
+    }
+    /// The phrases, with their words joined by single spaces.
+    pub(crate) fn phrases(&self) -> impl Iterator<Item = String> + '_ {
+        self.phrases.iter().map(|phrase| phrase.join(" "))
     }
 }
 
 impl PasswordGenerator for RandomPhrases {
-    fn generate_with_seed(&self, rng: &mut ThreadRng, seed: String) -> String {
-        let phrase = self.phrases.choose(rng).unwrap();
-        seed + &phrase.join(" ")
+    fn generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> String {
+        self.try_generate_with_seed(rng, seed)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Appends the phrase to `seed` without a separator, as the words of a
+    /// phrase are only separated from each other.
+    fn try_generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> Result<String> {
+        let phrase = self.phrases.choose(rng).ok_or(Error::EmptyVocabulary)?;
+        Ok(seed + &self.separator.join(rng, String::new(), phrase)?)
+    }
+
+    /// The entropy of the choice of phrase plus the average entropy of the
+    /// separators between the words of each phrase.
+    fn entropy(&self) -> f64 {
+        if self.phrases.is_empty() {
+            return 0.0;
+        }
+        let separator_entropy: f64 = self
+            .phrases
+            .iter()
+            .map(|phrase| self.separator.entropy(phrase.len().saturating_sub(1)))
+            .sum();
+        let phrase_entropy = *self
+            .phrase_entropy
+            .get_or_init(|| choice_entropy(&self.phrases));
+        phrase_entropy + separator_entropy / self.phrases.len() as f64
+    }
+
+    fn description(&self) -> String {
+        let phrases: HashSet<&Vec<String>> = self.phrases.iter().collect();
+        format!(
+            "RandomPhrases(phrases={}, separator={:?})",
+            phrases.len(),
+            self.separator
+        )
     }
 }
 
+impl_fmt_with_description!(std::fmt::Display; RandomWords, RandomPhrases);
+impl_fmt_with_description!(std::fmt::Debug; RandomWords, RandomPhrases);
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -114,20 +318,52 @@ mod test {
     #[test]
     fn test_text_load_words() {
         let words = Text::ALICE_IN_WONDERLAND.load_words();
-        assert!(words.len() > 0);
+        assert!(!words.is_empty());
     }
 
     #[test]
     fn test_text_load_phrases() {
         let phrases = Text::ALICE_IN_WONDERLAND.load_phrases();
-        assert!(phrases.len() > 0);
+        assert!(!phrases.is_empty());
+    }
+
+    #[test]
+    fn test_text_statistics() {
+        let text = Text::new("The cat sat. The cat ran, the cat sat.\n");
+        assert_eq!(
+            text.statistics(),
+            TextStatistics {
+                words: 9,
+                vocabulary: 4,
+                phrases: 2,
+                mean_phrase_length: 3.0,
+            }
+        );
+        let statistics = Text::NOUNS.statistics();
+        assert!(statistics.vocabulary > 1000 && statistics.vocabulary <= statistics.words);
+    }
+
+    #[test]
+    fn test_random_words_vocabulary() {
+        let text = Text::new("b a c a bb");
+        assert_eq!(
+            RandomWords::from_text(&text, 1, 1).vocabulary(),
+            ["a", "b", "bb", "c"]
+        );
+        assert_eq!(RandomWords::from_text(&text, 1, 2).vocabulary(), ["bb"]);
+        let words = RandomWords::from_text(&text, 1, 1).with_max_word_length(1);
+        assert_eq!(words.vocabulary(), ["a", "b", "c"]);
+        let text = Text::new("façade ça");
+        assert_eq!(RandomWords::from_text(&text, 1, 3).vocabulary(), ["façade"]);
+        let words = RandomWords::from_text_version(&text, 1, 3, AlgorithmVersion::V2);
+        assert_eq!(words.vocabulary(), ["façade", "ça"]);
     }
 
     #[test]
     fn test_texts() {
-        assert!(Text::ALICE_IN_WONDERLAND.text.len() > 0);
-        assert!(Text::THE_TIME_MACHINE.text.len() > 0);
-        assert!(Text::NOUNS.text.len() > 0);
+        assert!(!Text::ALICE_IN_WONDERLAND.text.is_empty());
+        assert!(!Text::THE_TIME_MACHINE.text.is_empty());
+        assert!(!Text::NOUNS.text.is_empty());
     }
 
     #[test]
@@ -135,7 +371,7 @@ mod test {
         let passwords = RandomWords::from_text(&Text::ALICE_IN_WONDERLAND, 4, 5);
 
         assert_eq!(passwords.n_words, 4);
-        assert!(passwords.words.len() > 0);
+        assert!(!passwords.words.is_empty());
         assert!(passwords.words.into_iter().all(|word| word.len() >= 5));
     }
 
@@ -154,17 +390,112 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_random_words_try_from_text() {
+        assert!(RandomWords::try_from_text(&Text::NOUNS, 4, 4).is_ok());
+        assert_eq!(
+            RandomWords::try_from_text(&Text::NOUNS, 4, 100).err(),
+            Some(Error::EmptyVocabulary)
+        );
+    }
+
+    #[test]
+    fn test_random_words_try_generate_with_empty_vocabulary() {
+        let passwords = RandomWords::from_text(&Text::new(""), 4, 4);
+        assert_eq!(passwords.try_generate(), Err(Error::EmptyVocabulary));
+    }
+
+    #[test]
+    fn test_random_words_entropy() {
+        let passwords = RandomWords {
+            words: vec!["a", "b", "c", "d"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            n_words: 3,
+            separator: Separator::space(),
+            word_entropy: OnceCell::new(),
+        };
+        assert_eq!(passwords.entropy(), 6.0);
+        let passwords = passwords.with_separator(Separator::PerGap(vec!['1', '2']));
+        assert_eq!(passwords.entropy(), 8.0);
+        assert_eq!(passwords.entropy_with_seed("seed"), 9.0);
+
+        let nouns = RandomWords::from_text(&Text::NOUNS, 4, 4);
+        assert!(nouns.entropy() > 4.0 * 10.0);
+    }
+
+    #[test]
+    fn test_random_words_with_separator() {
+        let passwords = RandomWords::from_text(&Text::NOUNS, 3, 4)
+            .with_separator(Separator::Fixed("-".to_string()));
+        let mut rng = rand::thread_rng();
+
+        let password = passwords.generate_with_seed(&mut rng, String::new());
+        assert_eq!(password.split('-').count(), 3);
+        let password = passwords.generate_with_seed(&mut rng, "seed".to_string());
+        assert!(password.starts_with("seed-"));
+        assert_eq!(password.split('-').count(), 4);
+    }
+
+    #[test]
+    fn test_random_phrases_with_separator() {
+        let passwords = RandomPhrases::from_text(&Text::new("a b c"), 3, 5)
+            .with_separator(Separator::Shared(vec!['7', '!']));
+        assert_eq!(passwords.entropy(), 1.0);
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..10 {
+            let password = passwords.generate_with_seed(&mut rng, String::new());
+            assert!(password == "a7b7c" || password == "a!b!c", "{}", password);
+        }
+        let password = passwords.generate_with_seed(&mut rand::thread_rng(), "x".to_string());
+        assert!(password == "xa7b7c" || password == "xa!b!c", "{}", password);
+    }
+
     #[test]
     fn test_random_phrases_from_text() {
         let passwords = RandomPhrases::from_text(&Text::ALICE_IN_WONDERLAND, 3, 5);
 
-        assert!(passwords.phrases.len() > 0);
+        assert!(!passwords.phrases.is_empty());
         assert!(passwords
             .phrases
             .into_iter()
             .all(|phrase| phrase.len() >= 3 && phrase.len() <= 5));
     }
 
+    #[test]
+    fn test_random_phrases_try_from_text() {
+        assert!(RandomPhrases::try_from_text(&Text::THE_TIME_MACHINE, 3, 5).is_ok());
+        assert_eq!(
+            RandomPhrases::try_from_text(&Text::THE_TIME_MACHINE, 5, 3).err(),
+            Some(Error::InvalidRange { min: 5, max: 3 })
+        );
+        assert_eq!(
+            RandomPhrases::try_from_text(
+                &Text::new("one two. three four five six seven eight"),
+                3,
+                5
+            )
+            .err(),
+            Some(Error::EmptyVocabulary)
+        );
+    }
+
+    #[test]
+    fn test_random_phrases_try_generate_with_empty_vocabulary() {
+        let passwords = RandomPhrases::from_text(&Text::new("one"), 3, 5);
+        assert_eq!(passwords.try_generate(), Err(Error::EmptyVocabulary));
+    }
+
+    #[test]
+    fn test_random_phrases_entropy() {
+        let passwords = RandomPhrases::from_text(&Text::new("a b c. d e f. a b c"), 3, 5);
+        assert_eq!(passwords.phrases.len(), 3);
+        let expected = -(2.0 / 3.0) * (2.0f64 / 3.0).log2() - (1.0 / 3.0) * (1.0f64 / 3.0).log2();
+        assert!((passwords.entropy() - expected).abs() < 1e-9);
+    }
+
     #[test]
     fn test_random_phrases_generate_with_seed() {
         let passwords = RandomPhrases::from_text(&Text::ALICE_IN_WONDERLAND, 3, 5);
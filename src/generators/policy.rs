@@ -0,0 +1,678 @@
+use rand::seq::{IteratorRandom, SliceRandom};
+use rand::RngCore;
+
+use super::base::{PasswordGenerator, Stage};
+use super::base::{ASCII_LOWERCASE, ASCII_PUNCTUATION, ASCII_UPPERCASE, DIGITS};
+use super::filter::{retry, AcceptanceRate};
+use crate::error::{Error, Result};
+
+/// A class of characters that a `Policy` may require a password to contain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CharacterClass {
+    Lower,
+    Upper,
+    Digit,
+    Symbol,
+}
+
+impl CharacterClass {
+    pub const ALL: [CharacterClass; 4] = [
+        CharacterClass::Lower,
+        CharacterClass::Upper,
+        CharacterClass::Digit,
+        CharacterClass::Symbol,
+    ];
+
+    /// Whether the character `c` belongs to this class.
+    ///
+    /// The classes are the ascii classes of the `passwordrules` syntax: the
+    /// symbols are the ascii punctuation characters and space, and a character
+    /// outside of ascii, such as `é`, belongs to no class.
+    pub fn contains(&self, c: char) -> bool {
+        match self {
+            CharacterClass::Lower => c.is_ascii_lowercase(),
+            CharacterClass::Upper => c.is_ascii_uppercase(),
+            CharacterClass::Digit => c.is_ascii_digit(),
+            CharacterClass::Symbol => c.is_ascii_punctuation() || c == ' ',
+        }
+    }
+    /// The ascii characters of this class that are used to repair passwords.
+    pub fn characters(&self) -> &'static str {
+        match self {
+            CharacterClass::Lower => ASCII_LOWERCASE,
+            CharacterClass::Upper => ASCII_UPPERCASE,
+            CharacterClass::Digit => DIGITS,
+            CharacterClass::Symbol => ASCII_PUNCTUATION,
+        }
+    }
+}
+
+/// A set of characters made up of whole `CharacterClass`es and individual characters.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CharacterSet {
+    classes: Vec<CharacterClass>,
+    characters: Vec<char>,
+}
+
+impl CharacterSet {
+    /// Create an empty `CharacterSet`.
+    pub fn new() -> CharacterSet {
+        CharacterSet::default()
+    }
+    /// Create a new `CharacterSet` that also contains every character of `class`.
+    pub fn with_class(mut self, class: CharacterClass) -> CharacterSet {
+        if !self.classes.contains(&class) {
+            self.classes.push(class);
+        }
+        self
+    }
+    /// Create a new `CharacterSet` that also contains each of `characters`.
+    pub fn with_characters(mut self, characters: &str) -> CharacterSet {
+        for c in characters.chars() {
+            if !self.characters.contains(&c) {
+                self.characters.push(c);
+            }
+        }
+        self
+    }
+    /// Create a new `CharacterSet` that also contains every character of `other`.
+    pub fn union(self, other: &CharacterSet) -> CharacterSet {
+        let characters: String = other.characters.iter().collect();
+        other
+            .classes
+            .iter()
+            .fold(self, |set, class| set.with_class(*class))
+            .with_characters(&characters)
+    }
+
+    /// Whether the character `c` belongs to this set.
+    pub fn contains(&self, c: char) -> bool {
+        self.characters.contains(&c) || self.classes.iter().any(|class| class.contains(c))
+    }
+    /// The ascii characters of this set that are used to repair passwords.
+    pub fn characters(&self) -> Vec<char> {
+        let mut characters: Vec<char> = self
+            .classes
+            .iter()
+            .flat_map(|class| class.characters().chars())
+            .collect();
+        for c in self.characters.iter() {
+            if !characters.contains(c) {
+                characters.push(*c);
+            }
+        }
+        characters
+    }
+}
+
+impl From<CharacterClass> for CharacterSet {
+    fn from(class: CharacterClass) -> CharacterSet {
+        CharacterSet::new().with_class(class)
+    }
+}
+
+/// A way in which a password fails to satisfy a `Policy`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    TooShort {
+        length: usize,
+        min_length: usize,
+    },
+    TooLong {
+        length: usize,
+        max_length: usize,
+    },
+    MissingClass(CharacterSet),
+    BannedCharacter(char),
+    DisallowedCharacter(char),
+    TooManyConsecutive {
+        character: char,
+        max_consecutive: usize,
+    },
+}
+
+/// A set of rules that a password must satisfy, such as those imposed by a website.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Policy {
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    required: Vec<CharacterSet>,
+    allowed: Option<CharacterSet>,
+    banned: Vec<char>,
+    max_consecutive: Option<usize>,
+}
+
+impl Policy {
+    /// Create a `Policy` that every password satisfies.
+    pub fn new() -> Policy {
+        Policy::default()
+    }
+    /// Create a new `Policy` that requires passwords to have at least `min_length` characters.
+    pub fn with_min_length(self, min_length: usize) -> Policy {
+        Policy {
+            min_length: Some(min_length),
+            ..self
+        }
+    }
+    /// Create a new `Policy` that requires passwords to have at most `max_length` characters.
+    pub fn with_max_length(self, max_length: usize) -> Policy {
+        Policy {
+            max_length: Some(max_length),
+            ..self
+        }
+    }
+    /// Create a new `Policy` that requires passwords to contain at least one
+    /// character of `required`, which may be a `CharacterClass` or a `CharacterSet`.
+    pub fn with_required<T: Into<CharacterSet>>(mut self, required: T) -> Policy {
+        let required = required.into();
+        if !self.required.contains(&required) {
+            self.required.push(required);
+        }
+        self
+    }
+    /// Create a new `Policy` that only allows passwords made of characters in `allowed`,
+    /// in addition to any that were already allowed.
+    pub fn with_allowed<T: Into<CharacterSet>>(self, allowed: T) -> Policy {
+        let allowed = match self.allowed {
+            Some(ref existing) => existing.clone().union(&allowed.into()),
+            None => allowed.into(),
+        };
+        Policy {
+            allowed: Some(allowed),
+            ..self
+        }
+    }
+    /// Create a new `Policy` that forbids passwords from containing any of `characters`.
+    pub fn with_banned_characters(mut self, characters: &str) -> Policy {
+        self.banned.extend(characters.chars());
+        self
+    }
+    /// Create a new `Policy` that forbids runs of more than `max_consecutive`
+    /// identical characters.
+    pub fn with_max_consecutive(self, max_consecutive: usize) -> Policy {
+        Policy {
+            max_consecutive: Some(max_consecutive),
+            ..self
+        }
+    }
+
+    /// The minimum number of characters in a password, if any.
+    pub fn min_length(&self) -> Option<usize> {
+        self.min_length
+    }
+    /// The maximum number of characters in a password, if any.
+    pub fn max_length(&self) -> Option<usize> {
+        self.max_length
+    }
+    /// Whether a password may contain the character `c`.
+    pub fn allows(&self, c: char) -> bool {
+        !self.banned.contains(&c) && self.allowed.as_ref().is_none_or(|a| a.contains(c))
+    }
+    /// Whether a password may contain every character of `class` that is used
+    /// to repair passwords.
+    pub fn allows_class(&self, class: CharacterClass) -> bool {
+        class.characters().chars().all(|c| self.allows(c))
+    }
+    /// Whether every password must contain a character of `class`.
+    pub fn requires(&self, class: CharacterClass) -> bool {
+        self.required
+            .iter()
+            .any(|set| set.classes == [class] && set.characters.is_empty())
+    }
+
+    /// List the ways in which `password` fails to satisfy this `Policy`.
+    pub fn violations(&self, password: &str) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        let length = password.chars().count();
+
+        if let Some(min_length) = self.min_length {
+            if length < min_length {
+                violations.push(Violation::TooShort { length, min_length });
+            }
+        }
+        if let Some(max_length) = self.max_length {
+            if length > max_length {
+                violations.push(Violation::TooLong { length, max_length });
+            }
+        }
+        for set in self.required.iter() {
+            if !password.chars().any(|c| set.contains(c)) {
+                violations.push(Violation::MissingClass(set.clone()));
+            }
+        }
+        for c in password.chars() {
+            if self.banned.contains(&c) {
+                violations.push(Violation::BannedCharacter(c));
+            } else if !self.allows(c) {
+                violations.push(Violation::DisallowedCharacter(c));
+            }
+        }
+        if let Some(max_consecutive) = self.max_consecutive {
+            let chars: Vec<char> = password.chars().collect();
+            let mut start = 0;
+            while start < chars.len() {
+                let run = chars[start..]
+                    .iter()
+                    .take_while(|c| **c == chars[start])
+                    .count();
+                if run > max_consecutive {
+                    violations.push(Violation::TooManyConsecutive {
+                        character: chars[start],
+                        max_consecutive,
+                    });
+                }
+                start += run;
+            }
+        }
+        violations
+    }
+    /// Whether `password` satisfies every rule of this `Policy`.
+    pub fn is_satisfied_by(&self, password: &str) -> bool {
+        self.violations(password).is_empty()
+    }
+
+    /// The characters of `set` that this `Policy` allows.
+    fn allowed_characters(&self, set: &CharacterSet) -> Vec<char> {
+        set.characters()
+            .into_iter()
+            .filter(|c| self.allows(*c))
+            .collect()
+    }
+    /// The fraction of the characters of `password` that `repair` keeps, as it
+    /// removes disallowed characters and truncates long passwords.
+    fn surviving_fraction(&self, password: &str) -> f64 {
+        let length = password.chars().count();
+        if length == 0 {
+            return 1.0;
+        }
+        let allowed = password.chars().filter(|c| self.allows(*c)).count();
+        let surviving = self.max_length.map_or(allowed, |max| allowed.min(max));
+        surviving as f64 / length as f64
+    }
+    /// Make the smallest random changes to `password` that satisfy this `Policy`:
+    /// remove disallowed characters, truncate long passwords, pad short passwords,
+    /// and replace characters to introduce missing character classes.
+    fn repair(&self, rng: &mut dyn RngCore, password: String) -> String {
+        let mut chars: Vec<char> = password.chars().filter(|c| self.allows(*c)).collect();
+
+        if let Some(max_length) = self.max_length {
+            chars.truncate(max_length);
+        }
+
+        let padding_set = if !self.required.is_empty() {
+            self.required
+                .iter()
+                .fold(CharacterSet::new(), |padding, set| padding.union(set))
+        } else if let Some(ref allowed) = self.allowed {
+            allowed.clone()
+        } else {
+            CharacterSet::from(CharacterClass::Lower).with_class(CharacterClass::Digit)
+        };
+        let padding = self.allowed_characters(&padding_set);
+        while chars.len() < self.min_length.unwrap_or(0) {
+            match padding.choose(rng) {
+                Some(c) => chars.push(*c),
+                None => break,
+            }
+        }
+
+        for set in self.required.iter() {
+            if chars.iter().any(|c| set.contains(*c)) {
+                continue;
+            }
+            let replacement = match self.allowed_characters(set).choose(rng) {
+                Some(c) => *c,
+                None => continue,
+            };
+            // Only replace characters whose removal does not remove a required class.
+            let position = (0..chars.len())
+                .filter(|&i| {
+                    self.required.iter().all(|other| {
+                        !other.contains(chars[i])
+                            || chars.iter().filter(|c| other.contains(**c)).count() > 1
+                    })
+                })
+                .choose(rng);
+            match position {
+                Some(i) => chars[i] = replacement,
+                None => chars.push(replacement),
+            }
+        }
+        chars.into_iter().collect()
+    }
+}
+
+/// A `PasswordGenerator` that regenerates, or optionally repairs, the passwords
+/// of another `PasswordGenerator` until they satisfy a `Policy`.
+pub struct WithPolicy<'a> {
+    generator: Box<dyn PasswordGenerator + 'a>,
+    policy: Policy,
+    max_attempts: usize,
+    repair: bool,
+    acceptance_rate: AcceptanceRate,
+}
+
+impl<'a> WithPolicy<'a> {
+    /// Create a `WithPolicy` object that makes up to 100 attempts to generate a
+    /// password from `generator` that satisfies `policy`.
+    pub fn new<T>(generator: T, policy: Policy) -> WithPolicy<'a>
+    where
+        T: PasswordGenerator + 'a,
+    {
+        WithPolicy {
+            generator: Box::new(generator),
+            policy,
+            max_attempts: 100,
+            repair: false,
+            acceptance_rate: AcceptanceRate::default(),
+        }
+    }
+    /// Create a new `WithPolicy` object that gives up after `max_attempts` attempts.
+    pub fn with_max_attempts(self, max_attempts: usize) -> WithPolicy<'a> {
+        WithPolicy {
+            max_attempts,
+            acceptance_rate: AcceptanceRate::default(),
+            ..self
+        }
+    }
+    /// Create a new `WithPolicy` object that repairs each candidate password that
+    /// does not satisfy the policy, rather than discarding it.
+    pub fn with_repair(self) -> WithPolicy<'a> {
+        WithPolicy {
+            repair: true,
+            acceptance_rate: AcceptanceRate::default(),
+            ..self
+        }
+    }
+    /// The `Policy` that generated passwords satisfy.
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+    /// Repair `password` if repair is enabled.
+    fn repaired(&self, rng: &mut dyn RngCore, password: String) -> String {
+        if self.repair {
+            self.policy.repair(rng, password)
+        } else {
+            password
+        }
+    }
+    /// The error returned when no attempt satisfies the policy.
+    fn rejected(&self) -> Error {
+        Error::PolicyNotSatisfied {
+            attempts: self.max_attempts,
+        }
+    }
+}
+
+impl<'a> PasswordGenerator for WithPolicy<'a> {
+    fn generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> String {
+        self.try_generate_with_seed(rng, seed)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> Result<String> {
+        retry(
+            self.max_attempts,
+            || {
+                let password = self.generator.try_generate_with_seed(rng, seed.clone())?;
+                let password = self.repaired(rng, password);
+                Ok(Some(password).filter(|password| self.policy.is_satisfied_by(password)))
+            },
+            || self.rejected(),
+        )
+    }
+
+    /// The entropy of the underlying generator reduced by the information revealed
+    /// by the policy, `-log2` of the estimated fraction of candidates that satisfy it.
+    ///
+    /// A candidate that is repaired is kept rather than rejected, but repair
+    /// maps many candidates to the same password when it removes disallowed
+    /// characters or truncates long candidates. Only the characters of each
+    /// candidate that survive repair are counted, assuming that the entropy of
+    /// a candidate is spread evenly over its characters, and the random
+    /// characters added by repairs are not counted.
+    fn entropy(&self) -> f64 {
+        self.acceptance_rate
+            .entropy(self.generator.as_ref(), |rng, password| {
+                let kept = if self.repair {
+                    self.policy.surviving_fraction(&password)
+                } else {
+                    1.0
+                };
+                Some(kept).filter(|_| self.policy.is_satisfied_by(&self.repaired(rng, password)))
+            })
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "WithPolicy(max_attempts={}, repair={}, policy={:?})",
+            self.max_attempts, self.repair, self.policy
+        )
+    }
+
+    fn children(&self) -> Vec<&dyn PasswordGenerator> {
+        vec![self.generator.as_ref()]
+    }
+
+    /// A candidate of the underlying generator, repaired if repair is enabled,
+    /// whether or not it satisfies the policy.
+    fn try_generate_candidate_with_seed(
+        &self,
+        rng: &mut dyn RngCore,
+        seed: String,
+    ) -> Result<String> {
+        let password = self.generator.try_generate_candidate_with_seed(rng, seed)?;
+        Ok(self.repaired(rng, password))
+    }
+
+    /// Records the stages of the attempt that satisfied the policy, followed by
+    /// a stage for the policy itself with the final, possibly repaired, password.
+    fn explain_with_seed(
+        &self,
+        rng: &mut dyn RngCore,
+        seed: String,
+        depth: usize,
+        stages: &mut Vec<Stage>,
+    ) -> Result<String> {
+        retry(
+            self.max_attempts,
+            || {
+                let mut attempt = Vec::new();
+                let password =
+                    self.generator
+                        .explain_with_seed(rng, seed.clone(), depth + 1, &mut attempt)?;
+                let password = self.repaired(rng, password);
+                if !self.policy.is_satisfied_by(&password) {
+                    return Ok(None);
+                }
+                stages.extend(attempt);
+                stages.push(Stage {
+                    depth,
+                    description: self.description(),
+                    output: password.clone(),
+                    entropy: 0.0,
+                });
+                Ok(Some(password))
+            },
+            || self.rejected(),
+        )
+    }
+}
+
+impl_fmt_with_description!(std::fmt::Display; WithPolicy<'_>);
+impl_fmt_with_description!(std::fmt::Debug; WithPolicy<'_>);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::generators::base::Constant;
+    use crate::generators::random_string::RandomString;
+
+    #[test]
+    fn test_policy_violations() {
+        let policy = Policy::new()
+            .with_min_length(8)
+            .with_max_length(10)
+            .with_required(CharacterClass::Upper)
+            .with_required(CharacterClass::Digit)
+            .with_banned_characters(" ");
+
+        assert!(policy.is_satisfied_by("Password1"));
+        assert_eq!(
+            policy.violations("pass word"),
+            vec![
+                Violation::MissingClass(CharacterClass::Upper.into()),
+                Violation::MissingClass(CharacterClass::Digit.into()),
+                Violation::BannedCharacter(' '),
+            ]
+        );
+        assert_eq!(
+            policy.violations("Pass1"),
+            vec![Violation::TooShort {
+                length: 5,
+                min_length: 8
+            }]
+        );
+        assert_eq!(
+            policy.violations("Password12345"),
+            vec![Violation::TooLong {
+                length: 13,
+                max_length: 10
+            }]
+        );
+    }
+
+    #[test]
+    fn test_policy_allowed_and_max_consecutive() {
+        let policy = Policy::new()
+            .with_allowed(CharacterClass::Lower)
+            .with_allowed(CharacterSet::new().with_characters("-!"))
+            .with_max_consecutive(2);
+
+        assert!(policy.is_satisfied_by("abba-!"));
+        assert_eq!(
+            policy.violations("aB1"),
+            vec![
+                Violation::DisallowedCharacter('B'),
+                Violation::DisallowedCharacter('1'),
+            ]
+        );
+        assert_eq!(
+            policy.violations("abbb"),
+            vec![Violation::TooManyConsecutive {
+                character: 'b',
+                max_consecutive: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn test_policy_required_set() {
+        let policy = Policy::new()
+            .with_required(CharacterSet::from(CharacterClass::Digit).with_characters("#"));
+        assert!(policy.is_satisfied_by("a1"));
+        assert!(policy.is_satisfied_by("a#"));
+        assert!(!policy.is_satisfied_by("a!"));
+    }
+
+    #[test]
+    fn test_character_class_contains() {
+        assert!(CharacterClass::Lower.contains('a'));
+        assert!(CharacterClass::Upper.contains('A'));
+        assert!(!CharacterClass::Upper.contains('Ä'));
+        assert!(!CharacterClass::Lower.contains('é'));
+        assert!(!CharacterClass::Symbol.contains('é'));
+        assert!(CharacterClass::Digit.contains('7'));
+        assert!(CharacterClass::Symbol.contains('!'));
+        assert!(CharacterClass::Symbol.contains(' '));
+        assert!(!CharacterClass::Symbol.contains('a'));
+    }
+
+    #[test]
+    fn test_with_policy_rejection() {
+        let policy = Policy::new().with_required(CharacterClass::Digit);
+        let passwords = RandomString::new(4)
+            .with_characters("a1".chars().collect())
+            .with_policy(policy.clone());
+
+        for password in passwords.iterator().take(20) {
+            assert!(policy.is_satisfied_by(&password));
+        }
+    }
+
+    #[test]
+    fn test_with_policy_gives_up() {
+        let passwords = Constant::new("abc")
+            .with_policy(Policy::new().with_min_length(8))
+            .with_max_attempts(3);
+        assert_eq!(
+            passwords.try_generate(),
+            Err(Error::PolicyNotSatisfied { attempts: 3 })
+        );
+        assert_eq!(passwords.entropy(), 0.0);
+    }
+
+    #[test]
+    fn test_with_policy_repair() {
+        let policy = Policy::new()
+            .with_min_length(12)
+            .with_max_length(14)
+            .with_required(CharacterClass::Upper)
+            .with_required(CharacterClass::Digit)
+            .with_required(CharacterClass::Symbol)
+            .with_banned_characters(" ");
+        let passwords = Constant::new("correct horse battery staple")
+            .with_policy(policy.clone())
+            .with_max_attempts(1)
+            .with_repair();
+
+        for password in passwords.iterator().take(20) {
+            assert!(policy.is_satisfied_by(&password), "{}", password);
+        }
+        let password = Constant::new("abc")
+            .with_policy(policy.clone())
+            .with_repair()
+            .generate();
+        assert!(policy.is_satisfied_by(&password), "{}", password);
+    }
+
+    #[test]
+    fn test_with_policy_explain() {
+        let policy = Policy::new().with_required(CharacterClass::Digit);
+        let passwords = WithPolicy::new(Constant::new("abc"), policy).with_repair();
+        let stages = passwords.explain(&mut rand::thread_rng()).unwrap();
+        assert_eq!(stages.len(), 2);
+        assert_eq!((stages[0].depth, stages[0].output.as_str()), (1, "abc"));
+        assert_eq!(stages[1].depth, 0);
+        assert!(stages[1]
+            .description
+            .starts_with("WithPolicy(max_attempts=100"));
+        assert!(passwords.policy().is_satisfied_by(&stages[1].output));
+    }
+
+    #[test]
+    fn test_with_policy_entropy() {
+        let passwords = RandomString::new(1).with_characters("ab".chars().collect());
+        assert_eq!(passwords.entropy(), 1.0);
+
+        let passwords = passwords.with_policy(Policy::new().with_banned_characters("b"));
+        let entropy = passwords.entropy();
+        assert!((0.0..0.3).contains(&entropy), "{}", entropy);
+
+        let passwords = RandomString::new(8).with_characters("ab".chars().collect());
+        let policy = Policy::new().with_required(CharacterClass::Digit);
+        assert_eq!(passwords.with_policy(policy.clone()).entropy(), 0.0);
+        let passwords = RandomString::new(8)
+            .with_characters("ab".chars().collect())
+            .with_policy(policy)
+            .with_repair();
+        assert_eq!(passwords.entropy(), 8.0);
+        assert_eq!(passwords.entropy(), 8.0);
+
+        // truncation to the maximum length keeps only the first 8 of 16 digits
+        let passwords = RandomString::digits(16)
+            .with_policy(Policy::new().with_max_length(8))
+            .with_repair();
+        assert!((passwords.entropy() - 8.0 * 10f64.log2()).abs() < 1e-9);
+    }
+}
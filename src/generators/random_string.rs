@@ -1,8 +1,9 @@
-use rand::rngs::ThreadRng;
 use rand::seq::SliceRandom;
+use rand::RngCore;
 
-use super::base::PasswordGenerator;
+use super::base::{choice_entropy, PasswordGenerator};
 use super::base::{ASCII_LOWERCASE, ASCII_UPPERCASE, DIGITS};
+use crate::error::{Error, Result};
 
 /// A `PasswordGenerator` that will generate a random string with size `length`
 /// out of the candidate list of `characters`.
@@ -24,10 +25,7 @@ impl RandomString {
     /// Create a new `RandomString` object with the same `length`, but a
     /// different set of `characters`.
     pub fn with_characters(self, characters: Vec<char>) -> RandomString {
-        RandomString {
-            characters: characters,
-            ..self
-        }
+        RandomString { characters, ..self }
     }
     /// Create a `RandomString` object that generates random strings of digits.
     pub fn digits(length: usize) -> RandomString {
@@ -44,27 +42,47 @@ impl RandomString {
 }
 
 impl PasswordGenerator for RandomString {
-    fn generate_with_seed(&self, rng: &mut ThreadRng, mut seed: String) -> String {
+    fn generate_with_seed(&self, rng: &mut dyn RngCore, seed: String) -> String {
+        self.try_generate_with_seed(rng, seed)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_generate_with_seed(&self, rng: &mut dyn RngCore, mut seed: String) -> Result<String> {
         for _ in 0..self.length {
-            let c = self.characters.choose(rng).unwrap();
+            let c = self.characters.choose(rng).ok_or(Error::EmptyVocabulary)?;
             seed.push(*c);
         }
-        seed
+        Ok(seed)
+    }
+
+    fn entropy(&self) -> f64 {
+        self.length as f64 * choice_entropy(&self.characters)
+    }
+
+    fn description(&self) -> String {
+        let characters: String = self.characters.iter().collect();
+        format!(
+            "RandomString(length={}, characters={:?})",
+            self.length, characters
+        )
     }
 }
 
+impl_fmt_with_description!(std::fmt::Display; RandomString);
+impl_fmt_with_description!(std::fmt::Debug; RandomString);
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     #[test]
     fn test_random_string_new() {
-        let characters = RandomString::new(5).characters.sort();
-        let expected_characters = format!("{}{}{}", DIGITS, ASCII_LOWERCASE, ASCII_UPPERCASE)
+        let mut characters = RandomString::new(5).characters;
+        characters.sort_unstable();
+        let mut expected_characters = format!("{}{}{}", DIGITS, ASCII_LOWERCASE, ASCII_UPPERCASE)
             .chars()
-            .into_iter()
-            .collect::<Vec<char>>()
-            .sort();
+            .collect::<Vec<char>>();
+        expected_characters.sort_unstable();
 
         assert_eq!(characters, expected_characters);
     }
@@ -104,6 +122,22 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_random_string_try_generate_with_empty_characters() {
+        let passwords = RandomString::new(2).with_characters(vec![]);
+        assert_eq!(passwords.try_generate(), Err(Error::EmptyVocabulary));
+        let passwords = RandomString::new(0).with_characters(vec![]);
+        assert_eq!(passwords.try_generate(), Ok(String::new()));
+    }
+
+    #[test]
+    fn test_random_string_entropy() {
+        assert_eq!(RandomString::digits(0).entropy(), 0.0);
+        assert!((RandomString::digits(2).entropy() - 2.0 * 10f64.log2()).abs() < 1e-9);
+        let passwords = RandomString::new(4).with_characters("ab".chars().collect());
+        assert_eq!(passwords.entropy(), 4.0);
+    }
+
     #[test]
     fn test_random_string_generate_with_trivial_seed() {
         let length = 5;
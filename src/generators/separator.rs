@@ -0,0 +1,159 @@
+use rand::seq::SliceRandom;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use super::base::choice_entropy;
+use super::base::{ASCII_PUNCTUATION, DIGITS};
+use crate::error::{Error, Result};
+
+/// A strategy for separating the words generated by `RandomWords` and `RandomPhrases`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Separator {
+    /// The same string between every pair of words.
+    Fixed(String),
+    /// A character chosen at random once per password and placed between
+    /// every pair of words.
+    Shared(Vec<char>),
+    /// A character chosen at random for each gap between words.
+    PerGap(Vec<char>),
+}
+
+impl Separator {
+    /// Separate words with a single space.
+    pub fn space() -> Separator {
+        Separator::Fixed(" ".to_string())
+    }
+    /// Separate words with a random digit in each gap.
+    pub fn digits() -> Separator {
+        Separator::PerGap(DIGITS.chars().collect())
+    }
+    /// Separate words with a random symbol in each gap.
+    pub fn symbols() -> Separator {
+        Separator::PerGap(ASCII_PUNCTUATION.chars().collect())
+    }
+    /// Separate words with a random digit or symbol in each gap.
+    pub fn digits_and_symbols() -> Separator {
+        Separator::PerGap(DIGITS.chars().chain(ASCII_PUNCTUATION.chars()).collect())
+    }
+
+    /// Choose the separators for `n_gaps` gaps between words.
+    pub(crate) fn choose(&self, rng: &mut dyn RngCore, n_gaps: usize) -> Result<Vec<String>> {
+        if n_gaps == 0 {
+            return Ok(Vec::new());
+        }
+        match self {
+            Separator::Fixed(separator) => Ok(vec![separator.clone(); n_gaps]),
+            Separator::Shared(characters) => {
+                let c = characters.choose(rng).ok_or(Error::EmptyVocabulary)?;
+                Ok(vec![c.to_string(); n_gaps])
+            }
+            Separator::PerGap(characters) => (0..n_gaps)
+                .map(|_| {
+                    let c = characters.choose(rng).ok_or(Error::EmptyVocabulary)?;
+                    Ok(c.to_string())
+                })
+                .collect(),
+        }
+    }
+    /// Join `seed`, unless it is empty, and `words` with separators.
+    pub(crate) fn join(
+        &self,
+        rng: &mut dyn RngCore,
+        seed: String,
+        words: &[String],
+    ) -> Result<String> {
+        let mut parts: Vec<&str> = Vec::new();
+        if !seed.is_empty() {
+            parts.push(&seed);
+        }
+        parts.extend(words.iter().map(|w| w.as_str()));
+
+        let separators = self.choose(rng, parts.len().saturating_sub(1))?;
+        let mut password = String::new();
+        for (i, part) in parts.into_iter().enumerate() {
+            if i > 0 {
+                password.push_str(&separators[i - 1]);
+            }
+            password.push_str(part);
+        }
+        Ok(password)
+    }
+    /// The entropy, in bits, of the separators for `n_gaps` gaps between words.
+    pub fn entropy(&self, n_gaps: usize) -> f64 {
+        if n_gaps == 0 {
+            return 0.0;
+        }
+        match self {
+            Separator::Fixed(_) => 0.0,
+            Separator::Shared(characters) => choice_entropy(characters),
+            Separator::PerGap(characters) => n_gaps as f64 * choice_entropy(characters),
+        }
+    }
+}
+
+impl Default for Separator {
+    fn default() -> Separator {
+        Separator::space()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn test_separator_join() {
+        let mut rng = rand::thread_rng();
+        let input = words(&["correct", "horse", "battery"]);
+
+        assert_eq!(
+            Separator::space().join(&mut rng, String::new(), &input),
+            Ok("correct horse battery".to_string())
+        );
+        assert_eq!(
+            Separator::Fixed("-".to_string()).join(&mut rng, "seed".to_string(), &input),
+            Ok("seed-correct-horse-battery".to_string())
+        );
+        assert_eq!(
+            Separator::Shared(vec!['.']).join(&mut rng, String::new(), &input),
+            Ok("correct.horse.battery".to_string())
+        );
+        assert_eq!(
+            Separator::PerGap(vec![]).join(&mut rng, String::new(), &input),
+            Err(Error::EmptyVocabulary)
+        );
+        assert_eq!(
+            Separator::PerGap(vec![]).join(&mut rng, String::new(), &input[..1]),
+            Ok("correct".to_string())
+        );
+    }
+
+    #[test]
+    fn test_separator_digits_and_symbols() {
+        let mut rng = rand::thread_rng();
+        let input = words(&["correct", "horse", "battery"]);
+        let separator = Separator::digits_and_symbols();
+
+        for _ in 0..10 {
+            let password = separator.join(&mut rng, String::new(), &input).unwrap();
+            let gaps: Vec<char> = password.chars().filter(|c| !c.is_alphabetic()).collect();
+            assert_eq!(gaps.len(), 2);
+            assert!(gaps
+                .iter()
+                .all(|c| !c.is_alphanumeric() || c.is_ascii_digit()));
+        }
+    }
+
+    #[test]
+    fn test_separator_entropy() {
+        assert_eq!(Separator::space().entropy(3), 0.0);
+        assert_eq!(Separator::Shared(vec!['a', 'b']).entropy(3), 1.0);
+        assert_eq!(Separator::PerGap(vec!['a', 'b']).entropy(3), 3.0);
+        assert_eq!(Separator::PerGap(vec!['a', 'b']).entropy(0), 0.0);
+    }
+}
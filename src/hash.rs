@@ -0,0 +1,299 @@
+//! Password hashes for provisioning accounts: sha512-crypt for `/etc/shadow`,
+//! bcrypt for `.htpasswd` files, and Argon2id PHC strings for applications.
+
+use std::fmt;
+use std::str::FromStr;
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use pwhash::bcrypt::{BcryptSetup, BcryptVariant};
+use pwhash::HashSetup;
+use rand::seq::SliceRandom;
+use rand::RngCore;
+
+use crate::error::{Error, Result};
+
+/// The characters of the salts of sha512-crypt and bcrypt.
+const CRYPT_ALPHABET: &[u8] = b"./ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+/// The number of rounds of sha512-crypt that is implied when none are given.
+const SHA512_DEFAULT_ROUNDS: u32 = 5000;
+
+/// A password hashing algorithm and its parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HashAlgorithm {
+    /// sha512-crypt, `$6$`, as used by `/etc/shadow`.
+    Sha512Crypt { rounds: u32 },
+    /// bcrypt, with the `$2y$` prefix that Apache's `htpasswd` uses.
+    Bcrypt { cost: u32 },
+    /// Argon2id (version 0x13), as a PHC string.
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+}
+
+impl HashAlgorithm {
+    /// sha512-crypt with the default 5000 rounds.
+    pub fn sha512_crypt() -> HashAlgorithm {
+        HashAlgorithm::Sha512Crypt {
+            rounds: SHA512_DEFAULT_ROUNDS,
+        }
+    }
+    /// bcrypt with a cost of 12.
+    pub fn bcrypt() -> HashAlgorithm {
+        HashAlgorithm::Bcrypt { cost: 12 }
+    }
+    /// Argon2id with 19 MiB of memory, 2 iterations and 1 lane, the minimum
+    /// that OWASP recommends.
+    pub fn argon2id() -> HashAlgorithm {
+        HashAlgorithm::Argon2id {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+
+    /// Hash `password` with a random salt.
+    pub fn hash(&self, password: &str) -> Result<String> {
+        self.hash_with_rng(&mut rand::thread_rng(), password)
+    }
+    /// Hash `password` with a salt from `rng`.
+    ///
+    /// bcrypt only uses the first 72 bytes of a password.
+    pub fn hash_with_rng(&self, rng: &mut dyn RngCore, password: &str) -> Result<String> {
+        match *self {
+            HashAlgorithm::Sha512Crypt { rounds } => {
+                let salt = crypt_salt(rng, 16);
+                let setup = HashSetup {
+                    salt: Some(&salt),
+                    // the default rounds are left implicit, as `passwd` does
+                    rounds: Some(rounds).filter(|rounds| *rounds != SHA512_DEFAULT_ROUNDS),
+                };
+                pwhash::sha512_crypt::hash_with(setup, password).map_err(hash_error)
+            }
+            HashAlgorithm::Bcrypt { cost } => {
+                // the last character of a salt only encodes 2 bits
+                let mut salt = crypt_salt(rng, 21);
+                salt.push(*[b'.', b'O', b'e', b'u'].choose(rng).unwrap_or(&b'.') as char);
+                let setup = BcryptSetup {
+                    salt: Some(&salt),
+                    cost: Some(cost),
+                    variant: Some(BcryptVariant::V2y),
+                };
+                pwhash::bcrypt::hash_with(setup, password).map_err(hash_error)
+            }
+            HashAlgorithm::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let params =
+                    Params::new(memory_kib, iterations, parallelism, None).map_err(hash_error)?;
+                let mut salt = [0u8; 16];
+                rng.fill_bytes(&mut salt);
+                let salt = SaltString::encode_b64(&salt).map_err(hash_error)?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password(password.as_bytes(), &salt)
+                    .map(|hash| hash.to_string())
+                    .map_err(hash_error)
+            }
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = Error;
+
+    /// Parse the name of an algorithm, with its default parameters.
+    fn from_str(name: &str) -> Result<HashAlgorithm> {
+        match name {
+            "sha512crypt" | "sha512-crypt" => Ok(HashAlgorithm::sha512_crypt()),
+            "bcrypt" => Ok(HashAlgorithm::bcrypt()),
+            "argon2id" => Ok(HashAlgorithm::argon2id()),
+            _ => Err(Error::Hash(format!("unknown hash algorithm '{}'", name))),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HashAlgorithm::Sha512Crypt { .. } => "sha512crypt",
+            HashAlgorithm::Bcrypt { .. } => "bcrypt",
+            HashAlgorithm::Argon2id { .. } => "argon2id",
+        })
+    }
+}
+
+/// Whether `hash`, a sha512-crypt, bcrypt or Argon2id hash, is a hash of `password`.
+pub fn verify(password: &str, hash: &str) -> Result<bool> {
+    if hash.starts_with("$6$") {
+        Ok(pwhash::sha512_crypt::verify(password, hash))
+    } else if ["$2a$", "$2b$", "$2y$"].iter().any(|p| hash.starts_with(p)) {
+        Ok(pwhash::bcrypt::verify(password, hash))
+    } else if hash.starts_with("$argon2") {
+        let hash = PasswordHash::new(hash).map_err(hash_error)?;
+        Ok(Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok())
+    } else {
+        Err(Error::Hash("unrecognized hash format".to_string()))
+    }
+}
+
+/// Format the lines of an Apache `.htpasswd` file for `entries` of a user name
+/// and the hash of their password.
+pub fn htpasswd(entries: &[(&str, &str)]) -> Result<String> {
+    let mut contents = String::new();
+    for (user, hash) in entries {
+        check_htpasswd_user(user)?;
+        if hash.starts_with("$argon2") {
+            return Err(htpasswd_argon2id_error());
+        }
+        contents.push_str(&format!("{}:{}\n", user, hash));
+    }
+    Ok(contents)
+}
+
+/// Check that an Apache `.htpasswd` file can hold hashes from `algorithm` for
+/// `users`, so that the errors of `htpasswd` can be reported before any
+/// passwords are generated.
+pub fn check_htpasswd<S: AsRef<str>>(algorithm: HashAlgorithm, users: &[S]) -> Result<()> {
+    if let HashAlgorithm::Argon2id { .. } = algorithm {
+        return Err(htpasswd_argon2id_error());
+    }
+    users
+        .iter()
+        .try_for_each(|user| check_htpasswd_user(user.as_ref()))
+}
+
+fn check_htpasswd_user(user: &str) -> Result<()> {
+    if user.is_empty() || user.contains(|c: char| c == ':' || c.is_control()) {
+        return Err(Error::Hash(format!(
+            "'{}' is not a valid htpasswd user name",
+            user.escape_default()
+        )));
+    }
+    Ok(())
+}
+
+fn htpasswd_argon2id_error() -> Error {
+    Error::Hash("htpasswd files do not support argon2id".to_string())
+}
+
+fn crypt_salt(rng: &mut dyn RngCore, length: usize) -> String {
+    (0..length)
+        .map(|_| *CRYPT_ALPHABET.choose(rng).unwrap_or(&b'.') as char)
+        .collect()
+}
+
+fn hash_error<E: fmt::Display>(error: E) -> Error {
+    Error::Hash(error.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    /// Parameters that are cheap enough for tests, but not for real passwords.
+    const FAST: [HashAlgorithm; 3] = [
+        HashAlgorithm::Sha512Crypt { rounds: 1000 },
+        HashAlgorithm::Bcrypt { cost: 4 },
+        HashAlgorithm::Argon2id {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        },
+    ];
+
+    #[test]
+    fn test_hash_and_verify() {
+        for algorithm in FAST.iter() {
+            let hash = algorithm.hash("correct horse").unwrap();
+            assert_eq!(verify("correct horse", &hash), Ok(true), "{}", hash);
+            assert_eq!(verify("correct horse!", &hash), Ok(false), "{}", hash);
+            assert_ne!(algorithm.hash("correct horse").unwrap(), hash);
+        }
+        assert!(matches!(verify("a", "plaintext"), Err(Error::Hash(_))));
+    }
+
+    #[test]
+    fn test_hash_formats() {
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+        let sha512 = HashAlgorithm::sha512_crypt()
+            .hash_with_rng(&mut rng, "password")
+            .unwrap();
+        assert!(
+            sha512.starts_with("$6$") && !sha512.contains("rounds="),
+            "{}",
+            sha512
+        );
+        assert_eq!(sha512.split('$').nth(2).unwrap().len(), 16);
+        let rounds = FAST[0].hash_with_rng(&mut rng, "password").unwrap();
+        assert!(rounds.starts_with("$6$rounds=1000$"), "{}", rounds);
+        let bcrypt = FAST[1].hash_with_rng(&mut rng, "password").unwrap();
+        assert!(
+            bcrypt.starts_with("$2y$04$") && bcrypt.len() == 60,
+            "{}",
+            bcrypt
+        );
+        let argon2 = FAST[2].hash_with_rng(&mut rng, "password").unwrap();
+        assert!(
+            argon2.starts_with("$argon2id$v=19$m=64,t=1,p=1$"),
+            "{}",
+            argon2
+        );
+
+        // known hashes, from the reference implementations
+        assert_eq!(
+            verify(
+                "password",
+                "$2y$05$bvIG6Nmid91Mu9RcmmWZfO5HJIMCT8riNW0hEp8f6/FuA2/mHZFpe"
+            ),
+            Ok(true)
+        );
+        assert_eq!(
+            verify(
+                "Hello world!",
+                "$6$saltstring$svn8UoSVapNtMuq1ukKS4tPQd8iKwSMHWjl/O817G3uBnIFNjnQJuesI68u4OTLiBFdcbYEdFCoEOfaS35inz1"
+            ),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn test_htpasswd() {
+        assert_eq!(
+            htpasswd(&[("alice", "$2y$04$abc"), ("bob", "$6$def")]),
+            Ok("alice:$2y$04$abc\nbob:$6$def\n".to_string())
+        );
+        assert!(matches!(
+            htpasswd(&[("a:b", "$2y$04$abc")]),
+            Err(Error::Hash(_))
+        ));
+        assert!(matches!(
+            htpasswd(&[("alice", "$argon2id$v=19$")]),
+            Err(Error::Hash(_))
+        ));
+        assert_eq!(check_htpasswd(HashAlgorithm::bcrypt(), &["alice"]), Ok(()));
+        assert!(matches!(
+            check_htpasswd(HashAlgorithm::bcrypt(), &["alice", "a:b"]),
+            Err(Error::Hash(_))
+        ));
+        assert!(matches!(
+            check_htpasswd(HashAlgorithm::argon2id(), &["alice"]),
+            Err(Error::Hash(_))
+        ));
+    }
+
+    #[test]
+    fn test_algorithm_names() {
+        for name in ["sha512crypt", "bcrypt", "argon2id"].iter() {
+            assert_eq!(name.parse::<HashAlgorithm>().unwrap().to_string(), *name);
+        }
+        assert!("md5".parse::<HashAlgorithm>().is_err());
+    }
+}
@@ -1,31 +1,181 @@
+pub mod breach;
+pub mod config;
+pub mod deterministic;
+pub mod error;
 pub mod generators;
+pub mod hash;
+pub mod output;
+pub mod password_rules;
+pub mod pipeline;
+pub mod presets;
+pub mod provision;
+pub mod pwquality;
+pub mod spec;
+pub mod strength;
+pub mod version;
 
-pub use generators::base::{ChainedGenerator, Constant, PasswordGenerator};
-pub use generators::case::Case;
+pub use error::Error;
+
+pub use generators::base::{
+    any_of, ChainedGenerator, Constant, PasswordGenerator, RandomSurround, Stage, Surround, Switch,
+};
+pub use generators::case::{Case, RandomCase};
 pub use generators::defects::Defects;
-pub use generators::phrase::{RandomPhrases, RandomWords, Text};
+pub use generators::filter::Filter;
+pub use generators::phrase::{RandomPhrases, RandomWords, Text, TextStatistics};
+pub use generators::policy::{CharacterClass, CharacterSet, Policy, Violation, WithPolicy};
 pub use generators::random_string::RandomString;
+pub use generators::separator::Separator;
+pub use presets::{PhraseOptions, XkcdOptions};
+pub use pwquality::PwQuality;
+pub use version::AlgorithmVersion;
 
 pub fn phrase_passwords<'a>(text: Option<&Text>) -> ChainedGenerator<'a> {
-    let text = text.unwrap_or_else(|| &Text::THE_TIME_MACHINE);
-    Constant::empty()
-        .pipe(RandomPhrases::from_text(text, 3, 5))
-        .pipe(Case::Class)
-        .pipe(RandomString::digits(2))
-        .pipe(Defects::with_symbols(1, 1))
-        .pipe(Defects::with_vowels(1, 1))
+    phrase_passwords_version(text, AlgorithmVersion::LATEST)
+}
+
+/// Create the generator of `phrase_passwords` with the algorithms of `version`.
+///
+/// Panics if `text` has no phrases of three to five words; `PhraseOptions::build`
+/// returns the error instead.
+pub fn phrase_passwords_version<'a>(
+    text: Option<&Text>,
+    version: AlgorithmVersion,
+) -> ChainedGenerator<'a> {
+    PhraseOptions::default()
+        .build(text, version)
+        .unwrap_or_else(|e| panic!("{}", e))
 }
 
+/// Create a generator of four random words from `text`, or either The Time
+/// Machine or the nouns, with the default `XkcdOptions`.
+///
+/// Panics if no words of `text` are long enough; `XkcdOptions::build` returns
+/// the error instead.
 pub fn xkcd_passwords<'a>(text: Option<&Text>) -> ChainedGenerator<'a> {
-    if let Some(text) = text {
-        Constant::empty().pipe(RandomWords::from_text(text, 4, 4))
+    xkcd_passwords_version(text, AlgorithmVersion::LATEST)
+}
+
+/// Create the generator of `xkcd_passwords` with the algorithms of `version`.
+///
+/// Panics if no words of `text` are long enough; `XkcdOptions::build` returns
+/// the error instead.
+pub fn xkcd_passwords_version<'a>(
+    text: Option<&Text>,
+    version: AlgorithmVersion,
+) -> ChainedGenerator<'a> {
+    XkcdOptions::default()
+        .build(text, version)
+        .unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Create a generator whose passwords satisfy `policy`.
+///
+/// When the policy allows mixed case letters and long passwords, this generates
+/// class-cased nouns with digits and symbol defects as the policy allows, and
+/// otherwise random strings of the allowed ascii characters. The number and
+/// lengths of the nouns are chosen so that the nouns and digits fit within the
+/// maximum length. Candidates are repaired to satisfy the policy.
+pub fn policy_passwords<'a>(policy: &Policy) -> WithPolicy<'a> {
+    policy_passwords_version(policy, AlgorithmVersion::LATEST)
+}
+
+/// Create the generator of `policy_passwords` with the algorithms of `version`.
+pub fn policy_passwords_version<'a>(policy: &Policy, version: AlgorithmVersion) -> WithPolicy<'a> {
+    let min_length = policy.min_length().unwrap_or(0);
+    let allows_words = policy.allows_class(CharacterClass::Lower)
+        && policy.allows_class(CharacterClass::Upper)
+        && policy
+            .max_length()
+            .is_none_or(|max_length| max_length >= 24);
+
+    if allows_words {
+        let digits = if policy.allows_class(CharacterClass::Digit) {
+            2
+        } else {
+            0
+        };
+        let n_words = std::cmp::max(4, min_length.div_ceil(6));
+        let words = match policy.max_length() {
+            Some(max_length) => {
+                // words of between min_word_length and max_word_length letters
+                // reach the minimum length and fit within the maximum length
+                let budget = max_length - digits;
+                let n_words = std::cmp::min(n_words, budget / 5);
+                let max_word_length = budget / n_words;
+                let min_word_length =
+                    std::cmp::max(5, (min_length.saturating_sub(digits)).div_ceil(n_words));
+                RandomWords::from_text_version(
+                    &Text::NOUNS,
+                    n_words,
+                    std::cmp::min(min_word_length, max_word_length),
+                    version,
+                )
+                .with_max_word_length(max_word_length)
+            }
+            None => RandomWords::from_text_version(&Text::NOUNS, n_words, 5, version),
+        };
+        let mut generator = Constant::empty().pipe(words).pipe(Case::Class);
+        if digits > 0 {
+            generator = generator.pipe(RandomString::digits(digits));
+        }
+        if policy.requires(CharacterClass::Symbol) {
+            generator = generator.pipe(Defects::with_symbols_version(1, 1, version));
+        }
+        generator.with_policy(policy.clone()).with_repair()
     } else {
-        Constant::empty().pipe(
-            RandomWords::from_text(&Text::NOUNS, 4, 4).or(RandomWords::from_text(
-                &Text::THE_TIME_MACHINE,
-                4,
-                5,
-            )),
-        )
+        let characters: Vec<char> = CharacterClass::ALL
+            .iter()
+            .flat_map(|class| class.characters().chars())
+            .filter(|c| policy.allows(*c))
+            .collect();
+        let length = std::cmp::max(min_length, 16);
+        let length = policy
+            .max_length()
+            .map_or(length, |max| std::cmp::min(length, max));
+        RandomString::new(length)
+            .with_characters(characters)
+            .with_policy(policy.clone())
+            .with_repair()
     }
 }
+
+/// Create a generator whose passwords pass the checks of `pwquality` for the
+/// user `user`, if it is known, so that `passwd` accepts them.
+///
+/// This generates the passwords of `policy_passwords` for the `PwQuality::policy`
+/// of `pwquality`, or random strings if `maxclassrepeat` is too small for words,
+/// and regenerates those that fail the remaining checks.
+pub fn pwquality_passwords<'a>(pwquality: &PwQuality, user: Option<&str>) -> Filter<'a> {
+    pwquality_passwords_version(pwquality, user, AlgorithmVersion::LATEST)
+}
+
+/// Create the generator of `pwquality_passwords` with the algorithms of `version`.
+pub fn pwquality_passwords_version<'a>(
+    pwquality: &PwQuality,
+    user: Option<&str>,
+    version: AlgorithmVersion,
+) -> Filter<'a> {
+    let policy = pwquality.policy();
+    let allows_words = pwquality.max_class_repeat == 0 || pwquality.max_class_repeat >= 8;
+    let generator: Box<dyn PasswordGenerator> = if allows_words {
+        Box::new(policy_passwords_version(&policy, version))
+    } else {
+        let characters: Vec<char> = CharacterClass::ALL
+            .iter()
+            .flat_map(|class| class.characters().chars())
+            .collect();
+        let length = std::cmp::max(policy.min_length().unwrap_or(0), 16);
+        Box::new(
+            RandomString::new(length)
+                .with_characters(characters)
+                .with_policy(policy)
+                .with_repair(),
+        )
+    };
+    let pwquality = pwquality.clone();
+    let user = user.map(str::to_string);
+    Filter::new(generator, "pwquality", move |password: &str| {
+        pwquality.is_satisfied_by(password, user.as_deref())
+    })
+}
@@ -1,22 +1,126 @@
+use std::cell::OnceCell;
+use std::convert::TryFrom;
+use std::env;
 use std::fs;
+use std::path::PathBuf;
 use std::str::FromStr;
 
 use anyhow::{anyhow, Error, Result};
+use rand::RngCore;
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
 
-use passwords::{phrase_passwords, xkcd_passwords, ChainedGenerator, PasswordGenerator, Text};
+use passwords::breach::{BreachCorpus, HashFile};
+use passwords::config::{self, Config, Profile};
+use passwords::deterministic::{Derivation, Kdf, Site};
+use passwords::hash::{self, HashAlgorithm};
+use passwords::output::{self, Format, Record};
+use passwords::provision::{self, Provisioner};
+use passwords::spec::PipelineSpec;
+use passwords::strength::{self, AttackModel};
+use passwords::{password_rules, pipeline, policy_passwords_version, Case};
+use passwords::{pwquality_passwords_version, PwQuality};
+use passwords::{AlgorithmVersion, PhraseOptions, XkcdOptions};
+use passwords::{PasswordGenerator, RandomPhrases, RandomWords, Text};
 
 /// A tool for generating memorable, high entropy passwords
+///
+/// The options of a subcommand come after it, e.g. `passwords entropy -g xkcd`,
+/// and a subcommand after options, e.g. `passwords -g xkcd entropy`, is an error.
 #[derive(StructOpt, Debug)]
-#[structopt(name = "passwords")]
+#[structopt(name = "passwords", setting = AppSettings::ArgsNegateSubcommands)]
 struct Opt {
+    #[structopt(flatten)]
+    generate: GenerateOpt,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+// The options of the `generate` subcommand, which are also accepted without a
+// subcommand.
+#[derive(StructOpt, Debug)]
+struct GenerateOpt {
     /// The number of password samples to generate.
     #[structopt(short, long, default_value = "1")]
     n_samples: usize,
 
-    /// The type of generator to use to generate passwords.
-    #[structopt(short, long, default_value = "phrases")]
-    generator_type: GeneratorType,
+    /// Describe the generator's pipeline and show the output of each of its
+    /// stages for a single sample, rather than generating passwords.
+    #[structopt(short, long)]
+    explain: bool,
+
+    /// Derive the password for this site deterministically from a master
+    /// passphrase, which is read from the PASSWORDS_MASTER environment
+    /// variable or prompted for.
+    #[structopt(long)]
+    site: Option<String>,
+
+    /// The login for the site.
+    #[structopt(long, default_value = "")]
+    login: String,
+
+    /// The counter for the site, incremented to change its password.
+    #[structopt(long, default_value = "1")]
+    counter: u32,
+
+    /// The key derivation function for the site: argon2id or scrypt.
+    #[structopt(long, default_value = "argon2id")]
+    kdf: KdfType,
+
+    /// Output the hash of each password with this algorithm after the
+    /// password: sha512crypt, bcrypt or argon2id.
+    #[structopt(long)]
+    hash: Option<HashAlgorithm>,
+
+    /// Output only the hash of each password, rather than the password.
+    #[structopt(long, requires = "hash", conflicts_with = "format")]
+    hash_only: bool,
+
+    /// Output a record of each password, with its pipeline, text, entropy and
+    /// the output of each stage, in this format: json, ndjson or csv.
+    #[structopt(long, parse(try_from_str = parse_format))]
+    format: Option<Format>,
+
+    /// Write an .htpasswd file to this path with a new password for each of
+    /// --users, hashed with --hash or bcrypt, and output each user's password.
+    #[structopt(long, requires = "users")]
+    htpasswd: Option<PathBuf>,
+
+    /// The comma separated users of --htpasswd.
+    #[structopt(long, use_delimiter = true)]
+    users: Vec<String>,
+
+    #[structopt(flatten)]
+    generator: GeneratorOpt,
+}
+
+// The options that choose a generator, shared by `generate`, `entropy` and
+// `provision`, which each flatten them.
+#[derive(StructOpt, Debug)]
+struct GeneratorOpt {
+    /// A profile of the configuration file, or the built-in phrases or xkcd
+    /// profile. Without one, the profile named default is used if the
+    /// configuration file has one and no other options choose a generator.
+    #[structopt(
+        short = "P",
+        long,
+        conflicts_with_all = &[
+            "generator-type", "filename", "text", "rules", "pwquality", "spec", "pipeline",
+            "min-words", "max-words", "digits", "symbol-defects", "vowel-defects", "case",
+            "words", "min-word-length",
+        ]
+    )]
+    profile: Option<String>,
+
+    /// The configuration file of the profiles [default:
+    /// $XDG_CONFIG_HOME/passwords/config.toml or ~/.config/passwords/config.toml].
+    #[structopt(long)]
+    config: Option<PathBuf>,
+
+    /// The type of generator to use to generate passwords [default: phrases].
+    #[structopt(short, long)]
+    generator_type: Option<GeneratorType>,
 
     /// An optional source text filename.
     #[structopt(short, long)]
@@ -25,11 +129,245 @@ struct Opt {
     /// An optional specification of an existing text
     #[structopt(short, long)]
     text: Option<TextType>,
+
+    /// The minimum number of words of the phrases of the phrases generator [default: 3].
+    #[structopt(long, conflicts_with_all = &["rules", "pwquality", "spec", "pipeline"])]
+    min_words: Option<usize>,
+
+    /// The maximum number of words of the phrases of the phrases generator [default: 5].
+    #[structopt(long, conflicts_with_all = &["rules", "pwquality", "spec", "pipeline"])]
+    max_words: Option<usize>,
+
+    /// The number of digits after the phrase of the phrases generator [default: 2].
+    #[structopt(long, conflicts_with_all = &["rules", "pwquality", "spec", "pipeline"])]
+    digits: Option<usize>,
+
+    /// The number of letters that the phrases generator replaces with similar
+    /// symbols [default: 1].
+    #[structopt(long, conflicts_with_all = &["rules", "pwquality", "spec", "pipeline"])]
+    symbol_defects: Option<usize>,
+
+    /// The number of vowels that the phrases generator replaces with other
+    /// vowels [default: 1].
+    #[structopt(long, conflicts_with_all = &["rules", "pwquality", "spec", "pipeline"])]
+    vowel_defects: Option<usize>,
+
+    /// The case of the words of the phrases generator, e.g. class, camel,
+    /// kebab, snake or title [default: class].
+    #[structopt(long, conflicts_with_all = &["rules", "pwquality", "spec", "pipeline"])]
+    case: Option<Case>,
+
+    /// The number of words of the xkcd generator [default: 4].
+    #[structopt(long, conflicts_with_all = &["rules", "pwquality", "spec", "pipeline"])]
+    words: Option<usize>,
+
+    /// The minimum length of the words of the xkcd generator [default: 5 for
+    /// The Time Machine and 4 for the nouns or another text].
+    #[structopt(long, conflicts_with_all = &["rules", "pwquality", "spec", "pipeline"])]
+    min_word_length: Option<usize>,
+
+    /// An optional `passwordrules` string that passwords must satisfy, e.g.
+    /// "minlength: 20; required: lower; required: upper; required: digit".
+    #[structopt(short, long)]
+    rules: Option<String>,
+
+    /// An optional pwquality.conf file, e.g. /etc/security/pwquality.conf, whose
+    /// checks passwords must pass.
+    #[structopt(long)]
+    pwquality: Option<PathBuf>,
+
+    /// The user name for the usercheck of --pwquality.
+    #[structopt(long)]
+    user: Option<String>,
+
+    /// An optional TOML or JSON pipeline specification file, which is used
+    /// instead of the generator type.
+    #[structopt(short, long)]
+    spec: Option<String>,
+
+    /// An optional pipeline expression, which is used instead of the generator
+    /// type, e.g. "phrases(the-time-machine,3,5) | case(class) | digits(2)".
+    #[structopt(short, long)]
+    pipeline: Option<String>,
+
+    /// The version of the generation algorithms, to regenerate passwords that
+    /// were generated by an older release. Defaults to the latest version, or
+    /// to the version of the pipeline specification. Also applies to profiles.
+    #[structopt(long)]
+    algorithm_version: Option<u32>,
+
+    /// Regenerate passwords until an attacker is estimated to need at least
+    /// this many guesses, e.g. 1e12.
+    #[structopt(long, parse(try_from_str = parse_min_guesses))]
+    min_guesses: Option<f64>,
+
+    /// Regenerate passwords that appear in this Pwned Passwords SHA-1 file or
+    /// binary index.
+    #[structopt(long)]
+    hibp_file: Option<PathBuf>,
+
+    /// Regenerate passwords that appear in Pwned Passwords, looked up with the
+    /// range API, which is only sent the first 5 digits of their SHA-1 hashes.
+    #[cfg(feature = "hibp-api")]
+    #[structopt(long)]
+    hibp_api: bool,
+
+    /// The profile of `get_profile`, once it has been loaded.
+    #[structopt(skip)]
+    loaded_profile: OnceCell<Option<(String, Profile)>>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Generate passwords, as when no subcommand is given.
+    Generate(GenerateOpt),
+    /// Estimate the strength of a password.
+    Check {
+        /// The password to check, which is prompted for if it is not given.
+        password: Option<String>,
+
+        /// A pwquality.conf file whose checks the password must pass.
+        #[structopt(long)]
+        pwquality: Option<PathBuf>,
+
+        /// The user name for the usercheck of --pwquality.
+        #[structopt(long)]
+        user: Option<String>,
+
+        /// A Pwned Passwords SHA-1 file or binary index to look the password up in.
+        #[structopt(long)]
+        hibp_file: Option<PathBuf>,
+
+        /// Look the password up with the Pwned Passwords range API.
+        #[cfg(feature = "hibp-api")]
+        #[structopt(long)]
+        hibp_api: bool,
+    },
+    /// Print the entropy, in bits, of the generator of the options.
+    Entropy {
+        /// Also print the entropy of each stage of the pipeline.
+        #[structopt(long)]
+        stages: bool,
+
+        #[structopt(flatten)]
+        generator: GeneratorOpt,
+    },
+    /// List the built-in texts, the text files of the profiles of the
+    /// configuration file and the text files given, with the statistics of their
+    /// words and phrases.
+    Texts {
+        /// Text files to list after the built-in texts.
+        files: Vec<PathBuf>,
+
+        /// The configuration file of the profiles [default:
+        /// $XDG_CONFIG_HOME/passwords/config.toml or ~/.config/passwords/config.toml].
+        #[structopt(long)]
+        config: Option<PathBuf>,
+    },
+    /// Export the distinct words of a text, sorted, one per line.
+    Wordlist {
+        /// A built-in text [default: the-time-machine].
+        #[structopt(short, long, conflicts_with = "filename")]
+        text: Option<TextType>,
+
+        /// A text file.
+        #[structopt(short, long)]
+        filename: Option<PathBuf>,
+
+        /// The minimum number of characters of a word.
+        #[structopt(long, default_value = "1")]
+        min_length: usize,
+
+        /// The maximum number of characters of a word.
+        #[structopt(long)]
+        max_length: Option<usize>,
+
+        /// Leave out words with digits.
+        #[structopt(long)]
+        alphabetic: bool,
+
+        /// The file to write the words to, rather than standard output.
+        #[structopt(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Build a compact binary index of a Pwned Passwords SHA-1 file, ordered by
+    /// hash, for use with --hibp-file.
+    HibpIndex {
+        /// The Pwned Passwords SHA-1 text file.
+        input: PathBuf,
+        /// The binary index to create.
+        output: PathBuf,
+    },
+    /// Generate a unique password for each account of a CSV file with a
+    /// `username` column and optional `pipeline` and `policy` columns, with the
+    /// generator of the generator options for rows without either.
+    Provision {
+        /// The CSV file of accounts.
+        input: PathBuf,
+
+        /// The file to write the accounts to, rather than standard output.
+        #[structopt(short, long)]
+        output: Option<PathBuf>,
+
+        /// The format of the accounts: csv, json or ndjson.
+        #[structopt(long, default_value = "csv", parse(try_from_str = parse_format))]
+        format: Format,
+
+        /// Add the hash of each password with this algorithm: sha512crypt,
+        /// bcrypt or argon2id.
+        #[structopt(long)]
+        hash: Option<HashAlgorithm>,
+
+        /// Write the user names and passwords to this file, readable only by its
+        /// owner, and leave the passwords out of the accounts.
+        #[structopt(long)]
+        passwords_file: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        generator: GeneratorOpt,
+    },
+}
+
+fn parse_format(input: &str) -> Result<Format> {
+    match input {
+        "csv" => Ok(Format::Csv),
+        "json" => Ok(Format::Json),
+        "ndjson" => Ok(Format::Ndjson),
+        _ => Err(anyhow!("Did not recognize '{}' as an output format", input)),
+    }
+}
+
+fn parse_min_guesses(input: &str) -> Result<f64> {
+    match input.parse::<f64>() {
+        Ok(guesses) if guesses.is_finite() && guesses > 0.0 => Ok(guesses),
+        _ => Err(anyhow!("'{}' is not a positive number of guesses", input)),
+    }
 }
 
 #[derive(Debug)]
+enum KdfType {
+    Argon2id,
+    Scrypt,
+}
+
+impl FromStr for KdfType {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input {
+            "argon2id" => Ok(KdfType::Argon2id),
+            "scrypt" => Ok(KdfType::Scrypt),
+            _ => Err(anyhow!(
+                "Did not recognize '{}' as a key derivation function",
+                input
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 enum GeneratorType {
-    XKCD,
+    Xkcd,
     Phrases,
 }
 
@@ -38,7 +376,7 @@ impl FromStr for GeneratorType {
 
     fn from_str(input: &str) -> Result<Self> {
         match input {
-            "xkcd" => Ok(GeneratorType::XKCD),
+            "xkcd" => Ok(GeneratorType::Xkcd),
             "phrases" => Ok(GeneratorType::Phrases),
             _ => Err(anyhow!("Did not recognize '{}' as a generator type", input)),
         }
@@ -66,7 +404,21 @@ impl FromStr for TextType {
 }
 
 impl TextType {
-    fn to_text(&self) -> Text {
+    const ALL: [TextType; 3] = [
+        TextType::Nouns,
+        TextType::AliceInWonderland,
+        TextType::TheTimeMachine,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            TextType::Nouns => "nouns",
+            TextType::AliceInWonderland => "alice-in-wonderland",
+            TextType::TheTimeMachine => "the-time-machine",
+        }
+    }
+
+    fn to_text(&self) -> Text<'static> {
         match self {
             TextType::Nouns => Text::NOUNS,
             TextType::AliceInWonderland => Text::ALICE_IN_WONDERLAND,
@@ -75,34 +427,611 @@ impl TextType {
     }
 }
 
-impl Opt {
-    fn get_generator<'a>(&'a self) -> Result<ChainedGenerator<'a>> {
+impl GeneratorOpt {
+    /// The profile of --profile, or the default profile of the configuration file
+    /// if no other options choose a generator, and its name. The configuration
+    /// file is read at most once.
+    fn get_profile(&self) -> Result<Option<(String, Profile)>> {
+        if let Some(profile) = self.loaded_profile.get() {
+            return Ok(profile.clone());
+        }
+        let profile = self.load_profile()?;
+        Ok(self.loaded_profile.get_or_init(|| profile).clone())
+    }
+
+    /// Load the profile of `get_profile`, reading the configuration file only
+    /// if a profile may be used.
+    fn load_profile(&self) -> Result<Option<(String, Profile)>> {
+        let name = match self.profile {
+            Some(ref name) => name.as_str(),
+            None if self.chooses_generator() => return Ok(None),
+            None => config::DEFAULT_PROFILE,
+        };
+        let config = match self.config {
+            Some(ref path) => Some(Config::load(path)?),
+            None => Config::load_default()?,
+        };
+        let profile = config
+            .as_ref()
+            .and_then(|config| config.profile(name).cloned())
+            .or_else(|| Profile::builtin(name));
+        match profile {
+            Some(profile) => Ok(Some((name.to_string(), profile))),
+            None if self.profile.is_none() => Ok(None),
+            None => Err(anyhow!(
+                "There is no profile '{}' in {}",
+                name,
+                self.config
+                    .clone()
+                    .or_else(config::default_path)
+                    .map_or("the configuration".to_string(), |path| path
+                        .display()
+                        .to_string())
+            )),
+        }
+    }
+
+    /// Whether any options other than --profile choose the generator.
+    fn chooses_generator(&self) -> bool {
+        self.generator_type.is_some()
+            || self.filename.is_some()
+            || self.text.is_some()
+            || self.rules.is_some()
+            || self.pwquality.is_some()
+            || self.spec.is_some()
+            || self.pipeline.is_some()
+            || self.min_words.is_some()
+            || self.max_words.is_some()
+            || self.digits.is_some()
+            || self.symbol_defects.is_some()
+            || self.vowel_defects.is_some()
+            || self.case.is_some()
+            || self.words.is_some()
+            || self.min_word_length.is_some()
+    }
+
+    fn get_generator<'a>(&'a self) -> Result<Box<dyn PasswordGenerator + 'a>> {
+        let version = self
+            .algorithm_version
+            .map(AlgorithmVersion::try_from)
+            .transpose()?;
+        if let Some((_, profile)) = self.get_profile()? {
+            return Ok(profile.build(version)?);
+        }
+        if let Some(ref rules) = self.rules {
+            let policy = password_rules::parse(rules)?;
+            let version = version.unwrap_or_default();
+            return Ok(Box::new(policy_passwords_version(&policy, version)));
+        }
+        if let Some(ref path) = self.pwquality {
+            let pwquality = PwQuality::load(path)?;
+            let version = version.unwrap_or_default();
+            let user = self.user.as_deref();
+            return Ok(Box::new(pwquality_passwords_version(
+                &pwquality, user, version,
+            )));
+        }
+        if let Some(ref expression) = self.pipeline {
+            let mut spec = pipeline::parse(expression)
+                .map_err(|e| anyhow!("{}", pipeline::annotate(expression, &e)))?;
+            spec.version = version.or(spec.version);
+            return Ok(spec.build()?);
+        }
+        if let Some(ref spec) = self.spec {
+            let mut spec = PipelineSpec::load(spec)?;
+            spec.version = version.or(spec.version);
+            return Ok(spec.build()?);
+        }
+
         let contents = self
             .filename
             .as_ref()
-            .map(|f| fs::read_to_string(f))
+            .map(fs::read_to_string)
             .map_or(Ok(None), |v| v.map(Some))?;
 
         let text = if let Some(ref contents) = contents {
             Some(Text::new(contents))
-        } else if let Some(ref text) = self.text {
-            Some(text.to_text())
         } else {
-            None
+            self.text.as_ref().map(TextType::to_text)
+        };
+
+        let phrase_knobs = [
+            ("min-words", self.min_words.is_some()),
+            ("max-words", self.max_words.is_some()),
+            ("digits", self.digits.is_some()),
+            ("symbol-defects", self.symbol_defects.is_some()),
+            ("vowel-defects", self.vowel_defects.is_some()),
+            ("case", self.case.is_some()),
+        ];
+        let xkcd_knobs = [
+            ("words", self.words.is_some()),
+            ("min-word-length", self.min_word_length.is_some()),
+        ];
+        let generator_type = self.generator_type.unwrap_or(GeneratorType::Phrases);
+        let (name, other_knobs): (&str, &[(&str, bool)]) = match generator_type {
+            GeneratorType::Xkcd => ("xkcd", &phrase_knobs),
+            GeneratorType::Phrases => ("phrases", &xkcd_knobs),
+        };
+        if let Some((knob, _)) = other_knobs.iter().find(|(_, given)| *given) {
+            return Err(anyhow!(
+                "--{} does not apply to the {} generator",
+                knob,
+                name
+            ));
+        }
+        for (knob, value) in [("min-words", self.min_words), ("words", self.words)].iter() {
+            if *value == Some(0) {
+                return Err(anyhow!("--{} must be at least 1", knob));
+            }
+        }
+
+        match generator_type {
+            GeneratorType::Xkcd => {
+                let defaults = XkcdOptions::default();
+                let options = XkcdOptions {
+                    words: self.words.unwrap_or(defaults.words),
+                    min_word_length: self.min_word_length.or(defaults.min_word_length),
+                };
+                Ok(Box::new(
+                    options.build(text.as_ref(), version.unwrap_or_default())?,
+                ))
+            }
+            GeneratorType::Phrases => {
+                let defaults = PhraseOptions::default();
+                let options = PhraseOptions {
+                    min_words: self.min_words.unwrap_or(defaults.min_words),
+                    max_words: self.max_words.unwrap_or(defaults.max_words),
+                    case: self.case.unwrap_or(defaults.case),
+                    digits: self.digits.unwrap_or(defaults.digits),
+                    symbol_defects: self.symbol_defects.unwrap_or(defaults.symbol_defects),
+                    vowel_defects: self.vowel_defects.unwrap_or(defaults.vowel_defects),
+                };
+                Ok(Box::new(
+                    options.build(text.as_ref(), version.unwrap_or_default())?,
+                ))
+            }
+        }
+    }
+
+    /// The name of the pipeline of `get_generator` and the texts that it uses,
+    /// for the records of --format.
+    fn get_source(&self) -> Result<(String, Option<String>)> {
+        if let Some((name, profile)) = self.get_profile()? {
+            let text = match (&profile.pipeline, &profile.spec) {
+                (Some(preset), _) if preset == "phrases" || preset == "xkcd" => Some(
+                    profile
+                        .text_spec()
+                        .map_or_else(|| default_text(preset), |text| text.to_string()),
+                ),
+                (Some(expression), _) => spec_texts(&pipeline::parse(expression)?),
+                (None, Some(path)) => spec_texts(&PipelineSpec::load(path)?),
+                (None, None) if profile.policy.is_some() => None,
+                (None, None) => Some(
+                    profile
+                        .text_spec()
+                        .map_or_else(|| default_text("phrases"), |text| text.to_string()),
+                ),
+            };
+            return Ok((name, text));
+        }
+        if self.rules.is_some() {
+            return Ok(("rules".to_string(), None));
+        }
+        if self.pwquality.is_some() {
+            return Ok(("pwquality".to_string(), None));
+        }
+        let spec = match (&self.pipeline, &self.spec) {
+            (Some(expression), _) => Some((expression.clone(), pipeline::parse(expression)?)),
+            (None, Some(path)) => Some((path.clone(), PipelineSpec::load(path)?)),
+            (None, None) => None,
         };
+        if let Some((source, spec)) = spec {
+            let texts = spec_texts(&spec);
+            return Ok((spec.name.unwrap_or(source), texts));
+        }
 
-        match self.generator_type {
-            GeneratorType::XKCD => Ok(xkcd_passwords(text.as_ref())),
-            GeneratorType::Phrases => Ok(phrase_passwords(text.as_ref())),
+        let generator_type = self.generator_type.unwrap_or(GeneratorType::Phrases);
+        let text = match (&self.filename, &self.text) {
+            (Some(filename), _) => Some(filename.clone()),
+            (None, Some(text)) => Some(text.name().to_string()),
+            (None, None) => None,
+        };
+        let name = match generator_type {
+            GeneratorType::Xkcd => "xkcd",
+            GeneratorType::Phrases => "phrases",
+        };
+        Ok((
+            name.to_string(),
+            Some(text.unwrap_or_else(|| default_text(name))),
+        ))
+    }
+
+    /// The generator of `get_generator`, wrapped with the filters of the options.
+    fn get_filtered_generator<'a>(&'a self) -> Result<Box<dyn PasswordGenerator + 'a>> {
+        Ok(self.filter(self.get_generator()?)?)
+    }
+
+    /// Wrap `generator` with the filters of the options, `--min-guesses`,
+    /// `--hibp-file` and `--hibp-api`.
+    fn filter<'a>(
+        &self,
+        mut generator: Box<dyn PasswordGenerator + 'a>,
+    ) -> passwords::error::Result<Box<dyn PasswordGenerator + 'a>> {
+        if let Some(min_guesses) = self.min_guesses {
+            generator = Box::new(generator.with_min_guesses(min_guesses));
+        }
+        if let Some(ref path) = self.hibp_file {
+            generator = Box::new(generator.without_breached(HashFile::open(path)?));
+        }
+        #[cfg(feature = "hibp-api")]
+        if self.hibp_api {
+            let client = passwords::breach::RangeClient::pwned_passwords();
+            generator = Box::new(generator.without_breached(client));
         }
+        Ok(generator)
     }
 }
 
+impl GenerateOpt {
+    /// A random number generator derived from the master passphrase if a site is
+    /// given, and a thread-local random number generator otherwise.
+    fn get_rng(&self) -> Result<Box<dyn RngCore>> {
+        let name = match self.site {
+            Some(ref name) => name,
+            None => return Ok(Box::new(rand::thread_rng())),
+        };
+        let master = match env::var("PASSWORDS_MASTER") {
+            Ok(master) => master,
+            Err(_) => rpassword::prompt_password("Master passphrase: ")?,
+        };
+        let kdf = match self.kdf {
+            KdfType::Argon2id => Kdf::argon2id(),
+            KdfType::Scrypt => Kdf::scrypt(),
+        };
+        let site = Site::new(name)
+            .with_login(&self.login)
+            .with_counter(self.counter);
+        Ok(Box::new(Derivation::new(kdf).rng(&master, &site)?))
+    }
+}
+
+fn check(
+    password: Option<&String>,
+    pwquality: Option<(PwQuality, Option<&str>)>,
+    corpora: Vec<(String, Box<dyn BreachCorpus>)>,
+) -> Result<()> {
+    let password = match password {
+        Some(password) => password.clone(),
+        None => rpassword::prompt_password("Password: ")?,
+    };
+    let estimate = strength::estimate(&password);
+    println!(
+        "guesses: 10^{:.1} (score {}/4)",
+        estimate.guesses_log10(),
+        estimate.score()
+    );
+    println!("\ntime to crack:");
+    for model in AttackModel::ALL.iter() {
+        println!("  {}: {}", model, estimate.crack_time_display(*model));
+    }
+    println!("\nmatches:");
+    for m in estimate.matches.iter() {
+        println!("  {}", m);
+    }
+    if let Some((pwquality, user)) = pwquality {
+        let violations = pwquality.violations(&password, user);
+        if violations.is_empty() {
+            println!("\npasses the pwquality checks");
+        } else {
+            println!("\nfails the pwquality checks:");
+            for violation in violations {
+                println!("  {}", violation);
+            }
+        }
+    }
+    for (name, corpus) in corpora.iter() {
+        match corpus.count(&password)? {
+            0 => println!("\nnot found in {}", name),
+            count => println!("\nbreached: found {} times in {}", count, name),
+        }
+    }
+    Ok(())
+}
+
+/// The texts of the built-in `phrases` or `xkcd` generator.
+fn default_text(name: &str) -> String {
+    match name {
+        "xkcd" => "the-time-machine,nouns".to_string(),
+        _ => "the-time-machine".to_string(),
+    }
+}
+
+/// The texts of the stages of `spec`, separated by commas.
+fn spec_texts(spec: &PipelineSpec) -> Option<String> {
+    let texts: Vec<String> = spec.texts().iter().map(|t| t.to_string()).collect();
+    Some(texts.join(",")).filter(|texts| !texts.is_empty())
+}
+
+/// Print the statistics of the built-in texts, of the text files of the profiles
+/// of `config` and of the text `files`.
+fn texts(files: &[PathBuf], config: Option<&Config>) -> Result<()> {
+    let mut names = Vec::new();
+    let mut contents = Vec::new();
+    for (profile, path) in config.map(Config::corpora).unwrap_or_default() {
+        names.push(format!("{} ({})", path.display(), profile));
+        contents.push(fs::read_to_string(path)?);
+    }
+    for path in files.iter() {
+        names.push(path.display().to_string());
+        contents.push(fs::read_to_string(path)?);
+    }
+    let mut texts: Vec<(String, Text)> = TextType::ALL
+        .iter()
+        .map(|text| (text.name().to_string(), text.to_text()))
+        .collect();
+    for (name, contents) in names.into_iter().zip(contents.iter()) {
+        texts.push((name, Text::new(contents)));
+    }
+    println!(
+        "{:<24} {:>8} {:>10} {:>9} {:>8} {:>12} {:>11}",
+        "text", "words", "vocabulary", "bits/word", "phrases", "words/phrase", "bits/phrase"
+    );
+    for (name, text) in texts.iter() {
+        let statistics = text.statistics();
+        let phrase_entropy = RandomPhrases::from_text(text, 3, 5).entropy();
+        println!(
+            "{:<24} {:>8} {:>10} {:>9.1} {:>8} {:>12.1} {:>11.1}",
+            name,
+            statistics.words,
+            statistics.vocabulary,
+            RandomWords::from_text(text, 1, 0).entropy(),
+            statistics.phrases,
+            statistics.mean_phrase_length,
+            phrase_entropy
+        );
+    }
+    Ok(())
+}
+
 fn main() -> Result<()> {
     let opts = Opt::from_args();
-    let generator = opts.get_generator()?;
-    for password in generator.iterator().into_iter().take(opts.n_samples) {
-        println!("{}", password);
+    match opts.command {
+        Some(Command::Check {
+            ref password,
+            ref pwquality,
+            ref user,
+            ref hibp_file,
+            #[cfg(feature = "hibp-api")]
+            hibp_api,
+        }) => {
+            let mut corpora: Vec<(String, Box<dyn BreachCorpus>)> = Vec::new();
+            if let Some(path) = hibp_file {
+                corpora.push((path.display().to_string(), Box::new(HashFile::open(path)?)));
+            }
+            #[cfg(feature = "hibp-api")]
+            if hibp_api {
+                let client = passwords::breach::RangeClient::pwned_passwords();
+                corpora.push(("Pwned Passwords".to_string(), Box::new(client)));
+            }
+            let pwquality = match pwquality {
+                Some(path) => Some((PwQuality::load(path)?, user.as_deref())),
+                None => None,
+            };
+            check(password.as_ref(), pwquality, corpora)
+        }
+        Some(Command::HibpIndex {
+            ref input,
+            ref output,
+        }) => {
+            let records = HashFile::build_index(input, output)?;
+            println!("indexed {} hashes in {}", records, output.display());
+            Ok(())
+        }
+        Some(Command::Provision {
+            ref input,
+            ref output,
+            format,
+            hash,
+            ref passwords_file,
+            ref generator,
+        }) => {
+            let requests = provision::parse_csv(&fs::read_to_string(input)?)?;
+            let mut provisioner = Provisioner::new(generator.get_filtered_generator()?)
+                .with_filter(|row| generator.filter(row));
+            if let Some(version) = generator.algorithm_version {
+                provisioner = provisioner.with_version(AlgorithmVersion::try_from(version)?);
+            }
+            if let Some(algorithm) = hash {
+                provisioner = provisioner.with_hash(algorithm);
+            }
+            let accounts = provisioner.provision(&mut rand::thread_rng(), &requests)?;
+            if let Some(path) = passwords_file {
+                provision::write_private(path, &provision::passwords_csv(&accounts))?;
+            }
+            let with_passwords = passwords_file.is_none();
+            let contents = match format {
+                Format::Csv => provision::to_csv(&accounts, with_passwords),
+                Format::Json => provision::to_json(&accounts, with_passwords)? + "\n",
+                Format::Ndjson => provision::to_ndjson(&accounts, with_passwords)?,
+            };
+            match output {
+                // the output has passwords unless they were written elsewhere
+                Some(path) if with_passwords => provision::write_private(path, &contents)?,
+                Some(path) => fs::write(path, contents)?,
+                None => print!("{}", contents),
+            }
+            Ok(())
+        }
+        Some(Command::Entropy {
+            stages,
+            ref generator,
+        }) => {
+            let generator = generator.get_filtered_generator()?;
+            if stages {
+                println!("{}", generator.describe());
+            } else {
+                println!("{:.1} bits", generator.entropy());
+            }
+            Ok(())
+        }
+        Some(Command::Generate(ref generate)) => generate_passwords(generate),
+        Some(Command::Texts {
+            ref files,
+            ref config,
+        }) => {
+            let config = match config {
+                Some(path) => Some(Config::load(path)?),
+                None => Config::load_default()?,
+            };
+            texts(files, config.as_ref())
+        }
+        Some(Command::Wordlist {
+            ref text,
+            ref filename,
+            min_length,
+            max_length,
+            alphabetic,
+            ref output,
+        }) => {
+            let contents = filename.as_ref().map(fs::read_to_string).transpose()?;
+            let text = match (&contents, text) {
+                (Some(contents), _) => Text::new(contents),
+                (None, Some(text)) => text.to_text(),
+                (None, None) => Text::THE_TIME_MACHINE,
+            };
+            let words = RandomWords::from_text(&text, 1, min_length);
+            let mut wordlist = String::new();
+            for word in words.vocabulary() {
+                let too_long = max_length.is_some_and(|max| word.chars().count() > max);
+                if too_long || (alphabetic && !word.chars().all(char::is_alphabetic)) {
+                    continue;
+                }
+                wordlist.push_str(word);
+                wordlist.push('\n');
+            }
+            match output {
+                Some(path) => fs::write(path, wordlist)?,
+                None => print!("{}", wordlist),
+            }
+            Ok(())
+        }
+        None => generate_passwords(&opts.generate),
+    }
+}
+
+/// Generate passwords with the options of `generate`.
+fn generate_passwords(opts: &GenerateOpt) -> Result<()> {
+    let generator = opts.generator.get_filtered_generator()?;
+    let mut rng = opts.get_rng()?;
+    if opts.explain {
+        println!("{}\n", generator.describe());
+        for stage in generator.explain(&mut rng)? {
+            println!(
+                "{}{} [{:.1} bits]: {}",
+                "  ".repeat(stage.depth),
+                stage.description,
+                stage.entropy,
+                stage.output
+            );
+        }
+        return Ok(());
+    }
+    if let Some(ref path) = opts.htpasswd {
+        let algorithm = opts.hash.unwrap_or_else(HashAlgorithm::bcrypt);
+        hash::check_htpasswd(algorithm, &opts.users)?;
+        let mut entries = Vec::new();
+        for user in opts.users.iter() {
+            let password = generator.try_generate_with_rng(&mut rng)?;
+            entries.push((user.as_str(), algorithm.hash(&password)?));
+            println!("{}\t{}", user, password);
+        }
+        let entries: Vec<(&str, &str)> = entries
+            .iter()
+            .map(|(user, hash)| (*user, hash.as_str()))
+            .collect();
+        fs::write(path, hash::htpasswd(&entries)?)?;
+        return Ok(());
+    }
+    let format = match opts.format {
+        Some(format) => Some(format),
+        None if opts.hash_only => None,
+        None => opts
+            .generator
+            .get_profile()?
+            .and_then(|(_, profile)| profile.format),
+    };
+    if let Some(format) = format {
+        let (pipeline, text) = opts.generator.get_source()?;
+        let entropy = generator.entropy();
+        let mut records = Vec::with_capacity(opts.n_samples);
+        for _ in 0..opts.n_samples {
+            let mut record =
+                Record::generate(&generator, &mut rng, &pipeline, text.as_deref(), entropy)?;
+            if let Some(algorithm) = opts.hash {
+                record.hash = Some(algorithm.hash(&record.password)?);
+            }
+            records.push(record);
+        }
+        match format {
+            Format::Csv => print!("{}", output::to_csv(&records)),
+            Format::Json => println!("{}", output::to_json(&records)?),
+            Format::Ndjson => print!("{}", output::to_ndjson(&records)?),
+        }
+        return Ok(());
+    }
+    for _ in 0..opts.n_samples {
+        let password = generator.try_generate_with_rng(&mut rng)?;
+        match opts.hash {
+            Some(algorithm) if opts.hash_only => println!("{}", algorithm.hash(&password)?),
+            Some(algorithm) => println!("{}\t{}", password, algorithm.hash(&password)?),
+            None => println!("{}", password),
+        }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The first password of the options of `generate`, with or without the
+    /// subcommand.
+    fn generate(args: &[&str]) -> String {
+        let opts = Opt::from_iter_safe(args).unwrap();
+        let generate = match opts.command {
+            Some(Command::Generate(ref generate)) => generate,
+            None => &opts.generate,
+            Some(command) => panic!("unexpected subcommand {:?}", command),
+        };
+        let generator = generate.generator.get_filtered_generator().unwrap();
+        generator
+            .try_generate_with_rng(&mut generate.get_rng().unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_generate_subcommand() {
+        env::set_var("PASSWORDS_MASTER", "correct horse battery staple");
+        let site = ["--site", "example.com", "--kdf", "scrypt", "-g", "xkcd"];
+        let without: Vec<&str> = ["passwords"].iter().chain(site.iter()).copied().collect();
+        let with: Vec<&str> = ["passwords", "generate"]
+            .iter()
+            .chain(site.iter())
+            .copied()
+            .collect();
+        assert_eq!(generate(&without), generate(&with));
+
+        // options before a subcommand are rejected rather than ignored
+        for args in [
+            &["passwords", "--site", "example.com", "generate"][..],
+            &["passwords", "--min-guesses", "1e6", "entropy"],
+            &["passwords", "-g", "xkcd", "provision", "accounts.csv"],
+        ]
+        .iter()
+        {
+            assert!(Opt::from_iter_safe(*args).is_err(), "{:?}", args);
+        }
+        let opts = Opt::from_iter_safe(&["passwords", "provision", "accounts.csv", "-g", "xkcd"]);
+        assert!(opts.is_ok());
+    }
+}
@@ -0,0 +1,193 @@
+//! Machine-readable records of generated passwords, as JSON, newline-delimited
+//! JSON or CSV, so that scripts can consume the output without scraping it.
+
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::generators::base::{PasswordGenerator, Stage};
+
+/// A machine-readable format of `Record`s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// A JSON array, as formatted by `to_json`.
+    Json,
+    /// One JSON object per line, as formatted by `to_ndjson`.
+    Ndjson,
+    /// A header row and a row per record, as formatted by `to_csv`.
+    Csv,
+}
+
+/// A generated password, with where it came from and how it was built.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Record {
+    pub password: String,
+    /// The name of the pipeline that generated the password, e.g. `phrases`.
+    pub pipeline: String,
+    /// The text or texts that the words of the password were chosen from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// The entropy, in bits, of the pipeline.
+    pub entropy: f64,
+    /// The output and entropy of each stage of the pipeline.
+    pub stages: Vec<Stage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl Record {
+    /// Generate a password with `generator`, recording its stages with
+    /// `PasswordGenerator::explain`. `entropy` is the entropy of `generator`,
+    /// which is passed in so that it is only estimated once for many records.
+    pub fn generate(
+        generator: &dyn PasswordGenerator,
+        rng: &mut dyn RngCore,
+        pipeline: &str,
+        text: Option<&str>,
+        entropy: f64,
+    ) -> Result<Record> {
+        let stages = generator.explain(rng)?;
+        Ok(Record {
+            password: stages.last().map(|s| s.output.clone()).unwrap_or_default(),
+            pipeline: pipeline.to_string(),
+            text: text.map(str::to_string),
+            entropy,
+            stages,
+            hash: None,
+        })
+    }
+}
+
+/// Format `records` as a JSON array.
+pub fn to_json(records: &[Record]) -> serde_json::Result<String> {
+    serde_json::to_string_pretty(records)
+}
+
+/// Format `records` as newline-delimited JSON, one object per line.
+pub fn to_ndjson(records: &[Record]) -> serde_json::Result<String> {
+    let mut ndjson = String::new();
+    for record in records.iter() {
+        ndjson.push_str(&serde_json::to_string(record)?);
+        ndjson.push('\n');
+    }
+    Ok(ndjson)
+}
+
+/// Format `records` as CSV, with a `hash` column if any record has a hash.
+///
+/// The stages are joined into a single column with ` | `, each formatted as
+/// `description [entropy bits]: output`, as `--explain` prints them.
+pub fn to_csv(records: &[Record]) -> String {
+    let hashes = records.iter().any(|record| record.hash.is_some());
+    let mut csv = "password,pipeline,text,entropy,stages".to_string();
+    if hashes {
+        csv.push_str(",hash");
+    }
+    csv.push('\n');
+    for record in records.iter() {
+        let stages: Vec<String> = record
+            .stages
+            .iter()
+            .map(|stage| {
+                format!(
+                    "{} [{:.1} bits]: {}",
+                    stage.description, stage.entropy, stage.output
+                )
+            })
+            .collect();
+        let mut row = vec![
+            csv_field(&record.password),
+            csv_field(&record.pipeline),
+            csv_field(record.text.as_deref().unwrap_or("")),
+            format!("{:.1}", record.entropy),
+            csv_field(&stages.join(" | ")),
+        ];
+        if hashes {
+            row.push(csv_field(record.hash.as_deref().unwrap_or("")));
+        }
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Quote a CSV field if it contains a delimiter, a quote, a line break, or
+/// leading or trailing whitespace.
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) || field.trim() != field {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::generators::base::Constant;
+    use crate::generators::random_string::RandomString;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    fn records() -> Vec<Record> {
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+        let generator = Constant::new("a, b").pipe(RandomString::digits(2));
+        let entropy = generator.entropy();
+        let mut records = vec![
+            Record::generate(&generator, &mut rng, "test", Some("nouns"), entropy).unwrap(),
+            Record::generate(&generator, &mut rng, "test", None, entropy).unwrap(),
+        ];
+        records[1].hash = Some("$6$abc".to_string());
+        records
+    }
+
+    #[test]
+    fn test_record() {
+        let records = records();
+        let record = &records[0];
+        assert!(record.password.starts_with("a, b") && record.password.len() == 6);
+        assert_eq!(record.stages.len(), 2);
+        assert_eq!(record.stages[1].output, record.password);
+        assert!((record.entropy - 2.0 * 10f64.log2()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_formats() {
+        let records = records();
+        let json: serde_json::Value = serde_json::from_str(&to_json(&records).unwrap()).unwrap();
+        assert_eq!(json[0]["pipeline"], "test");
+        assert_eq!(json[0]["text"], "nouns");
+        assert!(json[1].get("text").is_none());
+        assert_eq!(json[0]["stages"][0]["output"], "a, b");
+        assert_eq!(json[1]["hash"], "$6$abc");
+
+        let ndjson = to_ndjson(&records).unwrap();
+        let lines: Vec<&str> = ndjson.lines().collect();
+        assert_eq!(lines.len(), 2);
+        for (line, record) in lines.iter().zip(json.as_array().unwrap()) {
+            assert_eq!(
+                &serde_json::from_str::<serde_json::Value>(line).unwrap(),
+                record
+            );
+        }
+
+        let csv = to_csv(&records);
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("password,pipeline,text,entropy,stages,hash")
+        );
+        let row = lines.next().unwrap();
+        assert!(
+            row.starts_with(&format!("\"{}\",test,nouns,6.6,", records[0].password)),
+            "{}",
+            row
+        );
+        assert!(row.ends_with(&format!(
+            "RandomString(length=2, characters=\"\"0123456789\"\") [6.6 bits]: {}\",",
+            records[0].password
+        )));
+        assert_eq!(csv_field(" a"), "\" a\"");
+    }
+}
@@ -0,0 +1,223 @@
+//! A parser for the `passwordrules` syntax that websites use to publish their
+//! password requirements, e.g.
+//! `minlength: 20; required: lower; required: upper; allowed: [-().&@?'#,/+]; max-consecutive: 2`.
+
+use crate::error::{Error, Result};
+use crate::generators::policy::{CharacterClass, CharacterSet, Policy};
+
+/// Parse a `passwordrules` string into a `Policy`.
+///
+/// Each `required` property requires at least one character from the union of
+/// its classes, and the allowed characters are the union of every `required`
+/// and `allowed` property, or every character if any of them is `unicode`.
+/// Unknown properties are ignored, as the syntax requires.
+pub fn parse(rules: &str) -> Result<Policy> {
+    let mut policy = Policy::new();
+    let mut allowed: Option<CharacterSet> = None;
+    let mut unicode = false;
+
+    for property in split_outside_brackets(rules, ';') {
+        let property = property.trim();
+        if property.is_empty() {
+            continue;
+        }
+        let (name, value) = property
+            .split_once(':')
+            .ok_or_else(|| invalid(format!("expected 'name: value', found '{}'", property)))?;
+        let value = value.trim();
+
+        match name.trim().to_lowercase().as_str() {
+            "minlength" => policy = policy.with_min_length(parse_number(name, value)?),
+            "maxlength" => policy = policy.with_max_length(parse_number(name, value)?),
+            "max-consecutive" => policy = policy.with_max_consecutive(parse_number(name, value)?),
+            "required" => match parse_classes(value)? {
+                Some(set) => {
+                    allowed = Some(allowed.unwrap_or_default().union(&set));
+                    policy = policy.with_required(set);
+                }
+                None => unicode = true,
+            },
+            "allowed" => match parse_classes(value)? {
+                Some(set) => allowed = Some(allowed.unwrap_or_default().union(&set)),
+                None => unicode = true,
+            },
+            _ => {}
+        }
+    }
+
+    Ok(match allowed {
+        Some(allowed) if !unicode => policy.with_allowed(allowed),
+        _ => policy,
+    })
+}
+
+fn invalid(message: String) -> Error {
+    Error::InvalidRules(message)
+}
+
+fn parse_number(name: &str, value: &str) -> Result<usize> {
+    value.parse().map_err(|_| {
+        invalid(format!(
+            "expected a number for '{}', found '{}'",
+            name, value
+        ))
+    })
+}
+
+/// Parse a comma separated list of character classes, returning `None` if the
+/// list includes `unicode`, which allows every character.
+fn parse_classes(value: &str) -> Result<Option<CharacterSet>> {
+    let mut set = CharacterSet::new();
+    for class in split_outside_brackets(value, ',') {
+        let class = class.trim();
+        set = match class {
+            "upper" => set.with_class(CharacterClass::Upper),
+            "lower" => set.with_class(CharacterClass::Lower),
+            "digit" => set.with_class(CharacterClass::Digit),
+            "special" => set.with_class(CharacterClass::Symbol),
+            "ascii-printable" => CharacterClass::ALL
+                .iter()
+                .fold(set, |set, class| set.with_class(*class)),
+            "unicode" => return Ok(None),
+            _ if class.len() >= 2 && class.starts_with('[') && class.ends_with(']') => {
+                set.with_characters(&class[1..class.len() - 1])
+            }
+            _ => return Err(invalid(format!("unknown character class '{}'", class))),
+        };
+    }
+    Ok(Some(set))
+}
+
+/// Split `input` on `separator`, except where it appears inside a custom character
+/// class such as `[-,;]`. A custom class may only contain `]` as its last character,
+/// so it ends at the first `]` that is followed by a separator or the end of `input`.
+fn split_outside_brackets(input: &str, separator: char) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = input.char_indices().collect();
+    let closes_class = |i: usize| {
+        chars[i + 1..]
+            .iter()
+            .find(|(_, c)| !c.is_whitespace())
+            .is_none_or(|(_, c)| *c == ',' || *c == ';')
+    };
+
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut in_class = false;
+    for (i, &(index, c)) in chars.iter().enumerate() {
+        if in_class {
+            in_class = !(c == ']' && closes_class(i));
+        } else if c == '[' {
+            in_class = true;
+        } else if c == separator {
+            parts.push(&input[start..index]);
+            start = index + c.len_utf8();
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::generators::base::PasswordGenerator;
+
+    #[test]
+    fn test_parse() {
+        let policy =
+            parse("minlength: 20; required: lower; required: upper; allowed: [-().&@?'#,/+]; max-consecutive: 2")
+                .unwrap();
+        let expected = Policy::new()
+            .with_min_length(20)
+            .with_required(CharacterClass::Lower)
+            .with_required(CharacterClass::Upper)
+            .with_max_consecutive(2)
+            .with_allowed(
+                CharacterSet::new()
+                    .with_class(CharacterClass::Lower)
+                    .with_class(CharacterClass::Upper)
+                    .with_characters("-().&@?'#,/+"),
+            );
+        assert_eq!(policy, expected);
+
+        assert!(policy.is_satisfied_by("CorrectHorse&BatteryStaple"));
+        assert!(!policy.is_satisfied_by("CorrectHorse1BatteryStaple"));
+        assert!(!policy.is_satisfied_by("CorrectHorseBatteryStaaaple"));
+    }
+
+    #[test]
+    fn test_parse_required_alternatives() {
+        let policy = parse("required: digit, [;]; allowed: lower; maxlength: 8").unwrap();
+        assert!(policy.is_satisfied_by("abc1"));
+        assert!(policy.is_satisfied_by("abc;"));
+        assert!(!policy.is_satisfied_by("abcd"));
+        assert!(!policy.is_satisfied_by("abc123456"));
+    }
+
+    #[test]
+    fn test_parse_unicode_and_unknown_properties() {
+        let policy = parse("allowed: unicode; passwordrules-version: 2;").unwrap();
+        assert_eq!(policy, Policy::new());
+
+        let policy = parse("required: digit; allowed: lower; allowed: unicode").unwrap();
+        assert_eq!(policy, Policy::new().with_required(CharacterClass::Digit));
+        assert!(policy.is_satisfied_by("Åsa-1"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(parse("minlength 20"), Err(Error::InvalidRules(_))));
+        assert!(matches!(parse("minlength: x"), Err(Error::InvalidRules(_))));
+        assert!(matches!(
+            parse("required: letters"),
+            Err(Error::InvalidRules(_))
+        ));
+    }
+
+    #[test]
+    fn test_policy_passwords_satisfy_rules() {
+        let rules = [
+            "minlength: 20; required: lower; required: upper; allowed: [-().&@?'#,/+]; max-consecutive: 2",
+            "minlength: 8; maxlength: 12; required: lower, upper; required: digit; required: special",
+            "minlength: 6; maxlength: 6; allowed: digit",
+            "required: upper; required: digit; required: [-]; minlength: 30",
+        ];
+        for rules in rules.iter() {
+            let policy = parse(rules).unwrap();
+            let passwords = crate::policy_passwords(&policy);
+            for _ in 0..10 {
+                let password = passwords.try_generate().unwrap();
+                assert!(policy.is_satisfied_by(&password), "{}: {}", rules, password);
+            }
+        }
+    }
+
+    #[test]
+    fn test_policy_passwords_fit_without_repair() {
+        let policy = parse("minlength: 20; maxlength: 24; required: lower, upper, digit").unwrap();
+        let passwords = crate::policy_passwords(&policy);
+        let candidates = passwords.children()[0];
+        for _ in 0..100 {
+            let password = candidates.try_generate().unwrap();
+            assert!(policy.is_satisfied_by(&password), "{}", password);
+            assert!(
+                password.ends_with(|c: char| c.is_ascii_digit()),
+                "{}",
+                password
+            );
+        }
+        assert!((passwords.entropy() - candidates.entropy()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_split_outside_brackets() {
+        assert_eq!(
+            split_outside_brackets("a: [;]]; b: [,]", ';'),
+            vec!["a: [;]]", " b: [,]"]
+        );
+        assert_eq!(
+            split_outside_brackets("lower, [,]", ','),
+            vec!["lower", " [,]"]
+        );
+    }
+}
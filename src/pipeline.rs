@@ -0,0 +1,686 @@
+//! A compact expression language for pipelines, e.g.
+//! `phrases(the-time-machine,3,5) | case(class) | digits(2) | symbols(1) | vowels(1)`.
+//!
+//! Stages are separated by `|`, and each acts on the output of the stage before it.
+//! A parenthesized group of options separated by `|` chooses one of them at random,
+//! e.g. `(words(nouns,4) | words(time-machine,4))`. An option may be given a weight,
+//! as in `(3: words(nouns,4) | 1: phrases(alice,3,5))`, and may have several stages
+//! joined by `>`, as in `(words(nouns,4) > case(title) | phrases(alice,3,5))`.
+//!
+//! The stages are
+//!
+//! * `phrases(text, min, max)` - a phrase of between `min` and `max` words
+//! * `words(text, n)` or `words(text, n, min_word_length)` - `n` random words
+//! * `case(name)` - convert the case of the words, e.g. `case(class)`
+//! * `random_case(words)`, `random_case(flip, p)` or `random_case(letters, k)`
+//! * `digits(n)` - `n` random digits
+//! * `string(charset, n)` - `n` random characters from a named charset, such as
+//!   `alphanumeric`, or from a quoted string of characters
+//! * `symbols(min)`, `symbols(min, max)`, `vowels(min)` or `vowels(min, max)` - defects
+//! * `constant("value")`, `surround("left", "right")`, `surround("|")` or `brackets`
+//! * `policy("passwordrules")` or `policy("passwordrules", repair)` - filter the
+//!   passwords of the preceding stages
+//! * `min_guesses(n)` - regenerate the passwords of the preceding stages until an
+//!   attacker is estimated to need at least `n` guesses, e.g. `min_guesses(1e12)`
+//!
+//! A `text` is `nouns`, `alice-in-wonderland` (or `alice`), `the-time-machine`
+//! (or `time-machine`), or a quoted file name.
+
+use std::ops::Range;
+
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
+use crate::generators::base::PasswordGenerator;
+use crate::generators::case::{Case, RandomCase};
+use crate::spec::{Charset, DefectTable, PipelineSpec, StageSpec, TextSpec};
+use crate::version::AlgorithmVersion;
+
+/// Parse a pipeline expression into a `PipelineSpec` that uses the latest
+/// `AlgorithmVersion`.
+pub fn parse(expression: &str) -> Result<PipelineSpec> {
+    let tokens = tokenize(expression)?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+    };
+    let stages = parser.parse_stages(&Token::Pipe)?;
+    let token = parser.peek();
+    if token.token != Token::End {
+        return Err(invalid(
+            format!(
+                "expected '|' or the end of the pipeline, found {}",
+                token.token
+            ),
+            token.span.clone(),
+        ));
+    }
+    Ok(PipelineSpec {
+        name: None,
+        version: Some(AlgorithmVersion::LATEST),
+        stages,
+    })
+}
+
+/// Parse a pipeline expression and build its `PasswordGenerator`.
+pub fn build(expression: &str) -> Result<Box<dyn PasswordGenerator>> {
+    parse(expression)?.build()
+}
+
+/// Render an `Error::InvalidPipeline` with the expression and a marker under the
+/// characters at fault. Other errors are rendered as they are displayed.
+pub fn annotate(expression: &str, error: &Error) -> String {
+    match error {
+        Error::InvalidPipeline { span, .. } => {
+            let offset = expression[..span.start].chars().count();
+            let width = expression[span.clone()].chars().count().max(1);
+            format!(
+                "{}\n  {}\n  {}{}",
+                error,
+                expression,
+                " ".repeat(offset),
+                "^".repeat(width)
+            )
+        }
+        _ => error.to_string(),
+    }
+}
+
+fn invalid(message: String, span: Range<usize>) -> Error {
+    Error::InvalidPipeline { message, span }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    Number(String),
+    Str(String),
+    Open,
+    Close,
+    Comma,
+    Pipe,
+    Then,
+    Colon,
+    End,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Identifier(name) => write!(f, "'{}'", name),
+            Token::Number(number) => write!(f, "{}", number),
+            Token::Str(value) => write!(f, "{:?}", value),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+            Token::Comma => write!(f, "','"),
+            Token::Pipe => write!(f, "'|'"),
+            Token::Then => write!(f, "'>'"),
+            Token::Colon => write!(f, "':'"),
+            Token::End => write!(f, "the end of the pipeline"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Spanned {
+    token: Token,
+    span: Range<usize>,
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Spanned>> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            _ if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            '|' => Token::Pipe,
+            '>' => Token::Then,
+            ':' => Token::Colon,
+            '"' => {
+                let mut value = String::new();
+                let mut closed = false;
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '"' => {
+                            closed = true;
+                            break;
+                        }
+                        '\\' => match chars.next() {
+                            Some((_, c)) => value.push(c),
+                            None => break,
+                        },
+                        _ => value.push(c),
+                    }
+                }
+                if !closed {
+                    return Err(invalid(
+                        "unterminated string".to_string(),
+                        start..expression.len(),
+                    ));
+                }
+                Token::Str(value)
+            }
+            _ if c.is_ascii_digit() || c == '.' => {
+                let mut number = c.to_string();
+                while let Some(&(_, c)) = chars.peek() {
+                    // an exponent, e.g. `1e12` or `1e-3`
+                    let is_exponent = (c == 'e' || c == 'E') && !number.contains(['e', 'E']);
+                    let is_sign = (c == '+' || c == '-') && number.ends_with(['e', 'E']);
+                    if !(c.is_ascii_digit() || c == '.' || is_exponent || is_sign) {
+                        break;
+                    }
+                    number.push(c);
+                    chars.next();
+                }
+                Token::Number(number)
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                let mut name = c.to_string();
+                while let Some(&(_, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '-') {
+                        break;
+                    }
+                    name.push(c);
+                    chars.next();
+                }
+                Token::Identifier(name)
+            }
+            _ => {
+                return Err(invalid(
+                    format!("unexpected character '{}'", c),
+                    start..start + c.len_utf8(),
+                ))
+            }
+        };
+        let end = chars.peek().map_or(expression.len(), |&(i, _)| i);
+        tokens.push(Spanned {
+            token,
+            span: start..end,
+        });
+    }
+    tokens.push(Spanned {
+        token: Token::End,
+        span: expression.len()..expression.len(),
+    });
+    Ok(tokens)
+}
+
+/// An argument of a stage, with the span of its token.
+struct Argument {
+    token: Token,
+    span: Range<usize>,
+}
+
+impl Argument {
+    fn number<T: std::str::FromStr>(&self) -> Result<T> {
+        match &self.token {
+            Token::Number(number) => number.parse().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| {
+            invalid(
+                format!("expected a number, found {}", self.token),
+                self.span.clone(),
+            )
+        })
+    }
+    fn identifier(&self) -> Result<&str> {
+        match &self.token {
+            Token::Identifier(name) => Ok(name),
+            _ => Err(invalid(
+                format!("expected a name, found {}", self.token),
+                self.span.clone(),
+            )),
+        }
+    }
+    fn string(&self) -> Result<&str> {
+        match &self.token {
+            Token::Str(value) => Ok(value),
+            _ => Err(invalid(
+                format!("expected a quoted string, found {}", self.token),
+                self.span.clone(),
+            )),
+        }
+    }
+    /// Deserialize a unit variant of `T` from the name of this argument.
+    fn variant<'de, T: Deserialize<'de>>(&'de self, kind: &str) -> Result<T> {
+        let name = self.identifier()?;
+        T::deserialize(StrDeserializer::<ValueError>::new(name))
+            .map_err(|_| invalid(format!("unknown {} '{}'", kind, name), self.span.clone()))
+    }
+    fn text(&self) -> Result<TextSpec> {
+        match &self.token {
+            Token::Str(path) => Ok(TextSpec::File(path.into())),
+            Token::Identifier(name) if name == "alice" => Ok(TextSpec::AliceInWonderland),
+            Token::Identifier(name) if name == "time-machine" => Ok(TextSpec::TheTimeMachine),
+            Token::Identifier(name) if name != "file" => self.variant("text"),
+            _ => Err(invalid(
+                format!("expected a text, found {}", self.token),
+                self.span.clone(),
+            )),
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Spanned>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Spanned {
+        &self.tokens[self.position]
+    }
+
+    fn next(&mut self) -> Spanned {
+        let token = self.tokens[self.position].clone();
+        if token.token != Token::End {
+            self.position += 1;
+        }
+        token
+    }
+
+    /// Parse one or more stages separated by `separator`.
+    fn parse_stages(&mut self, separator: &Token) -> Result<Vec<StageSpec>> {
+        let mut stages = vec![self.parse_stage()?];
+        while &self.peek().token == separator {
+            self.next();
+            stages.push(self.parse_stage()?);
+        }
+        Ok(stages)
+    }
+
+    fn parse_stage(&mut self) -> Result<StageSpec> {
+        let token = self.next();
+        match token.token {
+            Token::Open => self.parse_group(),
+            Token::Identifier(name) => {
+                let arguments = if self.peek().token == Token::Open {
+                    self.next();
+                    self.parse_arguments()?
+                } else {
+                    Vec::new()
+                };
+                let span = token.span.start..self.tokens[self.position - 1].span.end;
+                stage(&name, &arguments, token.span, span)
+            }
+            _ => Err(invalid(
+                format!("expected a stage, found {}", token.token),
+                token.span,
+            )),
+        }
+    }
+
+    /// Parse the options of a group after its opening parenthesis.
+    fn parse_group(&mut self) -> Result<StageSpec> {
+        let mut weights = Vec::new();
+        let mut options = Vec::new();
+        loop {
+            let is_weighted = matches!(self.peek().token, Token::Number(_))
+                && self.tokens[self.position + 1].token == Token::Colon;
+            if is_weighted {
+                let token = self.next();
+                let weight = Argument {
+                    token: token.token,
+                    span: token.span,
+                }
+                .number::<f64>()?;
+                self.next();
+                weights.push(Some(weight));
+            } else {
+                weights.push(None);
+            }
+            options.push(self.parse_stages(&Token::Then)?);
+
+            let token = self.next();
+            match token.token {
+                Token::Pipe => continue,
+                Token::Close => break,
+                _ => {
+                    return Err(invalid(
+                        format!("expected '|', '>' or ')', found {}", token.token),
+                        token.span,
+                    ))
+                }
+            }
+        }
+        let weights = if weights.iter().any(Option::is_some) {
+            Some(weights.into_iter().map(|w| w.unwrap_or(1.0)).collect())
+        } else {
+            None
+        };
+        Ok(StageSpec::Switch { weights, options })
+    }
+
+    /// Parse comma separated arguments after an opening parenthesis.
+    fn parse_arguments(&mut self) -> Result<Vec<Argument>> {
+        let mut arguments = Vec::new();
+        if self.peek().token == Token::Close {
+            self.next();
+            return Ok(arguments);
+        }
+        loop {
+            let token = self.next();
+            match token.token {
+                Token::Identifier(_) | Token::Number(_) | Token::Str(_) => {
+                    arguments.push(Argument {
+                        token: token.token,
+                        span: token.span,
+                    })
+                }
+                _ => {
+                    return Err(invalid(
+                        format!("expected an argument, found {}", token.token),
+                        token.span,
+                    ))
+                }
+            }
+            let token = self.next();
+            match token.token {
+                Token::Comma => continue,
+                Token::Close => return Ok(arguments),
+                _ => {
+                    return Err(invalid(
+                        format!("expected ',' or ')', found {}", token.token),
+                        token.span,
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// Build the `StageSpec` of a stage called `name` with `arguments`, where `span`
+/// covers the stage and its arguments.
+fn stage(
+    name: &str,
+    arguments: &[Argument],
+    name_span: Range<usize>,
+    span: Range<usize>,
+) -> Result<StageSpec> {
+    let arity = |counts: &[usize]| -> Result<()> {
+        if counts.contains(&arguments.len()) {
+            return Ok(());
+        }
+        let counts: Vec<String> = counts.iter().map(|c| c.to_string()).collect();
+        Err(invalid(
+            format!(
+                "'{}' takes {} arguments, found {}",
+                name,
+                counts.join(" or "),
+                arguments.len()
+            ),
+            span.clone(),
+        ))
+    };
+    let range = |min: usize, max: usize| -> Result<()> {
+        if min > max {
+            return Err(invalid(
+                format!("minimum {} is larger than maximum {}", min, max),
+                span.clone(),
+            ));
+        }
+        Ok(())
+    };
+
+    Ok(match name {
+        "phrases" => {
+            arity(&[3])?;
+            let (min, max) = (arguments[1].number()?, arguments[2].number()?);
+            range(min, max)?;
+            StageSpec::RandomPhrases {
+                text: arguments[0].text()?,
+                min,
+                max,
+                separator: Default::default(),
+            }
+        }
+        "words" => {
+            arity(&[2, 3])?;
+            StageSpec::RandomWords {
+                text: arguments[0].text()?,
+                words: arguments[1].number()?,
+                min_word_length: match arguments.get(2) {
+                    Some(argument) => argument.number()?,
+                    None => 0,
+                },
+                separator: Default::default(),
+            }
+        }
+        "case" => {
+            arity(&[1])?;
+            StageSpec::Case {
+                case: arguments[0].variant::<Case>("case")?,
+            }
+        }
+        "random_case" => {
+            arity(&[1, 2])?;
+            let mode = match (arguments[0].identifier()?, arguments.get(1)) {
+                ("words", None) => RandomCase::Words,
+                ("flip", Some(p)) => RandomCase::Flip(p.number()?),
+                ("letters", Some(k)) => RandomCase::Letters(k.number()?),
+                _ => {
+                    return Err(invalid(
+                        "expected 'words', 'flip, p' or 'letters, k'".to_string(),
+                        span,
+                    ))
+                }
+            };
+            StageSpec::RandomCase { mode }
+        }
+        "digits" => {
+            arity(&[1])?;
+            StageSpec::RandomString {
+                charset: Charset::Digits,
+                length: arguments[0].number()?,
+            }
+        }
+        "string" => {
+            arity(&[2])?;
+            let charset = match &arguments[0].token {
+                Token::Str(characters) => Charset::Characters(characters.clone()),
+                _ => arguments[0].variant("charset")?,
+            };
+            StageSpec::RandomString {
+                charset,
+                length: arguments[1].number()?,
+            }
+        }
+        "symbols" | "vowels" => {
+            arity(&[1, 2])?;
+            let min = arguments[0].number()?;
+            let max = match arguments.get(1) {
+                Some(argument) => argument.number()?,
+                None => min,
+            };
+            range(min, max)?;
+            StageSpec::Defects {
+                table: if name == "symbols" {
+                    DefectTable::Symbols
+                } else {
+                    DefectTable::Vowels
+                },
+                min,
+                max,
+            }
+        }
+        "constant" => {
+            arity(&[1])?;
+            StageSpec::Constant {
+                value: arguments[0].string()?.to_string(),
+            }
+        }
+        "surround" => {
+            arity(&[1, 2])?;
+            let left = arguments[0].string()?.to_string();
+            let right = match arguments.get(1) {
+                Some(argument) => argument.string()?.to_string(),
+                None => left.clone(),
+            };
+            StageSpec::Surround { left, right }
+        }
+        "brackets" => {
+            arity(&[0])?;
+            StageSpec::RandomSurround
+        }
+        "policy" => {
+            arity(&[1, 2])?;
+            let repair = match arguments.get(1) {
+                Some(argument) if argument.identifier()? == "repair" => true,
+                Some(argument) => {
+                    return Err(invalid(
+                        "expected 'repair'".to_string(),
+                        argument.span.clone(),
+                    ))
+                }
+                None => false,
+            };
+            StageSpec::Policy {
+                rules: arguments[0].string()?.to_string(),
+                repair,
+                max_attempts: None,
+            }
+        }
+        "min_guesses" => {
+            arity(&[1])?;
+            StageSpec::MinGuesses {
+                guesses: arguments[0].number()?,
+                max_attempts: None,
+            }
+        }
+        _ => return Err(invalid(format!("unknown stage '{}'", name), name_span)),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn error_span(expression: &str) -> Range<usize> {
+        match parse(expression) {
+            Err(Error::InvalidPipeline { span, .. }) => span,
+            other => panic!("expected an invalid pipeline, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_matches_phrase_passwords() {
+        let generator = build(
+            "phrases(the-time-machine,3,5) | case(class) | digits(2) | symbols(1) | vowels(1)",
+        )
+        .unwrap();
+        let expected = crate::phrase_passwords(None);
+        let descriptions = |generator: &dyn PasswordGenerator| -> Vec<String> {
+            generator
+                .children()
+                .iter()
+                .map(|stage| stage.description())
+                .filter(|description| description != "Constant(\"\")")
+                .collect()
+        };
+        assert_eq!(descriptions(&*generator), descriptions(&expected));
+    }
+
+    #[test]
+    fn test_parse_switch() {
+        let spec = parse("(words(nouns,4) | words(time-machine,4))").unwrap();
+        assert_eq!(
+            spec.stages,
+            vec![StageSpec::Switch {
+                weights: None,
+                options: vec![
+                    vec![StageSpec::RandomWords {
+                        text: TextSpec::Nouns,
+                        words: 4,
+                        min_word_length: 0,
+                        separator: Default::default(),
+                    }],
+                    vec![StageSpec::RandomWords {
+                        text: TextSpec::TheTimeMachine,
+                        words: 4,
+                        min_word_length: 0,
+                        separator: Default::default(),
+                    }],
+                ],
+            }]
+        );
+
+        let spec =
+            parse(r#"(3: words(nouns, 4) > case(title) | constant("a b")) | brackets"#).unwrap();
+        assert_eq!(spec.stages.len(), 2);
+        match &spec.stages[0] {
+            StageSpec::Switch { weights, options } => {
+                assert_eq!(weights, &Some(vec![3.0, 1.0]));
+                assert_eq!(options[0].len(), 2);
+            }
+            stage => panic!("expected a switch, found {:?}", stage),
+        }
+        assert!(spec.build().unwrap().generate().len() > 2);
+    }
+
+    #[test]
+    fn test_parse_stages() {
+        let spec = parse(
+            r#"string(alphanumeric, 8) | string("ab\"", 2) | random_case(flip, 0.5) | policy("required: digit", repair) | surround("[", "]")"#,
+        )
+        .unwrap();
+        assert_eq!(
+            spec.stages[1],
+            StageSpec::RandomString {
+                charset: Charset::Characters("ab\"".to_string()),
+                length: 2
+            }
+        );
+        assert_eq!(
+            spec.stages[2],
+            StageSpec::RandomCase {
+                mode: RandomCase::Flip(0.5)
+            }
+        );
+        let password = spec.build().unwrap().try_generate().unwrap();
+        assert!(password.starts_with('[') && password.ends_with(']'));
+        assert!(password.chars().any(|c| c.is_ascii_digit()));
+
+        let spec = parse("string(lowercase, 12) | min_guesses(1e10)").unwrap();
+        assert_eq!(
+            spec.stages[1],
+            StageSpec::MinGuesses {
+                guesses: 1e10,
+                max_attempts: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(error_span("words(nouns,4) | foo(1)"), 17..20);
+        assert_eq!(error_span("case(classy)"), 5..11);
+        assert_eq!(error_span("digits(x)"), 7..8);
+        assert_eq!(error_span("digits(2"), 8..8);
+        assert_eq!(error_span("phrases(nouns, 5, 3)"), 0..20);
+        assert_eq!(error_span("digits(1, 2)"), 0..12);
+        assert_eq!(error_span("digits(2) $"), 10..11);
+        assert_eq!(error_span("constant(\"a"), 9..11);
+        assert_eq!(error_span("(digits(1) | digits(2)"), 22..22);
+        assert_eq!(error_span("digits(1) digits(2)"), 10..16);
+    }
+
+    #[test]
+    fn test_annotate() {
+        let expression = "words(nouns,4) | foo(1)";
+        let error = parse(expression).unwrap_err();
+        assert_eq!(
+            annotate(expression, &error),
+            [
+                "invalid pipeline at 17..20: unknown stage 'foo'",
+                "  words(nouns,4) | foo(1)",
+                "                   ^^^",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! The parameters of the built-in `phrases` and `xkcd` pipelines, so that their
+//! recipes can be adjusted without writing a pipeline from scratch.
+//!
+//! The defaults are the parameters of `phrase_passwords` and `xkcd_passwords`,
+//! whose passwords `PhraseOptions::build` and `XkcdOptions::build` reproduce.
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::error::{Error, Result};
+use crate::generators::base::{any_of, ChainedGenerator, Constant, PasswordGenerator};
+use crate::generators::case::Case;
+use crate::generators::defects::Defects;
+use crate::generators::phrase::{RandomPhrases, RandomWords, Text};
+use crate::generators::random_string::RandomString;
+use crate::version::AlgorithmVersion;
+
+/// The parameters of the `phrases` pipeline: a phrase from a text, converted to
+/// a case, followed by digits, with symbol and vowel defects.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhraseOptions {
+    pub min_words: usize,
+    pub max_words: usize,
+    pub case: Case,
+    pub digits: usize,
+    pub symbol_defects: usize,
+    pub vowel_defects: usize,
+}
+
+impl Default for PhraseOptions {
+    fn default() -> PhraseOptions {
+        PhraseOptions {
+            min_words: 3,
+            max_words: 5,
+            case: Case::Class,
+            digits: 2,
+            symbol_defects: 1,
+            vowel_defects: 1,
+        }
+    }
+}
+
+impl PhraseOptions {
+    /// Build the pipeline for phrases from `text`, or The Time Machine, with the
+    /// algorithms of `version`.
+    ///
+    /// Returns `Error::InvalidOption` if `min_words` is zero or no phrase has as
+    /// many letters that symbols can replace as `symbol_defects`,
+    /// `Error::InvalidRange` if `min_words` is larger than `max_words`, and
+    /// `Error::EmptyVocabulary` if no phrases of `text` have an allowed number of
+    /// words. Phrases with fewer replaceable letters get fewer symbol defects.
+    pub fn build<'a>(
+        &self,
+        text: Option<&Text>,
+        version: AlgorithmVersion,
+    ) -> Result<ChainedGenerator<'a>> {
+        if self.min_words == 0 {
+            return Err(Error::InvalidOption(
+                "phrases must have at least one word".to_string(),
+            ));
+        }
+        let text = text.unwrap_or(&Text::THE_TIME_MACHINE);
+        let phrases = RandomPhrases::try_from_text(text, self.min_words, self.max_words)?;
+        let symbols =
+            Defects::with_symbols_version(self.symbol_defects, self.symbol_defects, version);
+        if self.symbol_defects > 0 {
+            let mut rng = StdRng::seed_from_u64(0);
+            let most_candidates = phrases
+                .phrases()
+                .map(|phrase| {
+                    let phrase = self.case.generate_with_seed(&mut rng, phrase);
+                    phrase
+                        .chars()
+                        .filter(|c| symbols.replacements().contains_key(c))
+                        .count()
+                })
+                .max()
+                .unwrap_or(0);
+            if self.symbol_defects > most_candidates {
+                return Err(Error::InvalidOption(format!(
+                    "{} symbol defects, but no phrase has more than {} letters that symbols can replace",
+                    self.symbol_defects, most_candidates
+                )));
+            }
+        }
+        let mut generator = Constant::empty().pipe(phrases).pipe(self.case);
+        if self.digits > 0 {
+            generator = generator.pipe(RandomString::digits(self.digits));
+        }
+        Ok(generator
+            .pipe(symbols)
+            .pipe(Defects::with_vowels(self.vowel_defects, self.vowel_defects)))
+    }
+}
+
+/// The parameters of the `xkcd` pipeline: a number of random words.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct XkcdOptions {
+    pub words: usize,
+    /// The minimum length of the words, or `None` for 5 letters from The Time
+    /// Machine and 4 from the nouns by default, and 4 from any other text.
+    pub min_word_length: Option<usize>,
+}
+
+impl Default for XkcdOptions {
+    fn default() -> XkcdOptions {
+        XkcdOptions {
+            words: 4,
+            min_word_length: None,
+        }
+    }
+}
+
+impl XkcdOptions {
+    /// Build the pipeline for words from `text`, or either The Time Machine or
+    /// the nouns, with the algorithms of `version`.
+    ///
+    /// Returns `Error::InvalidOption` if `words` is zero, and
+    /// `Error::EmptyVocabulary` if no words of a text are long enough.
+    pub fn build<'a>(
+        &self,
+        text: Option<&Text>,
+        version: AlgorithmVersion,
+    ) -> Result<ChainedGenerator<'a>> {
+        if self.words == 0 {
+            return Err(Error::InvalidOption(
+                "passwords must have at least one word".to_string(),
+            ));
+        }
+        let words = |text: &Text, min_word_length: usize| {
+            let min_word_length = self.min_word_length.unwrap_or(min_word_length);
+            RandomWords::try_from_text_version(text, self.words, min_word_length, version)
+        };
+        Ok(match text {
+            Some(text) => Constant::empty().pipe(words(text, 4)?),
+            None => Constant::empty().pipe(any_of(vec![
+                Box::new(words(&Text::THE_TIME_MACHINE, 5)?),
+                Box::new(words(&Text::NOUNS, 4)?),
+            ])),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    fn assert_same_passwords(a: &dyn PasswordGenerator, b: &dyn PasswordGenerator) {
+        assert!((a.entropy() - b.entropy()).abs() < 1e-9);
+        let mut rng_a = ChaCha20Rng::seed_from_u64(7);
+        let mut rng_b = ChaCha20Rng::seed_from_u64(7);
+        for _ in 0..10 {
+            assert_eq!(
+                a.generate_with_rng(&mut rng_a),
+                b.generate_with_rng(&mut rng_b)
+            );
+        }
+    }
+
+    #[test]
+    fn test_defaults_match_presets() {
+        for version in [AlgorithmVersion::V1, AlgorithmVersion::LATEST].iter() {
+            let generator = PhraseOptions::default().build(None, *version).unwrap();
+            let expected = crate::phrase_passwords_version(None, *version);
+            assert_same_passwords(&generator, &expected);
+        }
+        let text = Text::ALICE_IN_WONDERLAND;
+        let generator = XkcdOptions::default()
+            .build(Some(&text), AlgorithmVersion::LATEST)
+            .unwrap();
+        assert_same_passwords(&generator, &crate::xkcd_passwords(Some(&text)));
+        let generator = XkcdOptions::default()
+            .build(None, AlgorithmVersion::LATEST)
+            .unwrap();
+        assert_same_passwords(&generator, &crate::xkcd_passwords(None));
+    }
+
+    #[test]
+    fn test_options() {
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+        let options = PhraseOptions {
+            min_words: 2,
+            max_words: 2,
+            case: Case::Snake,
+            digits: 4,
+            symbol_defects: 0,
+            vowel_defects: 0,
+        };
+        let password = options
+            .build(None, AlgorithmVersion::LATEST)
+            .unwrap()
+            .generate_with_rng(&mut rng);
+        let (words, digits) = password.split_at(password.len() - 4);
+        assert_eq!(words.split('_').count(), 2, "{}", password);
+        assert!(digits.chars().all(|c| c.is_ascii_digit()), "{}", password);
+
+        let options = XkcdOptions {
+            words: 6,
+            min_word_length: Some(8),
+        };
+        let password = options
+            .build(None, AlgorithmVersion::LATEST)
+            .unwrap()
+            .generate_with_rng(&mut rng);
+        let words: Vec<&str> = password.split(' ').collect();
+        assert_eq!(words.len(), 6);
+        assert!(words.iter().all(|word| word.len() >= 8), "{}", password);
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let version = AlgorithmVersion::LATEST;
+        let options = PhraseOptions {
+            min_words: 0,
+            ..PhraseOptions::default()
+        };
+        assert!(matches!(
+            options.build(None, version),
+            Err(Error::InvalidOption(_))
+        ));
+        let options = PhraseOptions {
+            min_words: 5,
+            max_words: 3,
+            ..PhraseOptions::default()
+        };
+        assert_eq!(
+            options.build(None, version).err(),
+            Some(Error::InvalidRange { min: 5, max: 3 })
+        );
+        let options = PhraseOptions {
+            symbol_defects: 100,
+            ..PhraseOptions::default()
+        };
+        assert!(matches!(
+            options.build(None, version),
+            Err(Error::InvalidOption(_))
+        ));
+        let options = PhraseOptions {
+            min_words: 200,
+            max_words: 300,
+            ..PhraseOptions::default()
+        };
+        assert!(matches!(
+            options.build(None, version),
+            Err(Error::EmptyVocabulary)
+        ));
+        let options = XkcdOptions {
+            words: 0,
+            min_word_length: None,
+        };
+        assert!(matches!(
+            options.build(None, version),
+            Err(Error::InvalidOption(_))
+        ));
+        let options = XkcdOptions {
+            words: 4,
+            min_word_length: Some(100),
+        };
+        assert!(matches!(
+            options.build(None, version),
+            Err(Error::EmptyVocabulary)
+        ));
+    }
+}
@@ -0,0 +1,612 @@
+//! Bulk provisioning of accounts: a CSV file of user names, each with an optional
+//! pipeline and `passwordrules` policy, is turned into a unique password per user,
+//! with its entropy and, optionally, its hash.
+//!
+//! The input has a header row with a `username` column and, optionally,
+//! `pipeline` and `policy` columns, e.g.
+//!
+//! ```text
+//! username,pipeline,policy
+//! alice,,
+//! bob,xkcd,
+//! carol,,"minlength: 12; required: lower, upper; required: digit"
+//! ```
+//!
+//! A pipeline is `phrases`, `xkcd` or a pipeline expression. Rows without one use
+//! the default generator of the `Provisioner`, or, if they have a policy, the
+//! generator of `policy_passwords`. The generators of the rows are built with the
+//! `AlgorithmVersion` and wrapped with the filter of the `Provisioner`, if any.
+
+use std::cell::OnceCell;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use rand::RngCore;
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::generators::base::PasswordGenerator;
+use crate::hash::HashAlgorithm;
+use crate::output::csv_field;
+use crate::version::AlgorithmVersion;
+use crate::{
+    password_rules, phrase_passwords_version, pipeline, policy_passwords_version,
+    xkcd_passwords_version,
+};
+
+/// An account to provision, as read from a row of the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub username: String,
+    /// The name or expression of the pipeline of the account's password.
+    pub pipeline: Option<String>,
+    /// The `passwordrules` policy that the account's password must satisfy.
+    pub policy: Option<String>,
+}
+
+impl Request {
+    fn key(&self) -> Key<'_> {
+        (self.pipeline.as_deref(), self.policy.as_deref())
+    }
+}
+
+/// The pipeline and policy of a `Request`, which share a generator.
+type Key<'r> = (Option<&'r str>, Option<&'r str>);
+
+/// A generator built for a `Key`, and its entropy.
+type Built<'a> = (Box<dyn PasswordGenerator + 'a>, f64);
+
+/// A function that wraps the generator of a row, e.g. with a minimum strength.
+type RowFilter<'a> =
+    Box<dyn Fn(Box<dyn PasswordGenerator + 'a>) -> Result<Box<dyn PasswordGenerator + 'a>> + 'a>;
+
+/// A provisioned account.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Account {
+    pub username: String,
+    pub password: String,
+    /// The entropy, in bits, of the generator of the password.
+    pub entropy: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+/// Generates a unique password for each of a list of `Request`s.
+pub struct Provisioner<'a> {
+    default: Box<dyn PasswordGenerator + 'a>,
+    hash: Option<HashAlgorithm>,
+    max_attempts: usize,
+    version: Option<AlgorithmVersion>,
+    filter: Option<RowFilter<'a>>,
+}
+
+impl<'a> Provisioner<'a> {
+    /// Create a `Provisioner` whose passwords are generated by `default` unless a
+    /// request has a pipeline or policy, and that makes up to 100 attempts to
+    /// generate a password that has not already been generated.
+    pub fn new<T: PasswordGenerator + 'a>(default: T) -> Provisioner<'a> {
+        Provisioner {
+            default: Box::new(default),
+            hash: None,
+            max_attempts: 100,
+            version: None,
+            filter: None,
+        }
+    }
+    /// Create a new `Provisioner` that hashes each password with `algorithm`.
+    pub fn with_hash(self, algorithm: HashAlgorithm) -> Provisioner<'a> {
+        Provisioner {
+            hash: Some(algorithm),
+            ..self
+        }
+    }
+    /// Create a new `Provisioner` that gives up on a request after
+    /// `max_attempts` attempts to generate a unique password.
+    pub fn with_max_attempts(self, max_attempts: usize) -> Provisioner<'a> {
+        Provisioner {
+            max_attempts,
+            ..self
+        }
+    }
+
+    /// Create a new `Provisioner` that builds the generators of requests with a
+    /// pipeline or policy with the algorithms of `version`, rather than the
+    /// latest. The default generator is used as it is.
+    pub fn with_version(self, version: AlgorithmVersion) -> Provisioner<'a> {
+        Provisioner {
+            version: Some(version),
+            ..self
+        }
+    }
+    /// Create a new `Provisioner` that wraps the generators of requests with a
+    /// pipeline or policy with `filter`, e.g. to reject weak or breached
+    /// passwords as the default generator does.
+    pub fn with_filter<F>(self, filter: F) -> Provisioner<'a>
+    where
+        F: Fn(Box<dyn PasswordGenerator + 'a>) -> Result<Box<dyn PasswordGenerator + 'a>> + 'a,
+    {
+        Provisioner {
+            filter: Some(Box::new(filter)),
+            ..self
+        }
+    }
+
+    /// Provision an account for each of `requests`, in order.
+    ///
+    /// Every generator is built before any password is generated, so that an
+    /// invalid pipeline or policy in any row is reported before any work is done.
+    /// The salts of the hashes are drawn from the thread's random number
+    /// generator rather than `rng`, so that they reveal nothing about the passwords.
+    pub fn provision(&self, rng: &mut dyn RngCore, requests: &[Request]) -> Result<Vec<Account>> {
+        let mut usernames = HashSet::new();
+        let mut generators: HashMap<Key, Option<Built>> = HashMap::new();
+        for request in requests.iter() {
+            if !usernames.insert(request.username.as_str()) {
+                return Err(Error::InvalidCsv(format!(
+                    "the user name '{}' appears more than once",
+                    request.username
+                )));
+            }
+            if let Entry::Vacant(entry) = generators.entry(request.key()) {
+                let generator = match self.build(request.key())? {
+                    Some(generator) => {
+                        let entropy = generator.entropy();
+                        Some((generator, entropy))
+                    }
+                    None => None,
+                };
+                entry.insert(generator);
+            }
+        }
+
+        let mut passwords = HashSet::new();
+        let mut accounts = Vec::with_capacity(requests.len());
+        let default_entropy = OnceCell::new();
+        for request in requests.iter() {
+            let (generator, entropy) = match generators.get(&request.key()) {
+                Some(Some((generator, entropy))) => (generator.as_ref(), *entropy),
+                _ => (
+                    self.default.as_ref(),
+                    *default_entropy.get_or_init(|| self.default.entropy()),
+                ),
+            };
+            let password = self.generate_unique(generator, rng, &mut passwords)?;
+            let hash = match self.hash {
+                Some(algorithm) => Some(algorithm.hash(&password)?),
+                None => None,
+            };
+            accounts.push(Account {
+                username: request.username.clone(),
+                password,
+                entropy,
+                hash,
+            });
+        }
+        Ok(accounts)
+    }
+
+    /// Build the generator of a request with `pipeline` and `policy`, wrapped with
+    /// the filter, or `None` for the default generator.
+    fn build(&self, key: Key) -> Result<Option<Box<dyn PasswordGenerator + 'a>>> {
+        let generator = match build(key, self.version)? {
+            Some(generator) => generator,
+            None => return Ok(None),
+        };
+        Ok(Some(match self.filter {
+            Some(ref filter) => filter(generator)?,
+            None => generator,
+        }))
+    }
+
+    fn generate_unique(
+        &self,
+        generator: &dyn PasswordGenerator,
+        rng: &mut dyn RngCore,
+        passwords: &mut HashSet<String>,
+    ) -> Result<String> {
+        for _ in 0..self.max_attempts {
+            let password = generator.try_generate_with_rng(rng)?;
+            if passwords.insert(password.clone()) {
+                return Ok(password);
+            }
+        }
+        Err(Error::Rejected {
+            filter: "unique".to_string(),
+            attempts: self.max_attempts,
+        })
+    }
+}
+
+/// Build the generator of a request with `pipeline` and `policy` with the
+/// algorithms of `version`, or `None` for the default generator.
+fn build(
+    (pipeline, policy): Key,
+    version: Option<AlgorithmVersion>,
+) -> Result<Option<Box<dyn PasswordGenerator>>> {
+    let policy = policy.map(password_rules::parse).transpose()?;
+    let generator: Box<dyn PasswordGenerator> = match pipeline {
+        Some("phrases") => Box::new(phrase_passwords_version(None, version.unwrap_or_default())),
+        Some("xkcd") => Box::new(xkcd_passwords_version(None, version.unwrap_or_default())),
+        Some(expression) => {
+            let mut spec = pipeline::parse(expression)?;
+            spec.version = version.or(spec.version);
+            spec.build()?
+        }
+        None => match policy {
+            Some(policy) => {
+                let version = version.unwrap_or_default();
+                return Ok(Some(Box::new(policy_passwords_version(&policy, version))));
+            }
+            None => return Ok(None),
+        },
+    };
+    Ok(Some(match policy {
+        Some(policy) => Box::new(generator.with_policy(policy).with_repair()),
+        None => generator,
+    }))
+}
+
+/// Parse the requests of a CSV file with a header row.
+pub fn parse_csv(input: &str) -> Result<Vec<Request>> {
+    let mut rows = input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let header = match rows.next() {
+        Some((number, line)) => parse_row(line, number + 1)?,
+        None => return Ok(Vec::new()),
+    };
+    let column = |name: &str| header.iter().position(|column| column.trim() == name);
+    let username = column("username")
+        .ok_or_else(|| Error::InvalidCsv("the header has no 'username' column".to_string()))?;
+    let (pipeline, policy) = (column("pipeline"), column("policy"));
+
+    let mut requests = Vec::new();
+    for (number, line) in rows {
+        let row = parse_row(line, number + 1)?;
+        if row.len() != header.len() {
+            return Err(Error::InvalidCsv(format!(
+                "line {} has {} fields, but the header has {}",
+                number + 1,
+                row.len(),
+                header.len()
+            )));
+        }
+        let field = |index: Option<usize>| {
+            index
+                .map(|index| row[index].trim())
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        let request = Request {
+            username: field(Some(username)).ok_or_else(|| {
+                Error::InvalidCsv(format!("line {} has no user name", number + 1))
+            })?,
+            pipeline: field(pipeline),
+            policy: field(policy),
+        };
+        requests.push(request);
+    }
+    Ok(requests)
+}
+
+/// Split a line into its fields, which may be quoted with `"`, with `""` for a
+/// quote within a quoted field.
+fn parse_row(line: &str, number: usize) -> Result<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(Error::InvalidCsv(format!(
+            "line {} has an unterminated quote",
+            number
+        )));
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+/// Format `accounts` as CSV, with a `password` column if `passwords` is true and a
+/// `hash` column if any account has a hash.
+pub fn to_csv(accounts: &[Account], passwords: bool) -> String {
+    let hashes = accounts.iter().any(|account| account.hash.is_some());
+    let mut header = vec!["username"];
+    if passwords {
+        header.push("password");
+    }
+    header.push("entropy");
+    if hashes {
+        header.push("hash");
+    }
+    let mut csv = header.join(",") + "\n";
+    for account in accounts.iter() {
+        let mut row = vec![csv_field(&account.username)];
+        if passwords {
+            row.push(csv_field(&account.password));
+        }
+        row.push(format!("{:.1}", account.entropy));
+        if hashes {
+            row.push(csv_field(account.hash.as_deref().unwrap_or("")));
+        }
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Format `accounts` as a JSON array, with their passwords if `passwords` is true.
+pub fn to_json(accounts: &[Account], passwords: bool) -> Result<String> {
+    serde_json::to_string_pretty(&json_rows(accounts, passwords))
+        .map_err(|e| Error::Json(e.to_string()))
+}
+
+/// Format `accounts` as newline-delimited JSON, with their passwords if
+/// `passwords` is true.
+pub fn to_ndjson(accounts: &[Account], passwords: bool) -> Result<String> {
+    let mut ndjson = String::new();
+    for row in json_rows(accounts, passwords) {
+        ndjson.push_str(&serde_json::to_string(&row).map_err(|e| Error::Json(e.to_string()))?);
+        ndjson.push('\n');
+    }
+    Ok(ndjson)
+}
+
+fn json_rows(accounts: &[Account], passwords: bool) -> Vec<JsonRow<'_>> {
+    accounts
+        .iter()
+        .map(|account| JsonRow {
+            username: &account.username,
+            password: Some(account.password.as_str()).filter(|_| passwords),
+            entropy: account.entropy,
+            hash: account.hash.as_deref(),
+        })
+        .collect()
+}
+
+/// An `Account` as it is formatted by `to_json`.
+#[derive(Serialize)]
+struct JsonRow<'r> {
+    username: &'r str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    password: Option<&'r str>,
+    entropy: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hash: Option<&'r str>,
+}
+
+/// Format the user names and passwords of `accounts` as CSV, for a file that is
+/// kept apart from the hashes.
+pub fn passwords_csv(accounts: &[Account]) -> String {
+    let mut csv = "username,password\n".to_string();
+    for account in accounts.iter() {
+        csv.push_str(&format!(
+            "{},{}\n",
+            csv_field(&account.username),
+            csv_field(&account.password)
+        ));
+    }
+    csv
+}
+
+/// Write `contents` to a new file at `path` that only its owner can read or
+/// write, replacing any existing file.
+pub fn write_private<P: AsRef<Path>>(path: P, contents: &str) -> io::Result<()> {
+    let path = path.as_ref();
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents.as_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::generators::random_string::RandomString;
+    use crate::hash;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    static INPUT: &str = "username,pipeline,policy
+alice,,
+bob,xkcd,
+\"carol, jr\",,\"minlength: 12; maxlength: 12; required: lower, upper; required: digit\"
+dave,digits(6),
+";
+
+    #[test]
+    fn test_parse_csv() {
+        let requests = parse_csv(INPUT).unwrap();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(
+            requests[2],
+            Request {
+                username: "carol, jr".to_string(),
+                pipeline: None,
+                policy: Some(
+                    "minlength: 12; maxlength: 12; required: lower, upper; required: digit"
+                        .to_string()
+                ),
+            }
+        );
+        assert_eq!(requests[1].pipeline.as_deref(), Some("xkcd"));
+        assert_eq!(
+            parse_csv("username\n\nalice\n\"bob \"\"b\"\"\"\n").unwrap()[1].username,
+            "bob \"b\""
+        );
+
+        for input in [
+            "user\nalice\n",
+            "username,policy\nalice\n",
+            "username\n\"alice\n",
+            "username,policy\n,x\n",
+        ]
+        .iter()
+        {
+            assert!(
+                matches!(parse_csv(input), Err(Error::InvalidCsv(_))),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_provision() {
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+        let requests = parse_csv(INPUT).unwrap();
+        let provisioner =
+            Provisioner::new(RandomString::digits(10)).with_hash(HashAlgorithm::Bcrypt { cost: 4 });
+        let accounts = provisioner.provision(&mut rng, &requests).unwrap();
+        assert_eq!(accounts.len(), 4);
+        assert!(accounts[0].password.chars().all(|c| c.is_ascii_digit()));
+        assert!((accounts[0].entropy - 10.0 * 10f64.log2()).abs() < 1e-9);
+        assert!(accounts[1].password.chars().any(char::is_alphabetic));
+        assert_eq!(accounts[2].password.len(), 12);
+        assert!(accounts[2].password.chars().any(|c| c.is_ascii_digit()));
+        assert_eq!(accounts[3].password.len(), 6);
+        for account in accounts.iter() {
+            let hash = account.hash.as_ref().unwrap();
+            assert_eq!(hash::verify(&account.password, hash), Ok(true));
+        }
+
+        let requests = parse_csv("username,pipeline\nalice,nonsense(\n").unwrap();
+        assert!(Provisioner::new(RandomString::digits(10))
+            .provision(&mut rng, &requests)
+            .is_err());
+        let requests = parse_csv("username,pipeline\nalice,digits(2)\nbob,\n").unwrap();
+        let provisioner = Provisioner::new(RandomString::digits(10))
+            .with_filter(|generator| Ok(Box::new(generator.with_min_guesses(1e6))));
+        assert!(matches!(
+            provisioner.provision(&mut rng, &requests),
+            Err(Error::Rejected { .. })
+        ));
+
+        let requests = parse_csv("username,pipeline\nalice,phrases\n").unwrap();
+        let accounts = Provisioner::new(RandomString::digits(10))
+            .with_version(AlgorithmVersion::V1)
+            .provision(&mut ChaCha20Rng::seed_from_u64(1), &requests)
+            .unwrap();
+        assert_eq!(
+            accounts[0].password,
+            phrase_passwords_version(None, AlgorithmVersion::V1)
+                .generate_with_rng(&mut ChaCha20Rng::seed_from_u64(1))
+        );
+
+        let requests = parse_csv("username\nalice\nalice\n").unwrap();
+        assert!(matches!(
+            Provisioner::new(RandomString::digits(10)).provision(&mut rng, &requests),
+            Err(Error::InvalidCsv(_))
+        ));
+    }
+
+    #[test]
+    fn test_unique_passwords() {
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+        let requests: Vec<Request> = (0..10)
+            .map(|i| Request {
+                username: format!("user{}", i),
+                pipeline: None,
+                policy: None,
+            })
+            .collect();
+        let accounts = Provisioner::new(RandomString::digits(1))
+            .provision(&mut rng, &requests)
+            .unwrap();
+        let passwords: HashSet<_> = accounts.iter().map(|a| a.password.as_str()).collect();
+        assert_eq!(passwords.len(), 10);
+
+        let requests: Vec<Request> = (0..11)
+            .map(|i| Request {
+                username: format!("user{}", i),
+                pipeline: None,
+                policy: None,
+            })
+            .collect();
+        assert!(matches!(
+            Provisioner::new(RandomString::digits(1)).provision(&mut rng, &requests),
+            Err(Error::Rejected { .. })
+        ));
+    }
+
+    #[test]
+    fn test_output_formats() {
+        let accounts = vec![
+            Account {
+                username: "alice".to_string(),
+                password: "a,b\"c".to_string(),
+                entropy: 40.04,
+                hash: Some("$2y$04$abc".to_string()),
+            },
+            Account {
+                username: "bob".to_string(),
+                password: "def".to_string(),
+                entropy: 40.0,
+                hash: None,
+            },
+        ];
+        assert_eq!(
+            to_csv(&accounts, true),
+            "username,password,entropy,hash\nalice,\"a,b\"\"c\",40.0,$2y$04$abc\nbob,def,40.0,\n"
+        );
+        assert_eq!(
+            to_csv(&accounts[1..], false),
+            "username,entropy\nbob,40.0\n"
+        );
+        assert_eq!(
+            passwords_csv(&accounts),
+            "username,password\nalice,\"a,b\"\"c\"\nbob,def\n"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&to_json(&accounts, false).unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {"username": "alice", "entropy": 40.04, "hash": "$2y$04$abc"},
+                {"username": "bob", "entropy": 40.0},
+            ])
+        );
+        assert_eq!(
+            to_ndjson(&accounts[1..], true).unwrap(),
+            "{\"username\":\"bob\",\"password\":\"def\",\"entropy\":40.0}\n"
+        );
+        let csv = to_csv(&accounts, true);
+        let parsed = parse_csv(&csv).unwrap();
+        assert_eq!(parsed[0].username, "alice");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = std::env::temp_dir().join(format!("passwords-private-{}", std::process::id()));
+        fs::write(&path, "old").unwrap();
+        write_private(&path, "alice,secret\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "alice,secret\n");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,598 @@
+//! A parser and checker for the `pwquality.conf` settings of libpwquality, which
+//! `passwd` enforces on many Linux systems, e.g.
+//!
+//! ```text
+//! # /etc/security/pwquality.conf
+//! minlen = 12
+//! dcredit = -1
+//! maxrepeat = 3
+//! ```
+//!
+//! The length and credit settings have the semantics of libpwquality: a
+//! password needs `minlen` characters, less one for each digit, uppercase,
+//! lowercase or other character up to the positive credit of its class, while a
+//! negative credit is the minimum number of characters of its class. The
+//! dictionary check uses the built-in `Text` vocabularies rather than cracklib.
+
+use std::fs;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::generators::policy::{CharacterClass, Policy};
+use crate::strength::{self, Pattern};
+
+/// The smallest `minlen` that libpwquality accepts.
+const MIN_MIN_LENGTH: usize = 6;
+/// The shortest user name that `usercheck` looks for.
+const MIN_USER_LENGTH: usize = 3;
+/// The shortest dictionary word that `dictcheck` rejects.
+const MIN_WORD_LENGTH: usize = 4;
+
+/// A way in which a password fails the checks of `PwQuality`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// The password is shorter than `min_length`, which is `minlen` less the
+    /// credits of the password.
+    TooShort {
+        length: usize,
+        min_length: usize,
+    },
+    /// The password has fewer than `min` characters of `class`.
+    TooFew {
+        class: CharacterClass,
+        min: usize,
+    },
+    TooFewClasses {
+        classes: usize,
+        min_classes: usize,
+    },
+    TooManyRepeats {
+        character: char,
+        max_repeat: usize,
+    },
+    TooManyClassRepeats {
+        class: CharacterClass,
+        max_class_repeat: usize,
+    },
+    TooLongSequence {
+        sequence: String,
+        max_sequence: usize,
+    },
+    /// The password is based on the dictionary word `word`.
+    DictionaryWord(String),
+    /// The password contains the user name, possibly reversed.
+    ContainsUser,
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let class_name = |class: &CharacterClass| match class {
+            CharacterClass::Lower => "lowercase letters",
+            CharacterClass::Upper => "uppercase letters",
+            CharacterClass::Digit => "digits",
+            CharacterClass::Symbol => "other characters",
+        };
+        match self {
+            Violation::TooShort { length, min_length } => write!(
+                f,
+                "the password is shorter than {} characters ({})",
+                min_length, length
+            ),
+            Violation::TooFew { class, min } => {
+                write!(
+                    f,
+                    "the password contains fewer than {} {}",
+                    min,
+                    class_name(class)
+                )
+            }
+            Violation::TooFewClasses {
+                classes,
+                min_classes,
+            } => write!(
+                f,
+                "the password contains fewer than {} character classes ({})",
+                min_classes, classes
+            ),
+            Violation::TooManyRepeats {
+                character,
+                max_repeat,
+            } => write!(
+                f,
+                "the password contains more than {} '{}' characters in a row",
+                max_repeat, character
+            ),
+            Violation::TooManyClassRepeats {
+                class,
+                max_class_repeat,
+            } => write!(
+                f,
+                "the password contains more than {} {} in a row",
+                max_class_repeat,
+                class_name(class)
+            ),
+            Violation::TooLongSequence {
+                sequence,
+                max_sequence,
+            } => write!(
+                f,
+                "the password contains the sequence '{}' of more than {} characters",
+                sequence, max_sequence
+            ),
+            Violation::DictionaryWord(word) => {
+                write!(f, "the password is based on the dictionary word '{}'", word)
+            }
+            Violation::ContainsUser => write!(f, "the password contains the user name"),
+        }
+    }
+}
+
+/// The settings of a `pwquality.conf` file that determine which passwords are
+/// accepted. A setting of zero disables its check, as it does in libpwquality.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PwQuality {
+    pub min_length: usize,
+    pub digit_credit: i64,
+    pub upper_credit: i64,
+    pub lower_credit: i64,
+    pub other_credit: i64,
+    pub min_classes: usize,
+    pub max_repeat: usize,
+    pub max_sequence: usize,
+    pub max_class_repeat: usize,
+    pub dict_check: bool,
+    pub user_check: bool,
+}
+
+impl Default for PwQuality {
+    /// The defaults of libpwquality.
+    fn default() -> PwQuality {
+        PwQuality {
+            min_length: 8,
+            digit_credit: 0,
+            upper_credit: 0,
+            lower_credit: 0,
+            other_credit: 0,
+            min_classes: 0,
+            max_repeat: 0,
+            max_sequence: 0,
+            max_class_repeat: 0,
+            dict_check: true,
+            user_check: true,
+        }
+    }
+}
+
+impl PwQuality {
+    /// Parse the contents of a `pwquality.conf` file, starting from the defaults.
+    ///
+    /// Settings that do not affect which passwords are accepted, such as `retry`,
+    /// and unknown settings are ignored.
+    pub fn parse(contents: &str) -> Result<PwQuality> {
+        PwQuality::default().with_settings(contents)
+    }
+
+    /// Load the `pwquality.conf` file at `path`, followed by the `*.conf` files
+    /// of the directory `path` with `.d` appended, in order of name, as
+    /// libpwquality does.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<PwQuality> {
+        let path = path.as_ref();
+        let mut pwquality = PwQuality::parse(&read(path)?)?;
+        let mut directory = path.as_os_str().to_owned();
+        directory.push(".d");
+        if let Ok(entries) = fs::read_dir(&directory) {
+            let mut paths: Vec<_> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|e| e == "conf"))
+                .collect();
+            paths.sort();
+            for path in paths {
+                pwquality = pwquality.with_settings(&read(&path)?)?;
+            }
+        }
+        Ok(pwquality)
+    }
+
+    /// Create a new `PwQuality` with the settings of `contents` in place of its own.
+    fn with_settings(mut self, contents: &str) -> Result<PwQuality> {
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = match line.split_once('=') {
+                Some((name, value)) => (name.trim(), value.trim()),
+                None => (line, ""),
+            };
+            let number = number + 1;
+            match name {
+                "minlen" => self.min_length = parse_value(number, name, value)?,
+                "dcredit" => self.digit_credit = parse_value(number, name, value)?,
+                "ucredit" => self.upper_credit = parse_value(number, name, value)?,
+                "lcredit" => self.lower_credit = parse_value(number, name, value)?,
+                "ocredit" => self.other_credit = parse_value(number, name, value)?,
+                "minclass" => self.min_classes = parse_value(number, name, value)?,
+                "maxrepeat" => self.max_repeat = parse_value(number, name, value)?,
+                "maxsequence" => self.max_sequence = parse_value(number, name, value)?,
+                "maxclassrepeat" => self.max_class_repeat = parse_value(number, name, value)?,
+                "dictcheck" => self.dict_check = parse_value::<u32>(number, name, value)? != 0,
+                "usercheck" => self.user_check = parse_value::<u32>(number, name, value)? != 0,
+                _ => {}
+            }
+        }
+        Ok(self)
+    }
+
+    /// List the ways in which `password` fails these checks, for the user `user`
+    /// if it is known.
+    pub fn violations(&self, password: &str, user: Option<&str>) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        let chars: Vec<char> = password.chars().collect();
+
+        let credits = [
+            (CharacterClass::Digit, self.digit_credit),
+            (CharacterClass::Upper, self.upper_credit),
+            (CharacterClass::Lower, self.lower_credit),
+            (CharacterClass::Symbol, self.other_credit),
+        ];
+        let mut min_length = std::cmp::max(self.min_length, MIN_MIN_LENGTH) as i64;
+        let mut classes = 0;
+        for (class, credit) in credits.iter() {
+            let count = chars.iter().filter(|c| class_of(**c) == *class).count() as i64;
+            if count > 0 {
+                classes += 1;
+            }
+            if *credit >= 0 {
+                min_length -= std::cmp::min(count, *credit);
+            } else if count < -credit {
+                violations.push(Violation::TooFew {
+                    class: *class,
+                    min: -credit as usize,
+                });
+            }
+        }
+        if (chars.len() as i64) < min_length {
+            violations.push(Violation::TooShort {
+                length: chars.len(),
+                min_length: min_length as usize,
+            });
+        }
+        if classes < self.min_classes {
+            violations.push(Violation::TooFewClasses {
+                classes,
+                min_classes: self.min_classes,
+            });
+        }
+
+        if self.max_repeat > 0 {
+            for run in runs(&chars, |a, b| a == b) {
+                if run.len() > self.max_repeat {
+                    violations.push(Violation::TooManyRepeats {
+                        character: run[0],
+                        max_repeat: self.max_repeat,
+                    });
+                }
+            }
+        }
+        if self.max_class_repeat > 0 {
+            for run in runs(&chars, |a, b| class_of(a) == class_of(b)) {
+                if run.len() > self.max_class_repeat {
+                    violations.push(Violation::TooManyClassRepeats {
+                        class: class_of(run[0]),
+                        max_class_repeat: self.max_class_repeat,
+                    });
+                }
+            }
+        }
+        if self.max_sequence > 0 {
+            for sequence in sequences(&chars) {
+                if sequence.len() > self.max_sequence {
+                    violations.push(Violation::TooLongSequence {
+                        sequence: sequence.iter().collect(),
+                        max_sequence: self.max_sequence,
+                    });
+                }
+            }
+        }
+
+        if self.dict_check {
+            if let Some(word) = dictionary_word(password) {
+                violations.push(Violation::DictionaryWord(word));
+            }
+        }
+        if let Some(user) = user.filter(|_| self.user_check) {
+            if contains_user(password, user) {
+                violations.push(Violation::ContainsUser);
+            }
+        }
+        violations
+    }
+    /// Whether `password` passes every check, for the user `user` if it is known.
+    pub fn is_satisfied_by(&self, password: &str, user: Option<&str>) -> bool {
+        self.violations(password, user).is_empty()
+    }
+
+    /// A `Policy` that is at least as strict as the length, credit and class
+    /// settings, to guide the generation of passwords. The classes that
+    /// `minclass` requires are the first of lowercase, uppercase, digits and
+    /// symbols that are not already required.
+    pub fn policy(&self) -> Policy {
+        let mut policy =
+            Policy::new().with_min_length(std::cmp::max(self.min_length, MIN_MIN_LENGTH));
+        let credits = [
+            (CharacterClass::Lower, self.lower_credit),
+            (CharacterClass::Upper, self.upper_credit),
+            (CharacterClass::Digit, self.digit_credit),
+            (CharacterClass::Symbol, self.other_credit),
+        ];
+        let mut required: Vec<CharacterClass> = credits
+            .iter()
+            .filter(|(_, credit)| *credit < 0)
+            .map(|(class, _)| *class)
+            .collect();
+        for (class, _) in credits.iter() {
+            if required.len() < self.min_classes && !required.contains(class) {
+                required.push(*class);
+            }
+        }
+        for class in required {
+            policy = policy.with_required(class);
+        }
+        if self.max_repeat > 0 {
+            policy = policy.with_max_consecutive(self.max_repeat);
+        }
+        policy
+    }
+}
+
+fn read(path: &Path) -> Result<String> {
+    fs::read_to_string(path)
+        .map_err(|e| Error::InvalidPwQuality(format!("could not read '{}': {}", path.display(), e)))
+}
+
+fn parse_value<T: std::str::FromStr>(number: usize, name: &str, value: &str) -> Result<T> {
+    value.parse().map_err(|_| {
+        Error::InvalidPwQuality(format!(
+            "line {}: expected a number for '{}', found '{}'",
+            number, name, value
+        ))
+    })
+}
+
+/// The class of `c`, where every character that is not an ascii digit or letter
+/// is an other character.
+fn class_of(c: char) -> CharacterClass {
+    CharacterClass::ALL
+        .iter()
+        .copied()
+        .find(|class| class.contains(c))
+        .unwrap_or(CharacterClass::Symbol)
+}
+
+/// Split `chars` into runs of consecutive characters in which each pair of
+/// neighbours satisfies `same`.
+fn runs(chars: &[char], same: impl Fn(char, char) -> bool) -> Vec<&[char]> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for i in 1..=chars.len() {
+        if i == chars.len() || !same(chars[i - 1], chars[i]) {
+            runs.push(&chars[start..i]);
+            start = i;
+        }
+    }
+    runs
+}
+
+/// The monotonic sequences of `chars`, such as `abcd` or `4321`, in which each
+/// character follows or precedes the last.
+fn sequences(chars: &[char]) -> Vec<&[char]> {
+    let mut sequences = Vec::new();
+    let mut start = 0;
+    while start + 1 < chars.len() {
+        let delta = chars[start + 1] as i64 - chars[start] as i64;
+        if delta.abs() != 1 {
+            start += 1;
+            continue;
+        }
+        let mut end = start + 2;
+        while end < chars.len() && chars[end] as i64 - chars[end - 1] as i64 == delta {
+            end += 1;
+        }
+        sequences.push(&chars[start..end]);
+        start = end - 1;
+    }
+    sequences
+}
+
+/// The dictionary word that `password` is based on, if it is a word of the
+/// built-in vocabularies, possibly reversed, with look-alike substitutions or
+/// surrounded by digits and symbols.
+fn dictionary_word(password: &str) -> Option<String> {
+    let letters: Vec<usize> = password
+        .chars()
+        .enumerate()
+        .filter(|(_, c)| c.is_alphabetic())
+        .map(|(i, _)| i)
+        .collect();
+    let (first, last) = (*letters.first()?, *letters.last()?);
+    strength::estimate(password)
+        .matches
+        .into_iter()
+        .find_map(|m| match m.pattern {
+            Pattern::Dictionary { word, .. }
+                if m.range.start <= first
+                    && m.range.end > last
+                    && m.range.len() >= MIN_WORD_LENGTH =>
+            {
+                Some(word)
+            }
+            _ => None,
+        })
+}
+
+/// Whether `password` contains `user`, or `user` reversed, ignoring case.
+fn contains_user(password: &str, user: &str) -> bool {
+    if user.chars().count() < MIN_USER_LENGTH {
+        return false;
+    }
+    let password = password.to_lowercase();
+    let user = user.to_lowercase();
+    let reversed: String = user.chars().rev().collect();
+    password.contains(&user) || password.contains(&reversed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let pwquality = PwQuality::parse(
+            "# comment\nminlen = 12\ndcredit=-1\nucredit = 1 # trailing\nmaxrepeat = 3\n\
+             dictcheck = 0\nenforce_for_root\nretry = 3\n",
+        )
+        .unwrap();
+        assert_eq!(
+            pwquality,
+            PwQuality {
+                min_length: 12,
+                digit_credit: -1,
+                upper_credit: 1,
+                max_repeat: 3,
+                dict_check: false,
+                ..PwQuality::default()
+            }
+        );
+        assert!(matches!(
+            PwQuality::parse("minlen = twelve"),
+            Err(Error::InvalidPwQuality(message)) if message.starts_with("line 1")
+        ));
+    }
+
+    #[test]
+    fn test_credits() {
+        let pwquality =
+            PwQuality::parse("minlen = 10\nucredit = 1\ndcredit = 2\ndictcheck = 0").unwrap();
+        // 8 characters, with a credit for an uppercase letter and two digits
+        assert!(pwquality.is_satisfied_by("Xkcdab12", None));
+        assert_eq!(
+            pwquality.violations("xkcdabc1", None),
+            vec![Violation::TooShort {
+                length: 8,
+                min_length: 9
+            }]
+        );
+        // the credit for digits is capped at 2, so this needs 7 characters
+        assert!(!pwquality.is_satisfied_by("X12345", None));
+
+        let pwquality = PwQuality::parse("minlen = 8\ndcredit = -2\nocredit = -1").unwrap();
+        assert_eq!(
+            pwquality.violations("qzxvbnmk1", None),
+            vec![
+                Violation::TooFew {
+                    class: CharacterClass::Digit,
+                    min: 2
+                },
+                Violation::TooFew {
+                    class: CharacterClass::Symbol,
+                    min: 1
+                }
+            ]
+        );
+        // minlen is at least 6
+        assert!(!PwQuality::parse("minlen = 1\ndictcheck = 0")
+            .unwrap()
+            .is_satisfied_by("qzx", None));
+    }
+
+    #[test]
+    fn test_repeats_and_sequences() {
+        let pwquality = PwQuality::parse(
+            "minclass = 3\nmaxrepeat = 2\nmaxclassrepeat = 4\nmaxsequence = 3\ndictcheck = 0",
+        )
+        .unwrap();
+        assert!(pwquality.is_satisfied_by("Qzx7Vkm9#", None));
+        assert_eq!(
+            pwquality.violations("qzxvqzxv", None),
+            vec![
+                Violation::TooFewClasses {
+                    classes: 1,
+                    min_classes: 3
+                },
+                Violation::TooManyClassRepeats {
+                    class: CharacterClass::Lower,
+                    max_class_repeat: 4
+                }
+            ]
+        );
+        assert_eq!(
+            pwquality.violations("Qz7aaaV#", None),
+            vec![Violation::TooManyRepeats {
+                character: 'a',
+                max_repeat: 2
+            }]
+        );
+        assert_eq!(
+            pwquality.violations("Qz#4321V", None),
+            vec![Violation::TooLongSequence {
+                sequence: "4321".to_string(),
+                max_sequence: 3
+            }]
+        );
+    }
+
+    #[test]
+    fn test_dictionary_and_user_checks() {
+        let pwquality = PwQuality::default();
+        assert_eq!(
+            pwquality.violations("P@ssword123", None),
+            vec![Violation::DictionaryWord("password".to_string())]
+        );
+        assert!(pwquality.is_satisfied_by("AndThatWa$Cemphor96", None));
+        assert_eq!(
+            pwquality.violations("xQ7#nosilla", Some("Allison")),
+            vec![Violation::ContainsUser]
+        );
+        let pwquality = PwQuality::parse("usercheck = 0").unwrap();
+        assert!(pwquality.is_satisfied_by("xQ7#nosilla", Some("Allison")));
+    }
+
+    #[test]
+    fn test_policy() {
+        let pwquality =
+            PwQuality::parse("minlen = 14\nucredit = -1\nminclass = 3\nmaxrepeat = 2").unwrap();
+        let policy = pwquality.policy();
+        assert_eq!(
+            policy,
+            Policy::new()
+                .with_min_length(14)
+                .with_required(CharacterClass::Upper)
+                .with_required(CharacterClass::Lower)
+                .with_required(CharacterClass::Digit)
+                .with_max_consecutive(2)
+        );
+    }
+
+    #[test]
+    fn test_pwquality_passwords() {
+        use crate::generators::base::PasswordGenerator;
+
+        let configurations = [
+            "",
+            "minlen = 16\ndcredit = -2\nocredit = -1\nmaxrepeat = 2",
+            "minlen = 12\nminclass = 4\nmaxclassrepeat = 3\nmaxsequence = 3",
+        ];
+        for configuration in configurations.iter() {
+            let pwquality = PwQuality::parse(configuration).unwrap();
+            let passwords = crate::pwquality_passwords(&pwquality, Some("allison"));
+            for _ in 0..5 {
+                let password = passwords.try_generate().unwrap();
+                assert!(
+                    pwquality.is_satisfied_by(&password, Some("allison")),
+                    "{}",
+                    password
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,617 @@
+//! A serializable specification of a password pipeline, so that a recipe for
+//! passwords can be written in a TOML or JSON file, checked into a repository,
+//! and shared. For example, the pipeline of `phrase_passwords` is
+//!
+//! ```toml
+//! name = "phrases"
+//!
+//! [[stages]]
+//! type = "random_phrases"
+//! text = "the-time-machine"
+//! min = 3
+//! max = 5
+//!
+//! [[stages]]
+//! type = "case"
+//! case = "class"
+//!
+//! [[stages]]
+//! type = "random_string"
+//! charset = "digits"
+//! length = 2
+//!
+//! [[stages]]
+//! type = "defects"
+//! table = "symbols"
+//! min = 1
+//! max = 1
+//!
+//! [[stages]]
+//! type = "defects"
+//! table = "vowels"
+//! min = 1
+//! max = 1
+//! ```
+//!
+//! The path of a text file, e.g. `text = { file = "words.txt" }`, is relative to
+//! the directory of the spec file.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{check_range, Error, Result};
+use crate::generators::base::{
+    Constant, PasswordGenerator, RandomSurround, Surround, Switch, ASCII_LOWERCASE,
+    ASCII_PUNCTUATION, ASCII_UPPERCASE, DIGITS,
+};
+use crate::generators::case::{Case, RandomCase};
+use crate::generators::defects::Defects;
+use crate::generators::phrase::{RandomPhrases, RandomWords, Text};
+use crate::generators::policy::WithPolicy;
+use crate::generators::random_string::RandomString;
+use crate::generators::separator::Separator;
+use crate::password_rules;
+use crate::version::AlgorithmVersion;
+
+/// A pipeline of stages, each of which acts on the output of the stage before it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The version of the algorithms of the stages. A spec without a version is
+    /// built with `AlgorithmVersion::LATEST`, like every other generator, so a
+    /// spec whose passwords must be reproducible should set its version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<AlgorithmVersion>,
+    pub stages: Vec<StageSpec>,
+}
+
+/// A stage of a `PipelineSpec`, tagged by its `type`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum StageSpec {
+    /// Append a constant `value`.
+    Constant { value: String },
+    /// Append a phrase of between `min` and `max` words from `text`.
+    RandomPhrases {
+        #[serde(default)]
+        text: TextSpec,
+        min: usize,
+        max: usize,
+        #[serde(default)]
+        separator: Separator,
+    },
+    /// Append `words` random words of at least `min_word_length` letters from `text`.
+    RandomWords {
+        #[serde(default)]
+        text: TextSpec,
+        words: usize,
+        #[serde(default)]
+        min_word_length: usize,
+        #[serde(default)]
+        separator: Separator,
+    },
+    /// Convert the case of the words of the password.
+    Case { case: Case },
+    /// Randomly change the case of the letters of the password.
+    RandomCase { mode: RandomCase },
+    /// Append `length` random characters from `charset`.
+    RandomString { charset: Charset, length: usize },
+    /// Apply between `min` and `max` defects from `table`.
+    Defects {
+        table: DefectTable,
+        min: usize,
+        max: usize,
+    },
+    /// Surround the password with `left` and `right`.
+    Surround { left: String, right: String },
+    /// Surround the password with a random pair of brackets.
+    RandomSurround,
+    /// Continue with one of the `options`, each a list of stages, chosen at
+    /// random in proportion to `weights`, or uniformly if there are no weights.
+    Switch {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        weights: Option<Vec<f64>>,
+        options: Vec<Vec<StageSpec>>,
+    },
+    /// Regenerate, or with `repair` repair, the passwords of the preceding
+    /// stages until they satisfy the `passwordrules` string `rules`.
+    Policy {
+        rules: String,
+        #[serde(default)]
+        repair: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_attempts: Option<usize>,
+    },
+    /// Regenerate the passwords of the preceding stages until the strength
+    /// estimator expects an attacker to need at least `guesses` guesses.
+    MinGuesses {
+        guesses: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_attempts: Option<usize>,
+    },
+}
+
+/// The source of the words or phrases of a stage: a built-in text, or a text file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum TextSpec {
+    Nouns,
+    AliceInWonderland,
+    #[default]
+    TheTimeMachine,
+    File(PathBuf),
+}
+
+/// The characters that a `random_string` stage chooses from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Charset {
+    Digits,
+    Lowercase,
+    Uppercase,
+    Letters,
+    Alphanumeric,
+    Symbols,
+    Characters(String),
+}
+
+/// The replacements that a `defects` stage makes.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DefectTable {
+    Symbols,
+    Vowels,
+}
+
+fn invalid(message: String) -> Error {
+    Error::InvalidSpec(message)
+}
+
+impl PipelineSpec {
+    /// Parse a `PipelineSpec` from a TOML document.
+    pub fn from_toml(input: &str) -> Result<PipelineSpec> {
+        toml::from_str(input).map_err(|e| invalid(e.to_string()))
+    }
+    /// Parse a `PipelineSpec` from a JSON document.
+    pub fn from_json(input: &str) -> Result<PipelineSpec> {
+        serde_json::from_str(input).map_err(|e| invalid(e.to_string()))
+    }
+    /// Load a `PipelineSpec` from a file, which is parsed as JSON if its
+    /// extension is `.json` and as TOML otherwise, resolving the relative paths
+    /// of its text files against the directory of the file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<PipelineSpec> {
+        let path = path.as_ref();
+        let contents = read(path)?;
+        let mut spec = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => PipelineSpec::from_json(&contents)?,
+            _ => PipelineSpec::from_toml(&contents)?,
+        };
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        resolve_paths(&mut spec.stages, directory);
+        Ok(spec)
+    }
+    /// Write this `PipelineSpec` as a TOML document.
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).map_err(|e| invalid(e.to_string()))
+    }
+    /// Write this `PipelineSpec` as a JSON document.
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(|e| invalid(e.to_string()))
+    }
+    /// The texts of the stages of this `PipelineSpec`, in the order that they
+    /// first appear, including those of the options of `switch` stages.
+    pub fn texts(&self) -> Vec<&TextSpec> {
+        let mut texts = Vec::new();
+        collect_texts(&self.stages, &mut texts);
+        texts
+    }
+    /// Build the `PasswordGenerator` that this `PipelineSpec` describes.
+    pub fn build(&self) -> Result<Box<dyn PasswordGenerator>> {
+        build_stages(&self.stages, self.version.unwrap_or_default())
+    }
+}
+
+fn collect_texts<'s>(stages: &'s [StageSpec], texts: &mut Vec<&'s TextSpec>) {
+    for stage in stages {
+        match stage {
+            StageSpec::RandomPhrases { text, .. } | StageSpec::RandomWords { text, .. }
+                if !texts.contains(&text) =>
+            {
+                texts.push(text)
+            }
+            StageSpec::Switch { options, .. } => {
+                for option in options {
+                    collect_texts(option, texts);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn resolve_paths(stages: &mut [StageSpec], directory: &Path) {
+    for stage in stages {
+        match stage {
+            StageSpec::RandomPhrases {
+                text: TextSpec::File(path),
+                ..
+            }
+            | StageSpec::RandomWords {
+                text: TextSpec::File(path),
+                ..
+            } if path.is_relative() => *path = directory.join(&*path),
+            StageSpec::Switch { options, .. } => {
+                for option in options {
+                    resolve_paths(option, directory);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Display for TextSpec {
+    /// The name of a built-in text, as it is written in a spec, or the path of a file.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextSpec::Nouns => f.write_str("nouns"),
+            TextSpec::AliceInWonderland => f.write_str("alice-in-wonderland"),
+            TextSpec::TheTimeMachine => f.write_str("the-time-machine"),
+            TextSpec::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<String> {
+    fs::read_to_string(path)
+        .map_err(|e| invalid(format!("could not read '{}': {}", path.display(), e)))
+}
+
+/// Build a pipeline of `stages`, which generates empty strings if there are none.
+fn build_stages(
+    stages: &[StageSpec],
+    version: AlgorithmVersion,
+) -> Result<Box<dyn PasswordGenerator>> {
+    let mut generator: Option<Box<dyn PasswordGenerator>> = None;
+    for stage in stages {
+        generator = Some(match (generator, stage) {
+            (
+                previous,
+                StageSpec::Policy {
+                    rules,
+                    repair,
+                    max_attempts,
+                },
+            ) => {
+                let previous = previous.unwrap_or_else(|| Box::new(Constant::empty()));
+                let mut with_policy = WithPolicy::new(previous, password_rules::parse(rules)?);
+                if let Some(max_attempts) = max_attempts {
+                    with_policy = with_policy.with_max_attempts(*max_attempts);
+                }
+                if *repair {
+                    with_policy = with_policy.with_repair();
+                }
+                Box::new(with_policy)
+            }
+            (
+                previous,
+                StageSpec::MinGuesses {
+                    guesses,
+                    max_attempts,
+                },
+            ) => {
+                if !(guesses.is_finite() && *guesses > 0.0) {
+                    return Err(invalid(format!(
+                        "min_guesses must be a positive number, not {}",
+                        guesses
+                    )));
+                }
+                let previous = previous.unwrap_or_else(|| Box::new(Constant::empty()));
+                let mut filter = previous.with_min_guesses(*guesses);
+                if let Some(max_attempts) = max_attempts {
+                    filter = filter.with_max_attempts(*max_attempts);
+                }
+                Box::new(filter)
+            }
+            (Some(previous), stage) => Box::new(previous.pipe(stage.build(version)?)),
+            (None, stage) => stage.build(version)?,
+        });
+    }
+    Ok(generator.unwrap_or_else(|| Box::new(Constant::empty())))
+}
+
+impl StageSpec {
+    /// Build the `PasswordGenerator` for a single stage, other than a `policy` or
+    /// `min_guesses`, which wrap the stages before them.
+    fn build(&self, version: AlgorithmVersion) -> Result<Box<dyn PasswordGenerator>> {
+        Ok(match self {
+            StageSpec::Constant { value } => Box::new(Constant::new(value)),
+            StageSpec::RandomPhrases {
+                text,
+                min,
+                max,
+                separator,
+            } => {
+                let contents = text.contents()?;
+                Box::new(
+                    RandomPhrases::try_from_text(&text.to_text(&contents), *min, *max)?
+                        .with_separator(separator.clone()),
+                )
+            }
+            StageSpec::RandomWords {
+                text,
+                words,
+                min_word_length,
+                separator,
+            } => {
+                let contents = text.contents()?;
+                Box::new(
+                    RandomWords::try_from_text_version(
+                        &text.to_text(&contents),
+                        *words,
+                        *min_word_length,
+                        version,
+                    )?
+                    .with_separator(separator.clone()),
+                )
+            }
+            StageSpec::Case { case } => Box::new(*case),
+            StageSpec::RandomCase { mode } => Box::new(*mode),
+            StageSpec::RandomString { charset, length } => {
+                let characters = charset.characters();
+                if characters.is_empty() {
+                    return Err(Error::EmptyVocabulary);
+                }
+                Box::new(RandomString::new(*length).with_characters(characters))
+            }
+            StageSpec::Defects { table, min, max } => {
+                check_range(*min, *max)?;
+                Box::new(match table {
+                    DefectTable::Symbols => Defects::with_symbols_version(*min, *max, version),
+                    DefectTable::Vowels => Defects::with_vowels(*min, *max),
+                })
+            }
+            StageSpec::Surround { left, right } => Box::new(Surround::new(left, right)),
+            StageSpec::RandomSurround => Box::new(RandomSurround::brackets()),
+            StageSpec::Switch { weights, options } => {
+                let weights = match weights {
+                    Some(weights) if weights.len() != options.len() => {
+                        return Err(invalid(format!(
+                            "a switch has {} weights for {} options",
+                            weights.len(),
+                            options.len()
+                        )))
+                    }
+                    Some(weights) => weights.clone(),
+                    None => vec![1.0; options.len()],
+                };
+                let generators = options
+                    .iter()
+                    .map(|stages| build_stages(stages, version))
+                    .collect::<Result<Vec<_>>>()?;
+                Box::new(Switch::try_weighted(
+                    weights.into_iter().zip(generators).collect(),
+                )?)
+            }
+            StageSpec::Policy { .. } | StageSpec::MinGuesses { .. } => {
+                build_stages(std::slice::from_ref(self), version)?
+            }
+        })
+    }
+}
+
+impl TextSpec {
+    /// The contents of the text file, if this is a `TextSpec::File`.
+    pub(crate) fn contents(&self) -> Result<Option<String>> {
+        match self {
+            TextSpec::File(path) => read(path).map(Some),
+            _ => Ok(None),
+        }
+    }
+    pub(crate) fn to_text<'a>(&self, contents: &'a Option<String>) -> Text<'a> {
+        match (self, contents) {
+            (_, Some(contents)) => Text::new(contents),
+            (TextSpec::Nouns, None) => Text::NOUNS,
+            (TextSpec::AliceInWonderland, None) => Text::ALICE_IN_WONDERLAND,
+            (TextSpec::TheTimeMachine, None) | (TextSpec::File(_), None) => Text::THE_TIME_MACHINE,
+        }
+    }
+}
+
+impl Charset {
+    fn characters(&self) -> Vec<char> {
+        match self {
+            Charset::Digits => DIGITS.chars().collect(),
+            Charset::Lowercase => ASCII_LOWERCASE.chars().collect(),
+            Charset::Uppercase => ASCII_UPPERCASE.chars().collect(),
+            Charset::Letters => ASCII_LOWERCASE
+                .chars()
+                .chain(ASCII_UPPERCASE.chars())
+                .collect(),
+            Charset::Alphanumeric => ASCII_LOWERCASE
+                .chars()
+                .chain(ASCII_UPPERCASE.chars())
+                .chain(DIGITS.chars())
+                .collect(),
+            Charset::Symbols => ASCII_PUNCTUATION.chars().collect(),
+            Charset::Characters(characters) => characters.chars().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static PHRASES: &str = include_str!("../specs/phrases.toml");
+
+    #[test]
+    fn test_spec_from_toml_matches_phrase_passwords() {
+        let spec = PipelineSpec::from_toml(PHRASES).unwrap();
+        assert_eq!(spec.name.as_deref(), Some("phrases"));
+        let generator = spec.build().unwrap();
+
+        let expected = crate::phrase_passwords(None);
+        let expected_stages: Vec<String> = expected
+            .children()
+            .iter()
+            .skip(1)
+            .map(|stage| stage.description())
+            .collect();
+        let stages: Vec<String> = generator
+            .children()
+            .iter()
+            .map(|stage| stage.description())
+            .collect();
+        assert_eq!(stages, expected_stages);
+        assert!((generator.entropy() - expected.entropy()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_spec_from_json_switch() {
+        let spec = PipelineSpec::from_json(
+            r#"{
+                "stages": [
+                    {"type": "switch", "weights": [3, 1], "options": [
+                        [{"type": "random_words", "text": "nouns", "words": 4}],
+                        [{"type": "constant", "value": "a"}, {"type": "random_surround"}]
+                    ]},
+                    {"type": "random_case", "mode": {"flip": 0.5}}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let generator = spec.build().unwrap();
+        assert_eq!(
+            generator.children()[0].description(),
+            "Switch(probabilities=[0.75, 0.25])"
+        );
+        assert!(!generator.generate().is_empty());
+    }
+
+    #[test]
+    fn test_spec_load() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("specs/xkcd.json");
+        let spec = PipelineSpec::load(path).unwrap();
+        let generator = spec.build().unwrap();
+        assert!((generator.entropy() - crate::xkcd_passwords(None).entropy()).abs() < 1e-9);
+        let texts: Vec<String> = spec.texts().iter().map(|t| t.to_string()).collect();
+        assert_eq!(texts, ["the-time-machine", "nouns"]);
+    }
+
+    #[test]
+    fn test_spec_load_resolves_text_files() {
+        let directory = std::env::temp_dir().join(format!("passwords-spec-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("words.txt"), "alpha bravo").unwrap();
+        let path = directory.join("spec.toml");
+        fs::write(
+            &path,
+            "[[stages]]\ntype = \"switch\"\noptions = [[{ type = \"random_words\", \
+             text = { file = \"words.txt\" }, words = 2 }]]\n",
+        )
+        .unwrap();
+        let spec = PipelineSpec::load(&path).unwrap();
+        let password = spec.build().unwrap().generate();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let texts: Vec<String> = spec.texts().iter().map(|t| t.to_string()).collect();
+        assert_eq!(texts, [directory.join("words.txt").display().to_string()]);
+        assert!(password
+            .split(' ')
+            .all(|word| word == "alpha" || word == "bravo"));
+    }
+
+    #[test]
+    fn test_spec_round_trip() {
+        let spec = PipelineSpec::from_toml(PHRASES).unwrap();
+        assert_eq!(
+            PipelineSpec::from_toml(&spec.to_toml().unwrap()),
+            Ok(spec.clone())
+        );
+        assert_eq!(PipelineSpec::from_json(&spec.to_json().unwrap()), Ok(spec));
+    }
+
+    #[test]
+    fn test_spec_policy_wraps_preceding_stages() {
+        let spec = PipelineSpec::from_toml(
+            r#"
+            [[stages]]
+            type = "random_string"
+            charset = { characters = "ab" }
+            length = 8
+
+            [[stages]]
+            type = "policy"
+            rules = "required: digit"
+            repair = true
+            "#,
+        )
+        .unwrap();
+        let generator = spec.build().unwrap();
+        assert!(generator.description().starts_with("WithPolicy"));
+        let password = generator.try_generate().unwrap();
+        assert!(password.chars().any(|c| c.is_ascii_digit()), "{}", password);
+    }
+
+    #[test]
+    fn test_spec_min_guesses_wraps_preceding_stages() {
+        let spec = PipelineSpec::from_toml(
+            r#"
+            [[stages]]
+            type = "random_string"
+            charset = "lowercase"
+            length = 12
+
+            [[stages]]
+            type = "min_guesses"
+            guesses = 1e10
+            max_attempts = 20
+            "#,
+        )
+        .unwrap();
+        let generator = spec.build().unwrap();
+        assert!(generator
+            .description()
+            .starts_with("Filter(guesses >= 1e10, max_attempts=20)"));
+        let password = generator.try_generate().unwrap();
+        assert!(crate::strength::estimate(&password).guesses >= 1e10);
+    }
+
+    #[test]
+    fn test_spec_errors() {
+        assert!(matches!(
+            PipelineSpec::from_toml("[[stages]]\ntype = \"unknown\""),
+            Err(Error::InvalidSpec(_))
+        ));
+        assert!(matches!(
+            PipelineSpec::from_json(r#"{"stages": [{"type": "case", "case": "class", "x": 1}]}"#),
+            Err(Error::InvalidSpec(_))
+        ));
+
+        let build = |json: &str| PipelineSpec::from_json(json).unwrap().build().err();
+        assert_eq!(
+            build(r#"{"stages": [{"type": "defects", "table": "vowels", "min": 2, "max": 1}]}"#),
+            Some(Error::InvalidRange { min: 2, max: 1 })
+        );
+        assert!(matches!(
+            build(r#"{"stages": [{"type": "switch", "weights": [1], "options": [[], []]}]}"#),
+            Some(Error::InvalidSpec(_))
+        ));
+        assert!(matches!(
+            build(
+                r#"{"stages": [{"type": "random_words", "text": {"file": "/nonexistent"}, "words": 4}]}"#
+            ),
+            Some(Error::InvalidSpec(_))
+        ));
+        assert!(matches!(
+            build(r#"{"stages": [{"type": "min_guesses", "guesses": -1}]}"#),
+            Some(Error::InvalidSpec(_))
+        ));
+    }
+}
@@ -0,0 +1,1199 @@
+//! A password strength estimator in the style of zxcvbn.
+//!
+//! A password is matched against patterns that an attacker would try before
+//! brute force: words from the built-in `Text` vocabularies, including reversed
+//! words and words with the look-alike substitutions of `Defects::with_symbols`,
+//! keyboard walks, dates, repeats and sequences. Each match is assigned a number
+//! of guesses, and the estimate is the number of guesses of the sequence of
+//! matches, with brute force between them, that is easiest to guess.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::generators::base::log2_binomial;
+use crate::generators::defects::Defects;
+use crate::generators::phrase::Text;
+
+/// Passwords are analysed up to this many characters, and any remaining
+/// characters are brute forced.
+const MAX_LENGTH: usize = 256;
+/// The penalty, per additional match, for a sequence of several matches.
+const MIN_GUESSES_BEFORE_GROWING_SEQUENCE: f64 = 10_000.0;
+const MIN_SUBMATCH_GUESSES_SINGLE_CHAR: f64 = 10.0;
+const MIN_SUBMATCH_GUESSES_MULTI_CHAR: f64 = 50.0;
+const BRUTEFORCE_CARDINALITY: f64 = 10.0;
+/// The smallest number of years that a date is assumed to be chosen from.
+const MIN_YEAR_SPACE: f64 = 20.0;
+/// The largest difference between consecutive characters of a sequence.
+const MAX_SEQUENCE_DELTA: i64 = 5;
+/// The most leet substitutions that are tried for a single token.
+const MAX_LEET_SUBSTITUTIONS: usize = 256;
+
+/// The rows of a QWERTY keyboard, unshifted and shifted. Each row after the first
+/// starts with a placeholder so that the key at column `c` lies between the keys
+/// at columns `c` and `c + 1` of the row above.
+static QWERTY: [(&str, &str); 4] = [
+    ("`1234567890-=", "~!@#$%^&*()_+"),
+    ("\0qwertyuiop[]\\", "\0QWERTYUIOP{}|"),
+    ("\0asdfghjkl;'", "\0ASDFGHJKL:\""),
+    ("\0zxcvbnm,./", "\0ZXCVBNM<>?"),
+];
+
+/// The pattern of a `Match`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    /// A word of a dictionary, which has `rank` among its words by frequency,
+    /// possibly reversed, and with look-alike `substitutions` of (symbol, letter).
+    Dictionary {
+        dictionary: String,
+        word: String,
+        rank: usize,
+        reversed: bool,
+        substitutions: Vec<(char, char)>,
+    },
+    /// A walk along adjacent keys of a keyboard, which changes direction `turns`
+    /// times and uses the shift key for `shifted` characters.
+    Spatial { turns: usize, shifted: usize },
+    /// A `base` string that is repeated `count` times.
+    Repeat { base: String, count: usize },
+    /// Characters with a constant difference, e.g. `abcd` or `9753`.
+    Sequence { ascending: bool },
+    /// A date, or a year on its own.
+    Date {
+        year: i32,
+        month: Option<u32>,
+        day: Option<u32>,
+        separator: Option<char>,
+    },
+    /// Characters that match no other pattern.
+    Bruteforce,
+}
+
+/// A pattern found in the characters `range` of a password.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub pattern: Pattern,
+    /// The range of character, not byte, indices of the match.
+    pub range: Range<usize>,
+    pub token: String,
+    pub guesses: f64,
+}
+
+impl fmt::Display for Match {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pattern = match &self.pattern {
+            Pattern::Dictionary {
+                dictionary,
+                word,
+                rank,
+                reversed,
+                substitutions,
+            } => {
+                let mut description = format!("{} word '{}' of rank {}", dictionary, word, rank);
+                if *reversed {
+                    description.push_str(", reversed");
+                }
+                if !substitutions.is_empty() {
+                    let substitutions: Vec<String> = substitutions
+                        .iter()
+                        .map(|(symbol, letter)| format!("{}->{}", symbol, letter))
+                        .collect();
+                    description.push_str(&format!(", with {}", substitutions.join(" ")));
+                }
+                description
+            }
+            Pattern::Spatial { turns, shifted } => {
+                format!("keyboard walk with {} turns and {} shifted", turns, shifted)
+            }
+            Pattern::Repeat { base, count } => format!("'{}' repeated {} times", base, count),
+            Pattern::Sequence { ascending: true } => "ascending sequence".to_string(),
+            Pattern::Sequence { ascending: false } => "descending sequence".to_string(),
+            Pattern::Date {
+                year,
+                month: Some(month),
+                day: Some(day),
+                ..
+            } => format!("date {:04}-{:02}-{:02}", year, month, day),
+            Pattern::Date { year, .. } => format!("year {}", year),
+            Pattern::Bruteforce => "brute force".to_string(),
+        };
+        write!(
+            f,
+            "'{}' ({}): {:.0} guesses",
+            self.token, pattern, self.guesses
+        )
+    }
+}
+
+/// A model of an attack, which determines how quickly guesses are made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttackModel {
+    /// An online attack against a service that limits the rate of attempts.
+    OnlineThrottled,
+    /// An online attack against a service that does not limit attempts.
+    OnlineUnthrottled,
+    /// An offline attack on a slow password hash, such as bcrypt or Argon2.
+    OfflineSlowHash,
+    /// An offline attack on a fast, unsalted hash, such as SHA-1.
+    OfflineFastHash,
+}
+
+impl AttackModel {
+    pub const ALL: [AttackModel; 4] = [
+        AttackModel::OnlineThrottled,
+        AttackModel::OnlineUnthrottled,
+        AttackModel::OfflineSlowHash,
+        AttackModel::OfflineFastHash,
+    ];
+
+    /// The number of guesses that an attacker makes per second.
+    pub fn guesses_per_second(self) -> f64 {
+        match self {
+            AttackModel::OnlineThrottled => 100.0 / 3600.0,
+            AttackModel::OnlineUnthrottled => 10.0,
+            AttackModel::OfflineSlowHash => 1e4,
+            AttackModel::OfflineFastHash => 1e10,
+        }
+    }
+}
+
+impl fmt::Display for AttackModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AttackModel::OnlineThrottled => "online, throttled (100 guesses per hour)",
+            AttackModel::OnlineUnthrottled => "online, unthrottled (10 guesses per second)",
+            AttackModel::OfflineSlowHash => "offline, slow hash (10^4 guesses per second)",
+            AttackModel::OfflineFastHash => "offline, fast hash (10^10 guesses per second)",
+        })
+    }
+}
+
+/// An estimate of the strength of a password.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    /// The estimated number of guesses needed to guess the password.
+    pub guesses: f64,
+    /// The sequence of matches that is easiest to guess, which covers the password.
+    pub matches: Vec<Match>,
+}
+
+impl Estimate {
+    pub fn guesses_log10(&self) -> f64 {
+        self.guesses.log10()
+    }
+    /// A score from 0, too guessable, to 4, very unguessable.
+    pub fn score(&self) -> u8 {
+        let thresholds = [1e3, 1e6, 1e8, 1e10];
+        thresholds
+            .iter()
+            .take_while(|threshold| self.guesses >= **threshold + 5.0)
+            .count() as u8
+    }
+    /// The expected time, in seconds, to guess the password under `model`.
+    pub fn crack_time_seconds(&self, model: AttackModel) -> f64 {
+        self.guesses / model.guesses_per_second()
+    }
+    /// The time to guess the password under `model`, e.g. `3 hours`.
+    pub fn crack_time_display(&self, model: AttackModel) -> String {
+        display_time(self.crack_time_seconds(model))
+    }
+}
+
+/// Describe a duration of `seconds` in the largest whole unit, e.g. `3 hours`.
+pub fn display_time(seconds: f64) -> String {
+    let units = [
+        ("year", 60.0 * 60.0 * 24.0 * 365.25),
+        ("month", 60.0 * 60.0 * 24.0 * 365.25 / 12.0),
+        ("day", 60.0 * 60.0 * 24.0),
+        ("hour", 60.0 * 60.0),
+        ("minute", 60.0),
+        ("second", 1.0),
+    ];
+    if seconds < 1.0 {
+        return "less than a second".to_string();
+    }
+    if seconds >= 100.0 * units[0].1 {
+        return "centuries".to_string();
+    }
+    let (unit, length) = units
+        .iter()
+        .find(|(_, length)| seconds >= *length)
+        .unwrap_or(&units[5]);
+    let count = (seconds / length).round() as u64;
+    format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+}
+
+/// A dictionary of words, ranked from most to least frequent.
+struct Dictionary {
+    name: String,
+    ranks: HashMap<String, usize>,
+    max_length: usize,
+}
+
+impl Dictionary {
+    /// Rank `words` by frequency, breaking ties by first appearance.
+    fn new(name: &str, words: Vec<String>) -> Dictionary {
+        let mut counts: HashMap<&str, (usize, usize)> = HashMap::new();
+        for (index, word) in words.iter().enumerate() {
+            counts.entry(word).or_insert((0, index)).0 += 1;
+        }
+        let mut ordered: Vec<(&str, (usize, usize))> = counts.into_iter().collect();
+        ordered.sort_by(|(_, (a_count, a_index)), (_, (b_count, b_index))| {
+            b_count.cmp(a_count).then(a_index.cmp(b_index))
+        });
+        let ranks: HashMap<String, usize> = ordered
+            .into_iter()
+            .enumerate()
+            .map(|(rank, (word, _))| (word.to_string(), rank + 1))
+            .collect();
+        let max_length = ranks.keys().map(|w| w.chars().count()).max().unwrap_or(0);
+        Dictionary {
+            name: name.to_string(),
+            ranks,
+            max_length,
+        }
+    }
+}
+
+/// Estimates the strength of passwords against its dictionaries and patterns.
+pub struct Estimator {
+    dictionaries: Vec<Dictionary>,
+    /// Look-alike symbols and the lowercase letters that they may stand for.
+    leet: HashMap<char, Vec<char>>,
+    /// The year that dates are assumed to be chosen near.
+    reference_year: i32,
+    /// The keys adjacent to each key of the keyboard, by direction.
+    keyboard: HashMap<char, [Option<char>; 6]>,
+}
+
+impl Estimator {
+    /// Create an `Estimator` with the built-in `Text` vocabularies as dictionaries.
+    pub fn new() -> Estimator {
+        Estimator {
+            dictionaries: Vec::new(),
+            leet: leet_table(),
+            reference_year: current_year(),
+            keyboard: keyboard_graph(),
+        }
+        .with_text("nouns", &Text::NOUNS)
+        .with_text("alice-in-wonderland", &Text::ALICE_IN_WONDERLAND)
+        .with_text("the-time-machine", &Text::THE_TIME_MACHINE)
+    }
+    /// Create a new `Estimator` with the words of `text` as an additional dictionary.
+    pub fn with_text(self, name: &str, text: &Text) -> Estimator {
+        self.with_dictionary(name, text.load_words())
+    }
+    /// Create a new `Estimator` with `words` as an additional dictionary, ranked
+    /// from most to least frequent, e.g. the user's name and email address.
+    pub fn with_words(self, name: &str, words: &[&str]) -> Estimator {
+        self.with_dictionary(name, words.iter().map(|w| w.to_lowercase()).collect())
+    }
+    /// Create a new `Estimator` that assumes dates are chosen near `year`, rather
+    /// than the current year.
+    pub fn with_reference_year(self, reference_year: i32) -> Estimator {
+        Estimator {
+            reference_year,
+            ..self
+        }
+    }
+
+    fn with_dictionary(mut self, name: &str, words: Vec<String>) -> Estimator {
+        self.dictionaries.push(Dictionary::new(name, words));
+        self
+    }
+
+    /// Estimate the strength of `password`.
+    pub fn estimate(&self, password: &str) -> Estimate {
+        let chars: Vec<char> = password.chars().collect();
+        let (chars, rest) = chars.split_at(std::cmp::min(chars.len(), MAX_LENGTH));
+        let mut estimate = self.most_guessable(chars);
+        if !rest.is_empty() {
+            estimate.guesses *= BRUTEFORCE_CARDINALITY.powi(rest.len() as i32);
+            estimate
+                .matches
+                .push(bruteforce(chars.len()..chars.len() + rest.len(), rest));
+        }
+        estimate
+    }
+
+    /// All matches of all patterns in `chars`, with their guesses.
+    fn matches(&self, chars: &[char]) -> Vec<Match> {
+        let mut matches = self.dictionary_matches(chars);
+        matches.extend(self.leet_matches(chars));
+        matches.extend(self.spatial_matches(chars));
+        matches.extend(self.repeat_matches(chars));
+        matches.extend(sequence_matches(chars));
+        matches.extend(self.date_matches(chars));
+        for m in matches.iter_mut() {
+            let min_guesses = if m.range.len() == chars.len() {
+                1.0
+            } else if m.range.len() == 1 {
+                MIN_SUBMATCH_GUESSES_SINGLE_CHAR
+            } else {
+                MIN_SUBMATCH_GUESSES_MULTI_CHAR
+            };
+            m.guesses = m.guesses.max(min_guesses);
+        }
+        matches
+    }
+
+    /// Find the sequence of matches, with brute force between them, that covers
+    /// `chars` and minimizes `l! * product(guesses) + D^(l - 1)`, where `l` is the
+    /// number of matches and `D` is `MIN_GUESSES_BEFORE_GROWING_SEQUENCE`.
+    fn most_guessable(&self, chars: &[char]) -> Estimate {
+        let n = chars.len();
+        if n == 0 {
+            return Estimate {
+                guesses: 1.0,
+                matches: Vec::new(),
+            };
+        }
+        let mut matches = self.matches(chars);
+        for start in 0..n {
+            for end in start + 1..=n {
+                matches.push(bruteforce(start..end, &chars[start..end]));
+            }
+        }
+
+        // best[end][l] is the smallest product of guesses of `l` matches that
+        // cover `chars[..end]`, with the last match and the previous end.
+        let mut best: Vec<HashMap<usize, (f64, usize, usize)>> = vec![HashMap::new(); n + 1];
+        best[0].insert(0, (1.0, usize::MAX, 0));
+        let mut by_end: Vec<Vec<usize>> = vec![Vec::new(); n + 1];
+        for (index, m) in matches.iter().enumerate() {
+            by_end[m.range.end].push(index);
+        }
+        for end in 1..=n {
+            for &index in by_end[end].iter() {
+                let m = &matches[index];
+                let previous: Vec<(usize, f64)> = best[m.range.start]
+                    .iter()
+                    .map(|(l, (product, _, _))| (*l, *product))
+                    .collect();
+                for (l, product) in previous {
+                    let product = product * m.guesses;
+                    let entry = best[end].entry(l + 1).or_insert((f64::INFINITY, 0, 0));
+                    if product < entry.0 {
+                        *entry = (product, index, m.range.start);
+                    }
+                }
+            }
+        }
+
+        let (mut l, guesses) = best[n]
+            .iter()
+            .map(|(l, (product, _, _))| (*l, sequence_guesses(*l, *product)))
+            .fold((0, f64::INFINITY), |(best_l, best_g), (l, g)| {
+                if g < best_g || (g == best_g && l < best_l) {
+                    (l, g)
+                } else {
+                    (best_l, best_g)
+                }
+            });
+        let mut sequence = Vec::new();
+        let mut end = n;
+        while l > 0 {
+            let (_, index, start) = best[end][&l];
+            sequence.push(matches[index].clone());
+            end = start;
+            l -= 1;
+        }
+        sequence.reverse();
+        Estimate {
+            guesses,
+            matches: sequence,
+        }
+    }
+
+    fn dictionary_matches(&self, chars: &[char]) -> Vec<Match> {
+        let lower: Vec<char> = chars.iter().map(|c| lowercase(*c)).collect();
+        let mut matches = Vec::new();
+        for dictionary in self.dictionaries.iter() {
+            for start in 0..lower.len() {
+                let max_end = std::cmp::min(lower.len(), start + dictionary.max_length);
+                for end in start + 1..=max_end {
+                    let word: String = lower[start..end].iter().collect();
+                    let reversed: String = lower[start..end].iter().rev().collect();
+                    let candidates = [(word.clone(), false), (reversed.clone(), true)];
+                    for (candidate, is_reversed) in candidates.iter() {
+                        if *is_reversed && reversed == word {
+                            continue;
+                        }
+                        if let Some(&rank) = dictionary.ranks.get(candidate) {
+                            let token = &chars[start..end];
+                            let mut guesses = rank as f64 * uppercase_variations(token);
+                            if *is_reversed {
+                                guesses *= 2.0;
+                            }
+                            matches.push(Match {
+                                pattern: Pattern::Dictionary {
+                                    dictionary: dictionary.name.clone(),
+                                    word: candidate.clone(),
+                                    rank,
+                                    reversed: *is_reversed,
+                                    substitutions: Vec::new(),
+                                },
+                                range: start..end,
+                                token: token.iter().collect(),
+                                guesses,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        matches
+    }
+
+    /// Dictionary matches of tokens with look-alike symbols replaced by letters.
+    fn leet_matches(&self, chars: &[char]) -> Vec<Match> {
+        let lower: Vec<char> = chars.iter().map(|c| lowercase(*c)).collect();
+        let max_length = self
+            .dictionaries
+            .iter()
+            .map(|d| d.max_length)
+            .max()
+            .unwrap_or(0);
+        let mut matches = Vec::new();
+        for start in 0..lower.len() {
+            for end in start + 1..=std::cmp::min(lower.len(), start + max_length) {
+                let token = &lower[start..end];
+                if !token
+                    .iter()
+                    .all(|c| c.is_alphabetic() || self.leet.contains_key(c))
+                    || !token.iter().any(|c| self.leet.contains_key(c))
+                {
+                    continue;
+                }
+                for substitutions in self.substitutions(token) {
+                    let word: String = token
+                        .iter()
+                        .map(|c| {
+                            substitutions
+                                .iter()
+                                .find(|(symbol, _)| symbol == c)
+                                .map_or(*c, |(_, letter)| *letter)
+                        })
+                        .collect();
+                    for dictionary in self.dictionaries.iter() {
+                        if let Some(&rank) = dictionary.ranks.get(&word) {
+                            let original = &chars[start..end];
+                            let guesses = rank as f64
+                                * uppercase_variations(original)
+                                * leet_variations(token, &substitutions);
+                            matches.push(Match {
+                                pattern: Pattern::Dictionary {
+                                    dictionary: dictionary.name.clone(),
+                                    word: word.clone(),
+                                    rank,
+                                    reversed: false,
+                                    substitutions: substitutions.clone(),
+                                },
+                                range: start..end,
+                                token: original.iter().collect(),
+                                guesses,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        matches
+    }
+
+    /// The ways of replacing each distinct look-alike symbol of `token` by a
+    /// letter, up to `MAX_LEET_SUBSTITUTIONS` of them.
+    fn substitutions(&self, token: &[char]) -> Vec<Vec<(char, char)>> {
+        let mut symbols: Vec<char> = token
+            .iter()
+            .filter(|c| self.leet.contains_key(c))
+            .cloned()
+            .collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+
+        let mut substitutions: Vec<Vec<(char, char)>> = vec![Vec::new()];
+        for symbol in symbols {
+            let mut next = Vec::new();
+            for partial in substitutions.iter() {
+                for letter in self.leet[&symbol].iter() {
+                    if next.len() == MAX_LEET_SUBSTITUTIONS {
+                        break;
+                    }
+                    let mut substitution = partial.clone();
+                    substitution.push((symbol, *letter));
+                    next.push(substitution);
+                }
+            }
+            substitutions = next;
+        }
+        substitutions
+    }
+
+    fn spatial_matches(&self, chars: &[char]) -> Vec<Match> {
+        let mut matches = Vec::new();
+        let mut start = 0;
+        while start < chars.len() {
+            let mut end = start + 1;
+            let mut turns = 0;
+            let mut last_direction = None;
+            while end < chars.len() {
+                let direction = self.keyboard.get(&chars[end - 1]).and_then(|neighbors| {
+                    neighbors
+                        .iter()
+                        .position(|n| *n == Some(unshifted(chars[end])))
+                });
+                match direction {
+                    Some(direction) => {
+                        if last_direction != Some(direction) {
+                            turns += 1;
+                            last_direction = Some(direction);
+                        }
+                        end += 1;
+                    }
+                    None => break,
+                }
+            }
+            if end - start >= 3 {
+                let token = &chars[start..end];
+                let shifted = token.iter().filter(|c| is_shifted(**c)).count();
+                matches.push(Match {
+                    pattern: Pattern::Spatial { turns, shifted },
+                    range: start..end,
+                    token: token.iter().collect(),
+                    guesses: self.spatial_guesses(token.len(), turns, shifted),
+                });
+            }
+            start = if end - start >= 3 { end } else { start + 1 };
+        }
+        matches
+    }
+
+    fn spatial_guesses(&self, length: usize, turns: usize, shifted: usize) -> f64 {
+        let starts = self.keyboard.len() as f64;
+        let degree = self
+            .keyboard
+            .values()
+            .map(|neighbors| neighbors.iter().filter(|n| n.is_some()).count())
+            .sum::<usize>() as f64
+            / starts;
+        let mut guesses = 0.0;
+        for i in 2..=length {
+            for j in 1..=std::cmp::min(turns, i - 1) {
+                guesses += binomial(i - 1, j - 1) * starts * degree.powi(j as i32);
+            }
+        }
+        guesses * variations(shifted, length - shifted)
+    }
+
+    fn repeat_matches(&self, chars: &[char]) -> Vec<Match> {
+        let mut matches = Vec::new();
+        let mut start = 0;
+        while start < chars.len() {
+            // the base length that repeats over the longest span from `start`
+            let mut best: Option<(usize, usize)> = None;
+            for base_length in 1..=(chars.len() - start) / 2 {
+                let base = &chars[start..start + base_length];
+                let count = chars[start..]
+                    .chunks(base_length)
+                    .take_while(|chunk| *chunk == base)
+                    .count();
+                if count >= 2
+                    && best.is_none_or(|(length, best_count)| {
+                        base_length * count > length * best_count
+                    })
+                {
+                    best = Some((base_length, count));
+                }
+            }
+            match best {
+                Some((base_length, count)) => {
+                    let end = start + base_length * count;
+                    let base = &chars[start..start + base_length];
+                    let base_guesses = self.most_guessable(base).guesses;
+                    matches.push(Match {
+                        pattern: Pattern::Repeat {
+                            base: base.iter().collect(),
+                            count,
+                        },
+                        range: start..end,
+                        token: chars[start..end].iter().collect(),
+                        guesses: base_guesses * count as f64,
+                    });
+                    start = end;
+                }
+                None => start += 1,
+            }
+        }
+        matches
+    }
+
+    fn date_matches(&self, chars: &[char]) -> Vec<Match> {
+        let mut matches = Vec::new();
+        for start in 0..chars.len() {
+            for end in start + 4..=std::cmp::min(chars.len(), start + 10) {
+                let token = &chars[start..end];
+                if let Some(pattern) = parse_date(token) {
+                    let year = match pattern {
+                        Pattern::Date { year, .. } => year,
+                        _ => unreachable!(),
+                    };
+                    let years = ((year - self.reference_year).abs() as f64).max(MIN_YEAR_SPACE);
+                    let guesses = match pattern {
+                        Pattern::Date {
+                            month: None,
+                            day: None,
+                            ..
+                        } => years,
+                        Pattern::Date {
+                            separator: Some(_), ..
+                        } => years * 365.0 * 4.0,
+                        _ => years * 365.0,
+                    };
+                    matches.push(Match {
+                        pattern,
+                        range: start..end,
+                        token: token.iter().collect(),
+                        guesses,
+                    });
+                }
+            }
+        }
+        matches
+    }
+}
+
+impl Default for Estimator {
+    fn default() -> Estimator {
+        Estimator::new()
+    }
+}
+
+/// Estimate the strength of `password` with the built-in dictionaries.
+pub fn estimate(password: &str) -> Estimate {
+    static ESTIMATOR: OnceLock<Estimator> = OnceLock::new();
+    ESTIMATOR.get_or_init(Estimator::new).estimate(password)
+}
+
+/// The year that `estimate_reproducibly` assumes dates are chosen near.
+pub const REPRODUCIBLE_REFERENCE_YEAR: i32 = 2026;
+
+/// Estimate the strength of `password` like `estimate`, but assuming that dates
+/// are chosen near `REPRODUCIBLE_REFERENCE_YEAR` rather than the current year, so
+/// that the estimate of a password does not change from one year to the next.
+/// Filters use it so that deterministic passwords can be regenerated.
+pub fn estimate_reproducibly(password: &str) -> Estimate {
+    static ESTIMATOR: OnceLock<Estimator> = OnceLock::new();
+    ESTIMATOR
+        .get_or_init(|| Estimator::new().with_reference_year(REPRODUCIBLE_REFERENCE_YEAR))
+        .estimate(password)
+}
+
+fn bruteforce(range: Range<usize>, token: &[char]) -> Match {
+    let min_guesses = if token.len() == 1 {
+        MIN_SUBMATCH_GUESSES_SINGLE_CHAR + 1.0
+    } else {
+        MIN_SUBMATCH_GUESSES_MULTI_CHAR + 1.0
+    };
+    Match {
+        pattern: Pattern::Bruteforce,
+        range,
+        token: token.iter().collect(),
+        guesses: BRUTEFORCE_CARDINALITY
+            .powi(token.len() as i32)
+            .max(min_guesses),
+    }
+}
+
+fn sequence_guesses(l: usize, product: f64) -> f64 {
+    let factorial: f64 = (1..=l).map(|i| i as f64).product();
+    factorial * product + MIN_GUESSES_BEFORE_GROWING_SEQUENCE.powi(l as i32 - 1)
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    if k > n {
+        return 0.0;
+    }
+    2f64.powf(log2_binomial(n, k)).round()
+}
+
+/// The number of ways of choosing which of `a + b` characters are the `a` that
+/// are changed, counting only the likely choices when all or none are changed.
+fn variations(a: usize, b: usize) -> f64 {
+    if a == 0 {
+        return 1.0;
+    }
+    if b == 0 {
+        return 2.0;
+    }
+    (1..=std::cmp::min(a, b)).map(|i| binomial(a + b, i)).sum()
+}
+
+fn uppercase_variations(token: &[char]) -> f64 {
+    let upper = token.iter().filter(|c| c.is_uppercase()).count();
+    let lower = token.iter().filter(|c| c.is_lowercase()).count();
+    if upper == 0 {
+        return 1.0;
+    }
+    let first_upper = token.first().is_some_and(|c| c.is_uppercase()) && upper == 1;
+    let last_upper = token.last().is_some_and(|c| c.is_uppercase()) && upper == 1;
+    if first_upper || last_upper || lower == 0 {
+        return 2.0;
+    }
+    variations(upper, lower)
+}
+
+fn leet_variations(token: &[char], substitutions: &[(char, char)]) -> f64 {
+    substitutions
+        .iter()
+        .map(|(symbol, letter)| {
+            let substituted = token.iter().filter(|c| *c == symbol).count();
+            let unsubstituted = token.iter().filter(|c| *c == letter).count();
+            if unsubstituted == 0 {
+                2.0
+            } else {
+                variations(substituted, unsubstituted)
+            }
+        })
+        .product()
+}
+
+fn sequence_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    let mut start = 0;
+    while start + 2 < chars.len() {
+        let delta = chars[start + 1] as i64 - chars[start] as i64;
+        let same_class = |a: char, b: char| {
+            (a.is_ascii_lowercase() && b.is_ascii_lowercase())
+                || (a.is_ascii_uppercase() && b.is_ascii_uppercase())
+                || (a.is_ascii_digit() && b.is_ascii_digit())
+        };
+        let mut end = start + 1;
+        while end < chars.len()
+            && chars[end] as i64 - chars[end - 1] as i64 == delta
+            && same_class(chars[start], chars[end])
+        {
+            end += 1;
+        }
+        if delta != 0 && delta.abs() <= MAX_SEQUENCE_DELTA && end - start >= 3 {
+            let first = chars[start];
+            let mut base = if "aAzZ019".contains(first) {
+                4.0
+            } else if first.is_ascii_digit() {
+                10.0
+            } else {
+                26.0
+            };
+            if delta < 0 {
+                base *= 2.0;
+            }
+            matches.push(Match {
+                pattern: Pattern::Sequence {
+                    ascending: delta > 0,
+                },
+                range: start..end,
+                token: chars[start..end].iter().collect(),
+                guesses: base * (end - start) as f64,
+            });
+            start = end - 1;
+        } else {
+            start += 1;
+        }
+    }
+    matches
+}
+
+/// Parse a date such as `1999`, `13-05-1999`, `1999.5.13` or `130599`.
+fn parse_date(token: &[char]) -> Option<Pattern> {
+    let separators: Vec<(usize, char)> = token
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| !c.is_ascii_digit())
+        .map(|(i, c)| (i, *c))
+        .collect();
+
+    let candidates: Vec<(Vec<u32>, Vec<usize>, Option<char>)> = match separators.as_slice() {
+        [] if token.len() == 4 => {
+            let year = number(token)?;
+            if (1900..=2099).contains(&year) {
+                return Some(Pattern::Date {
+                    year: year as i32,
+                    month: None,
+                    day: None,
+                    separator: None,
+                });
+            }
+            splits(token)
+        }
+        [] => splits(token),
+        [(i, a), (j, b)] if a == b && " /\\_.-".contains(*a) && *i > 0 && j > &(i + 1) => {
+            let parts = [&token[..*i], &token[i + 1..*j], &token[j + 1..]];
+            let numbers: Option<Vec<u32>> = parts.iter().map(|p| number(p)).collect();
+            let lengths = parts.iter().map(|p| p.len()).collect();
+            vec![(numbers?, lengths, Some(*a))]
+        }
+        _ => return None,
+    };
+
+    candidates
+        .into_iter()
+        .filter_map(|(numbers, lengths, separator)| {
+            let (year, month, day) = day_month_year(&numbers, &lengths)?;
+            Some(Pattern::Date {
+                year,
+                month: Some(month),
+                day: Some(day),
+                separator,
+            })
+        })
+        .next()
+}
+
+/// The ways of splitting 4 to 8 digits into a day, month and year.
+fn splits(token: &[char]) -> Vec<(Vec<u32>, Vec<usize>, Option<char>)> {
+    if !(4..=8).contains(&token.len()) {
+        return Vec::new();
+    }
+    let mut candidates = Vec::new();
+    for i in 1..token.len() - 1 {
+        for j in i + 1..token.len() {
+            let parts = [&token[..i], &token[i..j], &token[j..]];
+            if parts.iter().any(|p| p.len() > 4) {
+                continue;
+            }
+            if let Some(numbers) = parts.iter().map(|p| number(p)).collect() {
+                candidates.push((numbers, parts.iter().map(|p| p.len()).collect(), None));
+            }
+        }
+    }
+    candidates
+}
+
+fn number(digits: &[char]) -> Option<u32> {
+    if digits.is_empty() || digits.len() > 4 {
+        return None;
+    }
+    digits.iter().collect::<String>().parse().ok()
+}
+
+/// Interpret three numbers as a year, month and day, with the year first or last.
+fn day_month_year(numbers: &[u32], lengths: &[usize]) -> Option<(i32, u32, u32)> {
+    let year = |n: u32, length: usize| -> Option<i32> {
+        match length {
+            2 if n > 50 => Some(1900 + n as i32),
+            2 => Some(2000 + n as i32),
+            4 if (1000..=2050).contains(&n) => Some(n as i32),
+            _ => None,
+        }
+    };
+    let day_month = |a: u32, b: u32| -> Option<(u32, u32)> {
+        if (1..=31).contains(&a) && (1..=12).contains(&b) {
+            Some((a, b))
+        } else if (1..=12).contains(&a) && (1..=31).contains(&b) {
+            Some((b, a))
+        } else {
+            None
+        }
+    };
+    if lengths[2] != 1 && lengths[2] != 3 {
+        if let (Some(y), Some((d, m))) = (
+            year(numbers[2], lengths[2]),
+            day_month(numbers[0], numbers[1]),
+        ) {
+            if lengths[0] <= 2 && lengths[1] <= 2 {
+                return Some((y, m, d));
+            }
+        }
+    }
+    if lengths[0] != 1 && lengths[0] != 3 {
+        if let (Some(y), Some((d, m))) = (
+            year(numbers[0], lengths[0]),
+            day_month(numbers[2], numbers[1]),
+        ) {
+            if lengths[1] <= 2 && lengths[2] <= 2 {
+                return Some((y, m, d));
+            }
+        }
+    }
+    None
+}
+
+fn lowercase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Invert the table of `Defects::with_symbols`: each symbol maps to the
+/// lowercase letters that it may replace.
+fn leet_table() -> HashMap<char, Vec<char>> {
+    let mut table: HashMap<char, Vec<char>> = HashMap::new();
+    for (letter, symbols) in Defects::with_symbols(0, 0).replacements() {
+        for symbol in symbols {
+            let letters = table.entry(*symbol).or_default();
+            let letter = lowercase(*letter);
+            if !letters.contains(&letter) {
+                letters.push(letter);
+            }
+        }
+    }
+    table
+}
+
+/// The adjacent keys of each unshifted key of a QWERTY keyboard, in the
+/// directions left, right, up-left, up-right, down-left and down-right.
+fn keyboard_graph() -> HashMap<char, [Option<char>; 6]> {
+    let rows: Vec<Vec<char>> = QWERTY
+        .iter()
+        .map(|(row, _)| row.chars().collect())
+        .collect();
+    let key = |r: isize, c: isize| -> Option<char> {
+        if r < 0 || c < 0 {
+            return None;
+        }
+        rows.get(r as usize)
+            .and_then(|row| row.get(c as usize))
+            .filter(|k| **k != '\0')
+            .cloned()
+    };
+    let mut graph = HashMap::new();
+    for (r, row) in rows.iter().enumerate() {
+        for (c, k) in row.iter().enumerate() {
+            if *k == '\0' {
+                continue;
+            }
+            let (r, c) = (r as isize, c as isize);
+            graph.insert(
+                *k,
+                [
+                    key(r, c - 1),
+                    key(r, c + 1),
+                    key(r - 1, c),
+                    key(r - 1, c + 1),
+                    key(r + 1, c - 1),
+                    key(r + 1, c),
+                ],
+            );
+        }
+    }
+    graph
+}
+
+fn is_shifted(c: char) -> bool {
+    QWERTY
+        .iter()
+        .any(|(_, shifted)| c != '\0' && shifted.contains(c))
+}
+
+/// The character on the same key as `c` without the shift key.
+fn unshifted(c: char) -> char {
+    for (row, shifted) in QWERTY.iter() {
+        if let Some(i) = shifted.chars().position(|s| s == c) {
+            return row.chars().nth(i).unwrap_or(c);
+        }
+    }
+    c
+}
+
+fn current_year() -> i32 {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    1970 + (seconds as f64 / (365.2425 * 24.0 * 60.0 * 60.0)) as i32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn estimator() -> Estimator {
+        Estimator::new().with_reference_year(2020)
+    }
+
+    fn patterns(estimate: &Estimate) -> Vec<(&str, &Pattern)> {
+        estimate
+            .matches
+            .iter()
+            .map(|m| (m.token.as_str(), &m.pattern))
+            .collect()
+    }
+
+    #[test]
+    fn test_estimate_dictionary() {
+        let estimate = estimator().estimate("Machine");
+        assert_eq!(estimate.matches.len(), 1);
+        match &estimate.matches[0].pattern {
+            Pattern::Dictionary { word, reversed, .. } => {
+                assert_eq!(word, "machine");
+                assert!(!reversed);
+            }
+            pattern => panic!("expected a dictionary match, found {:?}", pattern),
+        }
+        assert!(estimate.guesses < 1e4);
+        assert!(estimate.score() <= 1);
+    }
+
+    #[test]
+    fn test_estimate_leet_and_reversed() {
+        let estimate = estimator().estimate("m@ch1ne");
+        match &estimate.matches[0].pattern {
+            Pattern::Dictionary {
+                word,
+                substitutions,
+                ..
+            } => {
+                assert_eq!(word, "machine");
+                assert_eq!(substitutions, &vec![('1', 'i'), ('@', 'a')]);
+            }
+            pattern => panic!("expected a dictionary match, found {:?}", pattern),
+        }
+        assert!(estimate.guesses < 1e5);
+
+        let estimate = estimator().estimate("enihcam");
+        assert!(matches!(
+            estimate.matches[0].pattern,
+            Pattern::Dictionary { reversed: true, .. }
+        ));
+    }
+
+    #[test]
+    fn test_estimate_spatial() {
+        let estimate = estimator().estimate("qwerty");
+        assert!(matches!(
+            patterns(&estimate)[..],
+            [(
+                _,
+                Pattern::Spatial {
+                    turns: 1,
+                    shifted: 0
+                }
+            )]
+        ));
+        let estimate = estimator().estimate("zxcvfr");
+        assert!(matches!(
+            patterns(&estimate)[..],
+            [(_, Pattern::Spatial { turns: 2, .. })]
+        ));
+    }
+
+    #[test]
+    fn test_estimate_repeat_and_sequence() {
+        let estimate = estimator().estimate("aaaaaaaaaa");
+        assert!(matches!(
+            patterns(&estimate)[..],
+            [("aaaaaaaaaa", Pattern::Repeat { count: 10, .. })]
+        ));
+        assert!(estimate.guesses < 1e3);
+
+        let estimate = estimator().estimate("13579");
+        assert_eq!(
+            patterns(&estimate),
+            vec![("13579", &Pattern::Sequence { ascending: true })]
+        );
+        let estimate = estimator().estimate("zyxw");
+        assert_eq!(
+            patterns(&estimate),
+            vec![("zyxw", &Pattern::Sequence { ascending: false })]
+        );
+    }
+
+    #[test]
+    fn test_estimate_dates() {
+        let date = |token: &str| parse_date(&token.chars().collect::<Vec<char>>());
+        assert_eq!(
+            date("13-05-1999"),
+            Some(Pattern::Date {
+                year: 1999,
+                month: Some(5),
+                day: Some(13),
+                separator: Some('-')
+            })
+        );
+        assert_eq!(
+            date("1999"),
+            Some(Pattern::Date {
+                year: 1999,
+                month: None,
+                day: None,
+                separator: None
+            })
+        );
+        assert!(matches!(
+            date("130599"),
+            Some(Pattern::Date {
+                year: 1999,
+                month: Some(5),
+                day: Some(13),
+                ..
+            })
+        ));
+        assert_eq!(date("13-05/1999"), None);
+        assert_eq!(date("99-99-99"), None);
+
+        let estimate = estimator().estimate("machine1999");
+        assert!(matches!(
+            patterns(&estimate)[..],
+            [
+                ("machine", Pattern::Dictionary { .. }),
+                ("1999", Pattern::Date { .. })
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_estimate_random_and_generated() {
+        let estimate = estimator().estimate("x7#Qk2!vP9");
+        assert!(estimate.guesses >= 1e9);
+
+        let weak = estimator().estimate("password1");
+        let generated = estimator().estimate("AndThatWa$Cemphor96");
+        assert!(generated.guesses > weak.guesses);
+        assert!(generated.guesses >= 1e10, "{:?}", generated);
+    }
+
+    #[test]
+    fn test_estimate_with_words() {
+        let estimator = estimator().with_words("user", &["Brendon"]);
+        let estimate = estimator.estimate("brendon");
+        assert!(matches!(
+            &estimate.matches[0].pattern,
+            Pattern::Dictionary { dictionary, rank: 1, .. } if dictionary == "user"
+        ));
+        // a single match of rank 1, plus the sequence penalty of a single match
+        assert_eq!(estimate.guesses, 2.0);
+    }
+
+    #[test]
+    fn test_crack_times() {
+        let estimate = Estimate {
+            guesses: 1e10,
+            matches: Vec::new(),
+        };
+        assert_eq!(
+            estimate.crack_time_display(AttackModel::OfflineFastHash),
+            "1 second"
+        );
+        assert_eq!(
+            estimate.crack_time_display(AttackModel::OnlineThrottled),
+            "centuries"
+        );
+        assert_eq!(display_time(0.5), "less than a second");
+        assert_eq!(display_time(7200.0), "2 hours");
+        assert_eq!(estimate.score(), 3);
+    }
+
+    #[test]
+    fn test_with_min_guesses() {
+        use crate::generators::base::{Constant, PasswordGenerator};
+        use crate::Error;
+
+        let passwords = Constant::new("password").with_min_guesses(1e6);
+        assert!(matches!(
+            passwords.try_generate(),
+            Err(Error::Rejected { .. })
+        ));
+        let passwords = Constant::new("x7#Qk2!vP9").with_min_guesses(1e6);
+        assert_eq!(passwords.try_generate(), Ok("x7#Qk2!vP9".to_string()));
+    }
+}
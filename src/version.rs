@@ -0,0 +1,184 @@
+//! Versions of the algorithms that turn random numbers into passwords.
+//!
+//! Given the same version, pipeline and random number generator, the same
+//! passwords are generated by every release, which matters when passwords are
+//! regenerated rather than stored, as with `deterministic`. When an algorithm
+//! must change, the change is made in a new version, and older versions remain
+//! available as a compatibility mode. The test vectors in `test-vectors/` pin
+//! the output of every version, and the keys derived with the default
+//! parameters of `Kdf::argon2id` and `Kdf::scrypt`.
+//!
+//! * `V1`: the first versioned algorithms. They keep the replacement table of
+//!   `Defects::with_symbols` from release 0.2.0, but otherwise differ from it:
+//!   `Case` does not use the `inflector` crate, so `Case::Class` does not
+//!   singularize the last word, and `Switch` chooses from a weighted distribution.
+//!   Release 0.2.0 could not be seeded, so its passwords cannot be reproduced.
+//! * `V2`: `Defects::with_symbols` may replace `L` with `1`, as well as with `7`
+//!   or `^`. In `V1` the second entry for `L` in the table replaced the first.
+//! * `V3`: the minimum length of the words of `RandomWords` is counted in
+//!   characters. In `V1` and `V2` it is counted in bytes, so that words with
+//!   non-ASCII letters, e.g. `façade`, are counted as longer than they are.
+
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// A version of the algorithms of the generators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "u32", into = "u32")]
+pub enum AlgorithmVersion {
+    V1 = 1,
+    V2 = 2,
+    V3 = 3,
+}
+
+impl AlgorithmVersion {
+    /// The version that generators use unless another is selected.
+    pub const LATEST: AlgorithmVersion = AlgorithmVersion::V3;
+    /// Every supported version, from oldest to newest.
+    pub const ALL: [AlgorithmVersion; 3] = [
+        AlgorithmVersion::V1,
+        AlgorithmVersion::V2,
+        AlgorithmVersion::V3,
+    ];
+
+    /// The number of this version, e.g. `1` for `V1`.
+    pub fn number(self) -> u32 {
+        self as u32
+    }
+}
+
+impl Default for AlgorithmVersion {
+    fn default() -> AlgorithmVersion {
+        AlgorithmVersion::LATEST
+    }
+}
+
+impl TryFrom<u32> for AlgorithmVersion {
+    type Error = Error;
+
+    fn try_from(number: u32) -> Result<AlgorithmVersion> {
+        AlgorithmVersion::ALL
+            .iter()
+            .find(|version| version.number() == number)
+            .copied()
+            .ok_or(Error::UnsupportedVersion(number))
+    }
+}
+
+impl From<AlgorithmVersion> for u32 {
+    fn from(version: AlgorithmVersion) -> u32 {
+        version.number()
+    }
+}
+
+impl std::fmt::Display for AlgorithmVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "v{}", self.number())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    use crate::deterministic::{Derivation, Kdf, Site};
+    use crate::pipeline;
+
+    #[derive(Deserialize)]
+    struct GenerationVector {
+        version: AlgorithmVersion,
+        pipeline: String,
+        seed: u64,
+        passwords: Vec<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct DerivationVector {
+        kdf: String,
+        master: String,
+        site: String,
+        login: String,
+        counter: u32,
+        key: String,
+    }
+
+    #[test]
+    fn test_version_numbers() {
+        assert_eq!(AlgorithmVersion::try_from(1), Ok(AlgorithmVersion::V1));
+        assert_eq!(AlgorithmVersion::try_from(2), Ok(AlgorithmVersion::V2));
+        assert_eq!(AlgorithmVersion::try_from(3), Ok(AlgorithmVersion::V3));
+        assert_eq!(
+            AlgorithmVersion::try_from(0),
+            Err(Error::UnsupportedVersion(0))
+        );
+        assert_eq!(
+            AlgorithmVersion::LATEST,
+            *AlgorithmVersion::ALL.last().unwrap()
+        );
+        assert_eq!(AlgorithmVersion::V2.to_string(), "v2");
+    }
+
+    #[test]
+    fn test_generation_vectors() {
+        let vectors: Vec<GenerationVector> =
+            serde_json::from_str(include_str!("../test-vectors/generation.json")).unwrap();
+        for version in AlgorithmVersion::ALL.iter() {
+            assert!(vectors.iter().any(|vector| vector.version == *version));
+        }
+        for vector in vectors {
+            let mut spec = pipeline::parse(&vector.pipeline).unwrap();
+            spec.version = Some(vector.version);
+            let generator = spec.build().unwrap();
+            let mut rng = ChaCha20Rng::seed_from_u64(vector.seed);
+            let passwords: Vec<String> = vector
+                .passwords
+                .iter()
+                .map(|_| generator.try_generate_with_rng(&mut rng).unwrap())
+                .collect();
+            assert_eq!(
+                passwords, vector.passwords,
+                "{} {}",
+                vector.version, vector.pipeline
+            );
+        }
+    }
+
+    #[test]
+    fn test_derivation_vectors() {
+        let vectors: Vec<DerivationVector> =
+            serde_json::from_str(include_str!("../test-vectors/derivation.json")).unwrap();
+        for vector in vectors {
+            let kdf = match vector.kdf.as_str() {
+                "argon2id" => Kdf::argon2id(),
+                "scrypt" => Kdf::scrypt(),
+                "argon2id-test" => Kdf::Argon2id {
+                    memory_kib: 64,
+                    iterations: 1,
+                    parallelism: 1,
+                },
+                "scrypt-test" => Kdf::Scrypt {
+                    log_n: 4,
+                    r: 8,
+                    p: 1,
+                },
+                kdf => panic!("unknown kdf '{}'", kdf),
+            };
+            let site = Site::new(&vector.site)
+                .with_login(&vector.login)
+                .with_counter(vector.counter);
+            let key: String = Derivation::new(kdf)
+                .key(&vector.master, &site)
+                .unwrap()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            assert_eq!(key, vector.key, "{} {}", vector.kdf, vector.site);
+        }
+    }
+}